@@ -10,6 +10,21 @@ pub fn decode_multiple_events(c: &mut Criterion) {
         group.bench_function("fsm iterator", |b| {
             b.iter(|| moss_decoder::decode_all_events(&f))
         });
+        group.bench_function("hit visitor", |b| {
+            b.iter(|| {
+                let mut hits_per_region = [0usize; 4];
+                moss_decoder::decode_with(&f, &mut |_, hit: moss_decoder::MossHit| {
+                    hits_per_region[hit.region as usize & 0x3] += 1
+                })
+                .map(|_| hits_per_region)
+            })
+        });
+        group.bench_function("skip 99000 take all", |b| {
+            b.iter(|| moss_decoder::skip_n_take_all(&f, 99000))
+        });
+        group.bench_function("scan event boundaries", |b| {
+            b.iter(|| moss_decoder::scan::event_boundaries(&f).count())
+        });
     }
     group.finish();
 }
@@ -0,0 +1,30 @@
+use criterion::Criterion;
+use moss_decoder::HitDecoder;
+
+const BENCH_FILE_PATHS: [&str; 3] = [
+    "tests/test-data/noise_all_regions.raw",
+    "tests/test-data/noise_random_region.raw",
+    "tests/test-data/pattern_all_regions.raw",
+];
+
+pub fn hit_decoder(c: &mut Criterion) {
+    let files: Vec<Vec<u8>> = BENCH_FILE_PATHS
+        .iter()
+        .map(|path| std::fs::read(std::path::PathBuf::from(path)).unwrap())
+        .collect();
+
+    let mut group = c.benchmark_group("hit_decoder_bench");
+    for (name, decoder) in [("sm fsm", HitDecoder::Fsm), ("table", HitDecoder::Table)] {
+        group.bench_function(name, |b| {
+            moss_decoder::set_hit_decoder(decoder);
+            b.iter(|| {
+                files
+                    .iter()
+                    .map(|f| moss_decoder::decode_all_events(f))
+                    .collect::<Vec<_>>()
+            })
+        });
+    }
+    group.finish();
+    moss_decoder::set_hit_decoder(HitDecoder::Fsm);
+}
@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((_, last_trailer_idx, _)) = moss_decoder::debug_decode_all_events(data) {
+        assert!(last_trailer_idx < data.len());
+    }
+    for correct in [false, true] {
+        if let Ok(report) = moss_decoder::diagnose_bit_flips(data, correct) {
+            assert!(report.diagnostics.iter().all(|d| d.index < data.len()));
+        }
+    }
+});
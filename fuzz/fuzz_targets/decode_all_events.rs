@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((_, last_trailer_idx)) = moss_decoder::decode_all_events(data) {
+        assert!(last_trailer_idx < data.len());
+    }
+});
@@ -0,0 +1,30 @@
+#![no_main]
+use libfuzzer_sys::{arbitrary, fuzz_target};
+use pyo3::Python;
+
+/// The interpreter needed by the parallel decoder is never finalized, its allocations are not leaks of the decoder.
+#[no_mangle]
+pub extern "C" fn __lsan_default_suppressions() -> *const std::ffi::c_char {
+    c"leak:libpython".as_ptr()
+}
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    threads: Option<usize>,
+    bytes: &'a [u8],
+}
+
+fuzz_target!(
+    init: pyo3::prepare_freethreaded_python(),
+    |input: Input| {
+        let res_parallel = Python::with_gil(|py| {
+            moss_decoder::decode_all_events_parallel(py, input.bytes, input.threads)
+        });
+
+        // The parallel decoder splits at event boundaries and must find the same packets
+        let res = moss_decoder::decode_all_events(input.bytes);
+        if let (Ok(packets), Ok(packets_parallel)) = (res, res_parallel) {
+            assert_eq!(packets, packets_parallel);
+        }
+    }
+);
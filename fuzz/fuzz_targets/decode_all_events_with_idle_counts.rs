@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let res = moss_decoder::decode_all_events_with_idle_counts(
+        data,
+        moss_decoder::DecodeOptions::default(),
+    );
+    if let Ok((packets, last_trailer_idx, _)) = res {
+        assert!(last_trailer_idx < data.len());
+        let (expect_packets, expect_last_trailer_idx) =
+            moss_decoder::decode_all_events(data).unwrap();
+        assert_eq!(last_trailer_idx, expect_last_trailer_idx);
+        assert!(packets.iter().map(|(p, _)| p).eq(expect_packets.iter()));
+    }
+});
@@ -0,0 +1,33 @@
+#![no_main]
+use libfuzzer_sys::{arbitrary, fuzz_target};
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    length: usize,
+    timestamp_offset: usize,
+    timestamp_len: usize,
+    trigger_id_offset: usize,
+    trigger_id_len: usize,
+    big_endian: bool,
+    bytes: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let Ok(prefix) = moss_decoder::FpgaPrefix::new(
+        input.length,
+        input.timestamp_offset,
+        input.timestamp_len,
+        input.trigger_id_offset,
+        input.trigger_id_len,
+        input.big_endian,
+    ) else {
+        return;
+    };
+    if let Ok((_, last_trailer_idx)) = moss_decoder::decode_all_events_with_prefix(
+        input.bytes,
+        &prefix,
+        moss_decoder::DecodeOptions::default(),
+    ) {
+        assert!(last_trailer_idx < input.bytes.len());
+    }
+});
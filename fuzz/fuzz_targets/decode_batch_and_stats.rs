@@ -0,0 +1,18 @@
+#![no_main]
+//! The decoders built on the same FSM as `decode_all_events` that don't return a list of packets.
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((batch, consumed)) = moss_decoder::PacketBatch::decode(data, None, None) {
+        assert!(consumed <= data.len());
+        assert_eq!(
+            batch.iter().map(|p| p.hits().len()).sum::<usize>(),
+            batch.hit_count()
+        );
+    }
+    if let Ok(consumed) = moss_decoder::decode_with(data, &mut |_, _| {}) {
+        assert!(consumed <= data.len());
+    }
+    _ = moss_decoder::validate_all_events(data);
+    _ = moss_decoder::run_stats(data);
+});
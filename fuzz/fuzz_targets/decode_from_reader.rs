@@ -0,0 +1,76 @@
+#![no_main]
+//! Decodes from a reader that returns the bytes in pieces of arbitrary sizes, like a pipe or a socket.
+use libfuzzer_sys::{arbitrary, fuzz_target};
+use moss_decoder::{source, DecodeOptions, ErrorPolicy, FpgaPrefix};
+use std::io::Read;
+
+#[derive(Debug, Clone, arbitrary::Arbitrary)]
+struct Input<'a> {
+    piece_sizes: Vec<u8>,
+    take: usize,
+    skip: usize,
+    prefix_len: usize,
+    bytes: &'a [u8],
+}
+
+impl Read for Input<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let piece_size = match self.piece_sizes.pop() {
+            Some(size) => usize::from(size),
+            None => self.bytes.len(),
+        };
+        let n = piece_size.min(self.bytes.len()).min(buf.len());
+        buf[..n].copy_from_slice(&self.bytes[..n]);
+        self.bytes = &self.bytes[n..];
+        Ok(n)
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let all = source::decode_all(input.clone(), DecodeOptions::default());
+
+    // The pieces must not change the result
+    let whole = Input {
+        piece_sizes: Vec::new(),
+        ..input.clone()
+    };
+    if let (Ok(packets), Ok(packets_whole)) = (
+        &all,
+        source::decode_all(whole.clone(), DecodeOptions::default()),
+    ) {
+        assert_eq!(packets, &packets_whole);
+    }
+    let skip_bad_events = DecodeOptions {
+        policy: ErrorPolicy::SkipBadEvents,
+        ..Default::default()
+    };
+    let skipped = source::decode_all(input.clone(), skip_bad_events);
+    let skipped_whole = source::decode_all(whole.clone(), skip_bad_events);
+    assert_eq!(skipped.ok(), skipped_whole.ok());
+
+    let skip = (input.skip > 0).then_some(input.skip);
+    _ = source::decode_n(
+        input.clone(),
+        input.take,
+        skip,
+        None,
+        DecodeOptions::default(),
+    );
+    _ = source::skip_n_take_all(input.clone(), input.skip, DecodeOptions::default());
+
+    // Skipping an FPGA prefix before each event must not depend on the pieces either
+    if let Ok(prefix) = FpgaPrefix::new(input.prefix_len, 0, 0, 0, 0, false) {
+        for policy in [ErrorPolicy::Strict, ErrorPolicy::SkipBadEvents] {
+            let prefixed = DecodeOptions {
+                policy,
+                prefix: Some(&prefix),
+                ..Default::default()
+            };
+            let packets = source::decode_all(input.clone(), prefixed);
+            let packets_whole = source::decode_all(whole.clone(), prefixed);
+            assert_eq!(packets.ok(), packets_whole.ok());
+            _ = source::decode_n(input.clone(), input.take, skip, None, prefixed);
+            _ = source::skip_n_take_all(input.clone(), input.skip, prefixed);
+        }
+    }
+});
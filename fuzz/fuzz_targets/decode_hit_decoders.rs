@@ -0,0 +1,20 @@
+#![no_main]
+//! Decodes with both hit decoders, which must produce the same hits and errors.
+use libfuzzer_sys::fuzz_target;
+use moss_decoder::{set_hit_decoder, HitDecoder, MossHit, PacketBatch};
+
+fuzz_target!(|data: &[u8]| {
+    let decode = |decoder| {
+        set_hit_decoder(decoder);
+        let packets = moss_decoder::decode_all_events(data).ok();
+        let batch = PacketBatch::decode(data, None, None).map_err(|e| e.to_string());
+        let mut hits = Vec::new();
+        let visited =
+            moss_decoder::decode_with(data, &mut |unit_id, hit: MossHit| hits.push((unit_id, hit)))
+                .map_err(|e| e.to_string());
+        (packets, batch, visited, hits)
+    };
+    let fsm = decode(HitDecoder::Fsm);
+    let table = decode(HitDecoder::Table);
+    assert_eq!(fsm, table);
+});
@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::{arbitrary, fuzz_target};
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+    bytes: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let res =
+        moss_decoder::decode_n_events(input.bytes, input.take, input.skip, input.prepend_buffer);
+    if let Ok((packets, _)) = res {
+        assert!(packets.len() <= input.take);
+    }
+});
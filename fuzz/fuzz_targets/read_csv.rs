@@ -0,0 +1,27 @@
+#![no_main]
+//! Reads delimited text written by `write_csv`, malformed text must return an error.
+use libfuzzer_sys::fuzz_target;
+use moss_decoder::csv_io::{CsvReader, CsvWriter};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(reader) = CsvReader::new(data) else {
+        return;
+    };
+    let Ok(records) = reader.collect::<std::io::Result<Vec<_>>>() else {
+        return;
+    };
+
+    // Writing the records back must give the same records
+    let mut writer = CsvWriter::new(Vec::new(), ',', true).unwrap();
+    for record in &records {
+        writer
+            .write_packet(record.event, record.offset, &record.packet)
+            .unwrap();
+    }
+    let text = writer.finish().unwrap();
+    let records_again = CsvReader::new(text.as_slice())
+        .unwrap()
+        .collect::<std::io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(records, records_again);
+});
@@ -0,0 +1,17 @@
+#![no_main]
+//! Reads a sidecar `.idx` file written by `index_file`, a corrupt index must return an error.
+use libfuzzer_sys::fuzz_target;
+use moss_decoder::EventIndex;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(index) = EventIndex::read_from(&mut &data[..]) else {
+        return;
+    };
+    assert_eq!(index.unit_ids().len(), index.len());
+    assert_eq!(index.hit_counts().len(), index.len());
+
+    let mut bytes = Vec::new();
+    index.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes, data[..bytes.len()]);
+    assert_eq!(EventIndex::read_from(&mut bytes.as_slice()).unwrap(), index);
+});
@@ -0,0 +1,26 @@
+#![no_main]
+//! Reads a packet file written by `save_packets`, a corrupt file must return an error.
+use libfuzzer_sys::fuzz_target;
+use moss_decoder::packet_file::{PacketReader, PacketWriter};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(reader) = PacketReader::new(data) else {
+        return;
+    };
+    let config = reader.config();
+    let Ok(packets) = reader.collect::<std::io::Result<Vec<_>>>() else {
+        return;
+    };
+
+    // The packets that were read fit the format, so writing them back must give the same packets
+    let mut writer = PacketWriter::with_config(Vec::new(), config).unwrap();
+    for packet in &packets {
+        writer.write_packet(packet).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    let packets_again = PacketReader::new(bytes.as_slice())
+        .unwrap()
+        .collect::<std::io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(packets, packets_again);
+});
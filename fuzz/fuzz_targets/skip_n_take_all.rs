@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::{arbitrary, fuzz_target};
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    skip: usize,
+    bytes: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    if let Ok((_, Some(remainder))) = moss_decoder::skip_n_take_all(input.bytes, input.skip) {
+        assert!(input.bytes.ends_with(&remainder));
+    }
+});
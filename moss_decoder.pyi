@@ -1,7 +1,8 @@
 """Performant decoding of MOSS readout data implemented in Rust"""
 
+from enum import Enum
 from pathlib import Path
-from typing import Optional
+from typing import BinaryIO, Iterator, Optional
 
 class MossHit:
     """A MOSS hit instance"""
@@ -15,6 +16,56 @@ class MossHit:
         self.column = column
         self.row = row
 
+    def __hash__(self) -> int: ...
+    def __lt__(self, other: MossHit) -> bool: ...
+    def __reduce__(self) -> tuple: ...
+    def to_tuple(self) -> tuple[int, int, int]: ...
+    def to_dict(self) -> dict[str, int]: ...
+    # Requires the `serde` feature
+    def to_json(self) -> str: ...
+    @staticmethod
+    def from_json(json: str) -> MossHit: ...
+
+class DmuMode(Enum):
+    """The DMU readout mode indicated by the idle word pattern"""
+
+    NoBackbone = ...
+    FourBit = ...
+    TwoBit = ...
+    OneBit = ...
+
+class HitDecoder(Enum):
+    """The implementation used to decode the hits of an event"""
+
+    Fsm = ...
+    Table = ...
+
+class ErrorPolicy(Enum):
+    """How the file decoding functions handle decoding and I/O errors"""
+
+    Strict = ...
+    SkipBadEvents = ...
+    StopAndReturnPartial = ...
+
+class IdleCounts:
+    """The number of idle words of each type in a MOSS event"""
+
+    no_backbone: int
+    four_bit: int
+    two_bit: int
+    one_bit: int
+
+    def total(self) -> int: ...
+    def dmu_mode(self) -> Optional[DmuMode]: ...
+    def is_mixed(self) -> bool: ...
+
+class DmuModeChange:
+    """A change of DMU mode between two packets, or a packet with mixed idle words"""
+
+    packet_idx: int
+    previous: Optional[DmuMode]
+    new: Optional[DmuMode]
+
 class MossPacket:
     """A decoded MOSS event packet with a `Unit ID` and a list of `MossHit`s"""
 
@@ -25,28 +76,371 @@ class MossPacket:
         self.unit_id = unit_id
         self.hits = []
 
+    def __hash__(self) -> int: ...
+    def __lt__(self, other: MossPacket) -> bool: ...
+    def __len__(self) -> int: ...
+    def __getitem__(self, index: int) -> MossHit: ...
+    def __iter__(self) -> Iterator[MossHit]: ...
+    def __reduce__(self) -> tuple: ...
+    def __getstate__(self) -> tuple: ...
+    def __setstate__(self, state: tuple) -> None: ...
+    def to_tuple(self) -> tuple[int, tuple[tuple[int, int, int], ...]]: ...
+    def to_dict(self) -> dict: ...
+    # Requires the `serde` feature
+    def to_json(self) -> str: ...
+    @staticmethod
+    def from_json(json: str) -> MossPacket: ...
+
+class FpgaPrefix:
+    """Layout of the FPGA prefix preceding each event, with an optional timestamp and trigger ID"""
+
+    length: int
+    timestamp_offset: int
+    timestamp_len: int
+    trigger_id_offset: int
+    trigger_id_len: int
+    big_endian: bool
+
+    def __init__(
+        self,
+        length: int,
+        timestamp_offset: int = 0,
+        timestamp_len: int = 0,
+        trigger_id_offset: int = 0,
+        trigger_id_len: int = 0,
+        big_endian: bool = False,
+    ) -> FpgaPrefix: ...
+
+class BatchArray:
+    """A read-only array of a `PacketBatch`, supports the buffer protocol e.g. `numpy.asarray` and `memoryview`"""
+
+    def __len__(self) -> int: ...
+    def __buffer__(self, flags: int) -> memoryview: ...
+
+class PacketBatch:
+    """Decoded packets stored as contiguous arrays.
+
+    The hits of packet `i` are at indices `packet_offsets[i]:packet_offsets[i + 1]` of `regions`, `rows` and `columns`
+    """
+
+    hit_count: int
+    packet_offsets: BatchArray  # uint64, one more than the number of packets
+    unit_ids: BatchArray  # uint8, per packet
+    regions: BatchArray  # uint8, per hit
+    rows: BatchArray  # uint16, per hit
+    columns: BatchArray  # uint16, per hit
+
+    def to_packets(self) -> list[MossPacket]: ...
+    def __len__(self) -> int: ...
+    def __getitem__(self, index: int) -> MossPacket: ...
+    def __iter__(self) -> Iterator[MossPacket]: ...
+
+class EventIndex:
+    """The byte offset, length, unit ID and hit count of every event in a raw data file"""
+
+    offsets: list[int]
+    unit_ids: list[int]
+    hit_counts: list[int]
+
+    @staticmethod
+    def build(path: str | Path) -> EventIndex: ...
+    @staticmethod
+    def load(path: str | Path) -> EventIndex: ...
+    @staticmethod
+    def sidecar_path(path: str | Path) -> Path: ...
+    def save(self, path: str | Path) -> None: ...
+    def __len__(self) -> int: ...
+
+class DecodeFilter:
+    """Selects which packets and hits the decode functions return"""
+
+    unit_ids: list[int]
+    regions: list[int]
+    min_hits: Optional[int]
+    max_hits: Optional[int]
+    drop_empty: bool
+
+    def __init__(
+        self,
+        unit_ids: Optional[list[int]] = None,
+        regions: Optional[list[int]] = None,
+        min_hits: Optional[int] = None,
+        max_hits: Optional[int] = None,
+        drop_empty: bool = False,
+    ) -> DecodeFilter: ...
+
+class PacketFileWriter:
+    """Writes `MossPacket`s to a compact packet file, use as a context manager or call `close` when done"""
+
+    def __init__(self, path: str | Path) -> PacketFileWriter: ...
+    def write(self, packets: list[MossPacket]) -> None: ...
+    def close(self) -> None: ...
+    def __enter__(self) -> PacketFileWriter: ...
+    def __exit__(self, exc_type, exc_value, traceback) -> None: ...
+
+class PacketFileReader:
+    """Iterates over the `MossPacket`s in a compact packet file"""
+
+    def __init__(self, path: str | Path) -> PacketFileReader: ...
+    def __iter__(self) -> PacketFileReader: ...
+    def __next__(self) -> MossPacket: ...
+
+class PacketStream:
+    """An iterator over the `MossPacket`s received from a network socket"""
+
+    def __iter__(self) -> PacketStream: ...
+    def __next__(self) -> MossPacket: ...
+
+class PixelMask:
+    """A set of masked pixels identified by `(unit_id, region, row, column)`"""
+
+    def __init__(self) -> PixelMask: ...
+    def add(self, unit_id: int, region: int, row: int, column: int) -> None: ...
+    def remove(self, unit_id: int, region: int, row: int, column: int) -> bool: ...
+    def contains(self, unit_id: int, region: int, row: int, column: int) -> bool: ...
+    def pixels(self) -> list[tuple[int, int, int, int]]: ...
+    @staticmethod
+    def load(path: str | Path) -> PixelMask: ...
+    def save(self, path: str | Path) -> None: ...
+    @staticmethod
+    def from_noise_run(packets: list[MossPacket], threshold: float) -> PixelMask: ...
+    def apply(self, packets: list[MossPacket]) -> list[MossPacket]: ...
+    def __len__(self) -> int: ...
+
+class HitDiagnosticKind(Enum):
+    """The type of issue found by the hit validation"""
+
+    DuplicatePixel = ...
+    RegionHeaderOutOfOrder = ...
+    HitOutOfOrder = ...
+
+class HitDiagnostic:
+    """An issue found by the hit validation"""
+
+    kind: HitDiagnosticKind
+    packet_idx: int
+    index: int
+    unit_id: int
+    region: int
+    hit: Optional[MossHit]
+
+class BitFlipDiagnostic:
+    """A protocol error diagnosed as a possible single bit flip"""
+
+    packet_idx: int
+    index: int
+    unit_id: int
+    byte: int
+    expected: str
+    candidates: list[int]
+    likely_word: Optional[int]
+    corrected: bool
+    flipped_bit: Optional[int]
+
+class BitFlipReport:
+    """The result of decoding with single bit flip diagnosis"""
+
+    packets: list[MossPacket]
+    diagnostics: list[BitFlipDiagnostic]
+    corrected: int
+    uncorrectable: int
+
+class RunStats:
+    """Summary statistics of a run of raw MOSS data"""
+
+    total_bytes: int
+    packets: int
+    empty_packets: int
+    hits: int
+    hits_per_unit_region: dict[int, list[int]]
+    idle_no_backbone: int
+    idle_four_bit: int
+    idle_two_bit: int
+    idle_one_bit: int
+    delimiter_bytes: int
+    bytes_between_events: int
+    largest_event_size: Optional[int]
+    smallest_event_size: Optional[int]
+    mixed_idle_packets: int
+    dmu_mode_changes: int
+    error_counts: dict[str, int]
+
+    def to_dict(self) -> dict: ...
+
 def decode_event(bytes: bytes) -> tuple[MossPacket, int]: ...
-def decode_all_events(bytes: bytes) -> tuple[list[MossPacket], int]: ...
-def decode_from_file(path: str | Path) -> list[MossPacket]: ...
+def decode_all_events(
+    bytes: bytes,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> tuple[list[MossPacket], int]: ...
+def decode_all_events_parallel(
+    bytes: bytes,
+    mask: Optional[PixelMask] = None,
+    threads: Optional[int] = None,
+    filter: Optional[DecodeFilter] = None,
+) -> tuple[list[MossPacket], int]: ...
+def decode_all_events_with_idle_counts(
+    bytes: bytes,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+) -> tuple[list[tuple[MossPacket, IdleCounts]], int, list[DmuModeChange]]: ...
+def decode_all_events_with_prefix(
+    bytes: bytes,
+    prefix: FpgaPrefix,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+) -> tuple[list[tuple[MossPacket, Optional[int], Optional[int]]], int]: ...
+def set_hit_decoder(decoder: HitDecoder) -> None: ...
+def get_hit_decoder() -> HitDecoder: ...
+def set_log_level(level: str | int) -> None: ...
+def get_log_level() -> str: ...
+def decode_tcp_stream(
+    address: str,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    timeout: Optional[float] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> PacketStream: ...
+def decode_udp_stream(
+    address: str,
+    header_len: int,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    timeout: Optional[float] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> PacketStream: ...
+def decode_from_file(
+    path: str | Path,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> list[MossPacket]: ...
+def decode_from_reader(
+    reader: BinaryIO,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> list[MossPacket]: ...
+def decode_n_events_from_reader(
+    reader: BinaryIO,
+    take: int,
+    skip: Optional[int] = None,
+    prepend_buffer: Optional[bytes] = None,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> list[MossPacket]: ...
+def skip_n_take_all_from_reader(
+    reader: BinaryIO,
+    skip: int = None,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> tuple[Optional[list[MossPacket]], Optional[bytes]]: ...
+def decode_all_events_batch(
+    bytes: bytes,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> tuple[PacketBatch, int]: ...
+def decode_from_file_batch(
+    path: str | Path,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> PacketBatch: ...
 def decode_n_events(
     path: str | Path,
     take: int,
     skip: Optional[int] = None,
     prepend_buffer: Optional[bytes] = None,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    prefix: Optional[FpgaPrefix] = None,
 ) -> tuple[list[MossPacket], int]: ...
 def decode_n_events_from_file(
-    bytes: bytes,
+    path: str | Path,
     take: int,
     skip: Optional[int] = None,
     prepend_buffer: Optional[bytes] = None,
-) -> tuple[list[MossPacket], int]: ...
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+    prefix: Optional[FpgaPrefix] = None,
+) -> list[MossPacket]: ...
+def index_file(path: str | Path) -> EventIndex: ...
+def read_events(
+    path: str | Path,
+    indices: list[int],
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+) -> list[MossPacket]: ...
 def skip_n_take_all(
-    bytes: bytes, skip: int = None
+    bytes: bytes,
+    skip: int = None,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    prefix: Optional[FpgaPrefix] = None,
 ) -> tuple[list[MossPacket], Optional[bytes]]: ...
 def skip_n_take_all_from_file(
-    path: str | Path, skip: int = None
+    path: str | Path,
+    skip: int = None,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+    prefix: Optional[FpgaPrefix] = None,
 ) -> tuple[list[MossPacket], Optional[bytes]]: ...
 def debug_decode_all_events(b: bytes) -> tuple[list[MossPacket], int, list[str]]: ...
+# The debug, validation, bit flip and statistics functions run over decoding errors and report them,
+# so they take no `error_policy`
 def debug_decode_all_events_from_file(
     path: str | Path,
 ) -> tuple[list[MossPacket], int, list[str]]: ...
+def validate_all_events(bytes: bytes) -> list[HitDiagnostic]: ...
+def validate_all_events_from_file(path: str | Path) -> list[HitDiagnostic]: ...
+def diagnose_bit_flips(bytes: bytes, correct: bool = False) -> BitFlipReport: ...
+def diagnose_bit_flips_from_file(
+    path: str | Path, correct: bool = False
+) -> BitFlipReport: ...
+def run_stats(bytes: bytes) -> RunStats: ...
+def run_stats_from_file(path: str | Path) -> RunStats: ...
+def run_stats_from_reader(reader: BinaryIO) -> RunStats: ...
+def save_packets(path: str | Path, packets: list[MossPacket]) -> None: ...
+def load_packets(path: str | Path) -> list[MossPacket]: ...
+
+def write_csv(
+    path: str | Path,
+    packets: list[MossPacket],
+    delimiter: str = ",",
+    include_empty: bool = False,
+) -> None: ...
+def decode_file_to_csv(
+    raw_path: str | Path,
+    csv_path: str | Path,
+    delimiter: str = ",",
+    include_empty: bool = False,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+) -> int: ...
+def read_csv(path: str | Path) -> list[MossPacket]: ...
+
+# Requires the experimental `hdf5` feature
+def decode_file_to_hdf5(
+    raw_path: str | Path,
+    h5_path: str | Path,
+    mask: Optional[PixelMask] = None,
+    filter: Optional[DecodeFilter] = None,
+    error_policy: Optional[ErrorPolicy] = None,
+) -> int: ...
+
+# Requires the `serde` feature
+def packets_to_json(packets: list[MossPacket]) -> str: ...
+def packets_from_json(json: str) -> list[MossPacket]: ...
+def main() -> None: ...
@@ -0,0 +1,189 @@
+//! Async decoding of MOSS data from any [AsyncRead] as a [Stream] of [MossPacket]s, enabled by the `async` feature.
+//!
+//! Uses the same partial-event buffering as the other chunked decoders, so events split across reads are completed by the following reads.
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::source::PacketBuffer;
+use crate::{DecodeError, DecodeFilter, FpgaPrefix, MossPacket, PixelMask};
+
+const READ_BUFFER_SIZE: usize = 64 * 1024; // 64 KiB
+
+/// A [Stream] of the [MossPacket]s decoded from an [AsyncRead], created by [decode_stream].
+///
+/// The stream ends when the reader reaches EOF, an incomplete event at the end is dropped.
+/// After an error is yielded the stream ends.
+#[derive(Debug)]
+pub struct DecodeStream<R> {
+    reader: R,
+    mask: Option<PixelMask>,
+    filter: Option<DecodeFilter>,
+    read_buf: Box<[u8]>,
+    packet_buffer: PacketBuffer,
+    finished: bool,
+}
+
+/// Decodes the MOSS data read from `reader` as a [Stream] of [MossPacket]s.
+///
+/// # Example
+/// ```ignore
+/// use tokio_stream::StreamExt;
+///
+/// let socket = tokio::net::TcpStream::connect("192.168.1.10:5000").await?;
+/// let mut packets = moss_decoder::async_decode::decode_stream(socket);
+/// while let Some(packet) = packets.next().await {
+///     println!("{}", packet?);
+/// }
+/// ```
+pub fn decode_stream<R: AsyncRead + Unpin>(reader: R) -> DecodeStream<R> {
+    DecodeStream {
+        reader,
+        mask: None,
+        filter: None,
+        read_buf: vec![0; READ_BUFFER_SIZE].into_boxed_slice(),
+        packet_buffer: PacketBuffer::default(),
+        finished: false,
+    }
+}
+
+impl<R> DecodeStream<R> {
+    /// Drops hits on pixels in the given [PixelMask] from the decoded packets.
+    pub fn with_mask(mut self, mask: PixelMask) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Drops the packets and hits that are not selected by the given [DecodeFilter] from the decoded packets.
+    pub fn with_filter(mut self, filter: DecodeFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Skips the FPGA prefix with the given [FpgaPrefix] layout preceding each event, must be set before the stream is polled.
+    pub fn with_prefix(mut self, prefix: FpgaPrefix) -> Self {
+        self.packet_buffer = PacketBuffer::new(Some(&prefix));
+        self
+    }
+
+    /// Returns the underlying reader, any buffered bytes of an incomplete event are discarded.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for DecodeStream<R> {
+    type Item = Result<MossPacket, DecodeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(next_packet) = this.packet_buffer.next_packet() {
+                this.finished |= next_packet.is_err();
+                return Poll::Ready(Some(next_packet));
+            }
+            if this.finished {
+                return Poll::Ready(None);
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.read_buf);
+            match Pin::new(&mut this.reader).poll_read(cx, &mut read_buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    this.finished = true;
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                Poll::Ready(Ok(())) if read_buf.filled().is_empty() => this.finished = true,
+                Poll::Ready(Ok(())) => this.packet_buffer.extend(
+                    read_buf.filled(),
+                    this.mask.as_ref(),
+                    this.filter.as_ref(),
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::{fake_event_protocol_error, fake_event_simple};
+    use crate::ParseErrorKind;
+    use pretty_assertions::assert_eq;
+    use tokio::io::AsyncWriteExt;
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn test_decode_stream_split_events() {
+        // A small pipe buffer splits the events across many reads
+        let (mut writer, reader) = tokio::io::duplex(4);
+        let writer_task = tokio::spawn(async move {
+            for _ in 0..3 {
+                writer.write_all(&fake_event_simple()).await.unwrap();
+            }
+        });
+
+        let mut stream = decode_stream(reader);
+        let mut packets = Vec::new();
+        while let Some(packet) = next(&mut stream).await {
+            packets.push(packet.unwrap());
+        }
+        writer_task.await.unwrap();
+
+        assert_eq!(packets.len(), 3);
+        assert!(packets.iter().all(|p| p.unit_id == 1 && p.hits.len() == 4));
+    }
+
+    #[tokio::test]
+    async fn test_decode_stream_error_ends_stream() {
+        let bytes = [fake_event_simple(), fake_event_protocol_error()].concat();
+        let mut stream = decode_stream(bytes.as_slice());
+
+        assert!(next(&mut stream).await.unwrap().is_ok());
+        match next(&mut stream).await {
+            Some(Err(DecodeError::Parse {
+                packet_num, kind, ..
+            })) => {
+                assert_eq!(packet_num, 2);
+                assert_eq!(kind, ParseErrorKind::ProtocolError);
+            }
+            other => panic!("Expected a protocol error, got {other:?}"),
+        }
+        assert!(next(&mut stream).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_decode_stream_filter() {
+        let mut other_unit = fake_event_simple();
+        other_unit[0] += 1;
+        let bytes = [fake_event_simple(), other_unit, fake_event_protocol_error()].concat();
+        let filter = DecodeFilter::new(Some(vec![1]), None, None, None, false).unwrap();
+        let mut stream = decode_stream(bytes.as_slice()).with_filter(filter);
+
+        assert_eq!(next(&mut stream).await.unwrap().unwrap().unit_id, 1);
+        // The packet number counts the packet dropped by the filter
+        match next(&mut stream).await {
+            Some(Err(DecodeError::Parse { packet_num, .. })) => assert_eq!(packet_num, 3),
+            other => panic!("Expected a protocol error, got {other:?}"),
+        }
+        assert!(next(&mut stream).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_decode_stream_prefix() {
+        let prefix = FpgaPrefix::new(2, 0, 0, 0, 0, false).unwrap();
+        // The prefix bytes are a trailer and a header
+        let prefixed_event = [[0xE0, 0xD1].as_slice(), &fake_event_simple()].concat();
+        let bytes = prefixed_event.repeat(2);
+        let mut stream = decode_stream(bytes.as_slice()).with_prefix(prefix);
+
+        for _ in 0..2 {
+            assert_eq!(next(&mut stream).await.unwrap().unwrap().hits.len(), 4);
+        }
+        assert!(next(&mut stream).await.is_none());
+    }
+}
@@ -0,0 +1,290 @@
+//! Bit-error tolerant decoding, for data from radiation tests where single bits of the readout are corrupted.
+//!
+//! When a word is not legal in the current state of the hit decoder, the words that differ from it in a single bit and would have been legal are looked up.
+//! If there is exactly one such word it is reported as the likely intended word, and can optionally be decoded in place of the corrupted word.
+//! A bit flip that turns a word into another word that is legal in the same state, e.g. a data word into another data word, cannot be detected.
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::fmt::{write, Display};
+
+use crate::{decode_hits_table, decompress, scan, MossPacket, MINIMUM_EVENT_SIZE};
+
+#[pyclass(get_all)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A protocol error diagnosed as a possible single bit flip.
+pub struct BitFlipDiagnostic {
+    /// The index of the event in the byte stream, starting from 0.
+    pub packet_idx: usize,
+    /// The index of the offending byte.
+    pub index: usize,
+    /// The unit ID of the event.
+    pub unit_id: u8,
+    /// The offending byte.
+    pub byte: u8,
+    /// The words the decoder expected in its current state.
+    pub expected: String,
+    /// The bytes one bit flip away from the offending byte that are legal in the current state, in order of the flipped bit.
+    pub candidates: Vec<u8>,
+    /// The only candidate, `None` if there are no or several candidates.
+    pub likely_word: Option<u8>,
+    /// Whether the likely word was decoded in place of the offending byte.
+    pub corrected: bool,
+}
+
+#[pymethods]
+impl BitFlipDiagnostic {
+    /// The bit position (0 is the least significant bit) that differs between the offending byte and the likely word.
+    #[getter]
+    pub fn flipped_bit(&self) -> Option<u32> {
+        self.likely_word
+            .map(|word| (word ^ self.byte).trailing_zeros())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+
+    /// Returns a string representation of the [BitFlipDiagnostic] instance.
+    pub fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for BitFlipDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "Invalid word=0x{byte:02X} at index={i} in packet #{packet} unit {unit_id}: {expected}",
+                byte = self.byte,
+                i = self.index,
+                packet = self.packet_idx + 1,
+                unit_id = self.unit_id,
+                expected = self.expected,
+            ),
+        )?;
+        match (self.likely_word, self.flipped_bit()) {
+            (Some(word), Some(bit)) => {
+                write(f, format_args!(", likely 0x{word:02X} (bit {bit} flipped)"))?;
+                if self.corrected {
+                    write(f, format_args!(", corrected"))?;
+                }
+            }
+            _ if self.candidates.is_empty() => write(f, format_args!(", not a single bit flip"))?,
+            _ => write(
+                f,
+                format_args!(", {} possible words", self.candidates.len()),
+            )?,
+        }
+        Ok(())
+    }
+}
+
+#[pyclass(get_all)]
+#[derive(Debug, Default, Clone, PartialEq)]
+/// The result of decoding with single bit flip diagnosis, see [diagnose_bit_flips].
+pub struct BitFlipReport {
+    /// The decoded packets, events with an uncorrected error are dropped.
+    pub packets: Vec<MossPacket>,
+    /// A diagnostic for each protocol error.
+    pub diagnostics: Vec<BitFlipDiagnostic>,
+    /// Number of errors that were corrected.
+    pub corrected: usize,
+    /// Number of errors that are not a single bit flip with a unique likely word.
+    pub uncorrectable: usize,
+}
+
+#[pymethods]
+impl BitFlipReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "BitFlipReport(packets={}, diagnostics={}, corrected={}, uncorrectable={})",
+            self.packets.len(),
+            self.diagnostics.len(),
+            self.corrected,
+            self.uncorrectable
+        )
+    }
+}
+
+/// Decodes all events in `bytes` and diagnoses each protocol error in the hits of an event as a possible single bit flip.
+///
+/// If `correct` is true, an error with a unique likely word is corrected and decoding of the event continues.
+/// Otherwise the event is dropped and decoding resumes at the next Unit Frame Header.
+pub(crate) fn decode_events(bytes: &[u8], correct: bool) -> BitFlipReport {
+    let mut report = BitFlipReport::default();
+    let mut packet_idx = 0;
+    let mut pos = 0;
+
+    while let Some(header_idx) = scan::find_header(&bytes[pos..]).map(|i| pos + i) {
+        let unit_id = bytes[header_idx] & 0x0F;
+        let mut moss_packet = MossPacket::new(unit_id);
+        let mut byte_iter = bytes[header_idx + 1..].iter();
+        let byte_cnt = byte_iter.len();
+
+        let res = decode_hits_table::visit_hits_correcting(
+            &mut byte_iter,
+            |hit| moss_packet.hits.push(hit),
+            |err, candidates| {
+                let index = header_idx + 1 + err.err_index();
+                let likely_word = match candidates {
+                    [word] => Some(*word),
+                    _ => None,
+                };
+                let corrected = correct && likely_word.is_some();
+                if corrected {
+                    report.corrected += 1;
+                } else if likely_word.is_none() {
+                    report.uncorrectable += 1;
+                }
+                let diagnostic = BitFlipDiagnostic {
+                    packet_idx,
+                    index,
+                    unit_id,
+                    byte: bytes[index],
+                    expected: err.message().to_string(),
+                    candidates: candidates.to_vec(),
+                    likely_word,
+                    corrected,
+                };
+                log::warn!("{diagnostic}");
+                report.diagnostics.push(diagnostic);
+                likely_word.filter(|_| corrected)
+            },
+        );
+        pos = match res {
+            Ok(()) => {
+                report.packets.push(moss_packet);
+                header_idx + 1 + byte_cnt - byte_iter.len()
+            }
+            // An event without a trailer at the end of the bytes is not counted
+            Err(_) if byte_iter.len() == 0 => break,
+            Err(e) => scan::resume_after_error(bytes, header_idx + 1 + e.err_index())
+                .unwrap_or(bytes.len()),
+        };
+        packet_idx += 1;
+    }
+
+    report
+}
+
+/// Decodes all MOSS events in the given bytes and diagnoses protocol errors as possible single bit flips, e.g. a corrupted region header or trailer.
+///
+/// For each error a [BitFlipDiagnostic] reports the legal words one bit flip away from the offending byte, and the likely intended word if there is only one.
+/// If `correct` is true, the likely word is decoded in place of the offending byte and decoding of the event continues.
+/// Otherwise, or if there is no likely word, the event is dropped and decoding resumes at the next Unit Frame Header.
+///
+/// Returns a [BitFlipReport] with the decoded packets, the diagnostics and the number of corrected and uncorrectable errors.
+#[pyfunction]
+#[pyo3(signature = (bytes, correct=false))]
+pub fn diagnose_bit_flips(bytes: &[u8], correct: bool) -> PyResult<BitFlipReport> {
+    if bytes.len() < MINIMUM_EVENT_SIZE {
+        return Err(PyValueError::new_err(
+            "Received less than the minimum event size",
+        ));
+    }
+    Ok(decode_events(bytes, correct))
+}
+
+/// Decodes all MOSS events in a file with single bit flip diagnosis, see [diagnose_bit_flips].
+///
+/// Takes no [ErrorPolicy](crate::ErrorPolicy) because each error is reported as a diagnostic and decoding continues.
+#[pyfunction]
+#[pyo3(signature = (path, correct=false))]
+pub fn diagnose_bit_flips_from_file(
+    path: std::path::PathBuf,
+    correct: bool,
+) -> PyResult<BitFlipReport> {
+    let bytes = decompress::read_file(path)?;
+    diagnose_bit_flips(&bytes, correct)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::*;
+    use crate::rust_only::extract_packet_from_buf;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_valid_events_no_diagnostics() {
+        // The third event has an idle word after a region header
+        let bytes = fake_multiple_events();
+        let (packets, last_trailer_idx) = crate::decode_n_events(&bytes, 2, None, None).unwrap();
+
+        let report = decode_events(&bytes[..=last_trailer_idx], true);
+
+        assert_eq!(report.diagnostics, vec![]);
+        assert_eq!(report.packets, packets);
+        assert_eq!((report.corrected, report.uncorrectable), (0, 0));
+    }
+
+    #[test]
+    fn test_diagnose_corrupted_region_header_and_trailer() {
+        let mut bytes = fake_event_simple();
+        let (expected_packet, _, _) = extract_packet_from_buf(&bytes, None).unwrap();
+        bytes[9] ^= 0x08; // REGION_HEADER_1 -> 0xC9
+        bytes[18] ^= 0x08; // UNIT_FRAME_TRAILER -> 0xE8
+        bytes.extend(fake_event_simple());
+
+        let report = decode_events(&bytes, false);
+        assert_eq!(report.packets, vec![expected_packet.clone()]);
+        assert_eq!(report.diagnostics.len(), 1);
+        let diagnostic = &report.diagnostics[0];
+        assert_eq!((diagnostic.index, diagnostic.byte), (9, 0xC9));
+        assert_eq!(diagnostic.likely_word, Some(REGION_HEADER_1));
+        assert_eq!(diagnostic.flipped_bit(), Some(3));
+        assert!(!diagnostic.corrected);
+        assert_eq!((report.corrected, report.uncorrectable), (0, 0));
+        assert_eq!(
+            diagnostic.to_string(),
+            "Invalid word=0xC9 at index=9 in packet #1 unit 1: Expected REGION_HEADER_{1-3}/DATA_0/IDLE/UNIT_FRAME_TRAILER, likely 0xC1 (bit 3 flipped)"
+        );
+
+        let report = decode_events(&bytes, true);
+        assert_eq!(
+            report.packets,
+            vec![expected_packet.clone(), expected_packet]
+        );
+        assert_eq!(report.diagnostics.len(), 2);
+        assert_eq!(report.diagnostics[1].index, 18);
+        assert_eq!(report.diagnostics[1].likely_word, Some(UNIT_FRAME_TRAILER));
+        assert!(report.diagnostics.iter().all(|d| d.corrected));
+        assert_eq!((report.corrected, report.uncorrectable), (2, 0));
+    }
+
+    #[test]
+    fn test_uncorrectable_errors() {
+        let mut bytes = fake_event_simple();
+        bytes[11] = 0x80; // DATA_1 -> DATA_2, two bit flips away from any DATA_1
+        bytes.extend(fake_event_simple());
+        bytes[19 + 5] = 0xF4; // Idle after DATA_2 -> both idle words 0xF0 and 0xFC are one bit flip away
+
+        let report = decode_events(&bytes, true);
+
+        assert_eq!(report.packets, vec![]);
+        assert_eq!(report.diagnostics.len(), 2);
+        assert!(report.diagnostics[0].candidates.is_empty());
+        assert!(report.diagnostics[0]
+            .to_string()
+            .ends_with("not a single bit flip"));
+        assert_eq!(report.diagnostics[1].packet_idx, 1);
+        assert_eq!((report.corrected, report.uncorrectable), (0, 2));
+    }
+
+    #[test]
+    fn test_event_without_trailer() {
+        let event = [UNIT_FRAME_HEADER_1, REGION_HEADER_0, 0x00, 0x50, 0x80];
+        // The first event has no trailer, the header of the second event is the offending byte
+        let trailer = [UNIT_FRAME_TRAILER];
+        let bytes = [&event[..], &event, &trailer, &event, &trailer].concat();
+
+        let report = decode_events(&bytes, false);
+
+        assert_eq!(report.packets.len(), 2);
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(report.diagnostics[0].index, event.len());
+        assert_eq!(report.diagnostics[0].packet_idx, 0);
+    }
+}
@@ -0,0 +1,95 @@
+//! Command-line entry point, installed as the `moss-decoder` script by the Python package.
+use pyo3::exceptions::PySystemExit;
+use pyo3::prelude::*;
+
+const USAGE: &str = "\
+Usage: moss-decoder <COMMAND> [ARGS]
+
+Commands:
+  stats <FILE>... [--json]    Print run statistics of raw MOSS data files as a table or JSON
+  csv <FILE> [OUTPUT] [--tsv] [--include-empty]
+                              Export the hits of a raw MOSS data file as CSV, or TSV with --tsv,
+                              to OUTPUT or stdout, --include-empty adds a row for each event without hits
+  help                        Print this message";
+
+/// Entry point of the `moss-decoder` command-line tool, arguments are read from `sys.argv`.
+#[pyfunction]
+pub(crate) fn main(py: Python) -> PyResult<()> {
+    let argv: Vec<String> = py.import("sys")?.getattr("argv")?.extract()?;
+    let args: Vec<&str> = argv.iter().skip(1).map(String::as_str).collect();
+
+    match args.split_first() {
+        Some((&"stats", args)) => stats(py, args),
+        Some((&"csv", args)) => csv(args),
+        Some((&"help", _)) | Some((&"--help", _)) | Some((&"-h", _)) => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some((cmd, _)) => usage_error(&format!("Unknown command: {cmd}")),
+        None => usage_error("No command given"),
+    }
+}
+
+fn stats(py: Python, args: &[&str]) -> PyResult<()> {
+    let as_json = args.contains(&"--json");
+    let files: Vec<&str> = args.iter().copied().filter(|a| *a != "--json").collect();
+    if files.is_empty() {
+        return usage_error("stats: no files given");
+    }
+
+    if as_json {
+        let stats = pyo3::types::PyDict::new(py);
+        for file in files {
+            stats.set_item(file, crate::run_stats_from_file(file.into())?.to_dict(py)?)?;
+        }
+        let kwargs = pyo3::types::PyDict::new(py);
+        kwargs.set_item("indent", 2)?;
+        let json: String = py
+            .import("json")?
+            .call_method("dumps", (stats,), Some(kwargs))?
+            .extract()?;
+        println!("{json}");
+    } else {
+        for file in files {
+            println!("{file}");
+            println!("{}\n", crate::run_stats_from_file(file.into())?);
+        }
+    }
+    Ok(())
+}
+
+fn csv(args: &[&str]) -> PyResult<()> {
+    let (flags, paths): (Vec<&str>, Vec<&str>) = args.iter().partition(|a| a.starts_with("--"));
+    if let Some(flag) = flags
+        .iter()
+        .find(|f| !matches!(**f, "--tsv" | "--include-empty"))
+    {
+        return usage_error(&format!("csv: unknown option {flag}"));
+    }
+    let delimiter = if flags.contains(&"--tsv") { "\t" } else { "," };
+    let include_empty = flags.contains(&"--include-empty");
+    match paths[..] {
+        [raw_path, csv_path] => {
+            _ = crate::decode_file_to_csv(
+                raw_path.into(),
+                csv_path.into(),
+                delimiter,
+                include_empty,
+                None,
+                None,
+                None,
+            )?;
+            Ok(())
+        }
+        [raw_path] => {
+            crate::csv_io::decode_file_to_stdout(raw_path.into(), delimiter, include_empty)
+        }
+        [] => usage_error("csv: no file given"),
+        _ => usage_error("csv: too many arguments"),
+    }
+}
+
+fn usage_error(msg: &str) -> PyResult<()> {
+    eprintln!("{msg}\n\n{USAGE}");
+    Err(PySystemExit::new_err(2))
+}
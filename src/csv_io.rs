@@ -0,0 +1,378 @@
+//! Export of decoded hits to CSV/TSV and import back into [MossPacket]s, for tools that only read plain text.
+//!
+//! The first line is the header `event,offset,unit_id,region,row,column`, followed by one row per hit:
+//! - `event` is the index of the event, in the raw data when decoding a file or in the list of packets otherwise.
+//! - `offset` is the byte offset of the Unit Frame Header in the raw data, empty if unknown.
+//! - Empty events are optionally written as a single row with empty `region`, `row` and `column`.
+//!
+//! The delimiter is a comma for CSV or a tab for TSV, the reader detects it from the header.
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::{
+    decompress, source, DecodeFilter, DecodeOptions, ErrorPolicy, MossHit, MossPacket, PixelMask,
+};
+
+const COLUMNS: [&str; 6] = ["event", "offset", "unit_id", "region", "row", "column"];
+
+/// Writes packets as delimited text, one row per hit.
+#[derive(Debug)]
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    delimiter: char,
+    include_empty: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Writes the header line, `include_empty` writes a row without hit for packets without hits.
+    pub fn new(mut writer: W, delimiter: char, include_empty: bool) -> io::Result<Self> {
+        writeln!(writer, "{}", COLUMNS.join(&delimiter.to_string()))?;
+        Ok(Self {
+            writer,
+            delimiter,
+            include_empty,
+        })
+    }
+
+    /// Writes the rows of the packet of event number `event` found at byte `offset` of the raw data.
+    pub fn write_packet(
+        &mut self,
+        event: u64,
+        offset: Option<u64>,
+        packet: &MossPacket,
+    ) -> io::Result<()> {
+        let d = self.delimiter;
+        let offset = offset.map(|o| o.to_string()).unwrap_or_default();
+        let unit_id = packet.unit_id;
+        for MossHit {
+            region,
+            row,
+            column,
+        } in &packet.hits
+        {
+            writeln!(
+                self.writer,
+                "{event}{d}{offset}{d}{unit_id}{d}{region}{d}{row}{d}{column}"
+            )?;
+        }
+        if packet.hits.is_empty() && self.include_empty {
+            writeln!(self.writer, "{event}{d}{offset}{d}{unit_id}{d}{d}{d}")?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// A packet read back from delimited text with its event number and byte offset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRecord {
+    /// The event number from the `event` column.
+    pub event: u64,
+    /// The byte offset from the `offset` column, `None` if empty.
+    pub offset: Option<u64>,
+    /// The packet with the hits of all rows of the event.
+    pub packet: MossPacket,
+}
+
+/// Reads packets from delimited text written by [CsvWriter], consecutive rows with the same event number form a packet.
+///
+/// Returns an `InvalidData` error for malformed lines.
+#[derive(Debug)]
+pub struct CsvReader<R: BufRead> {
+    lines: io::Lines<R>,
+    delimiter: char,
+    line_num: usize,
+    pending: Option<CsvRecord>,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    /// Reads the header line and detects the delimiter.
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let header = header.trim_end_matches('\r');
+        let delimiter = ['\t', ',']
+            .into_iter()
+            .find(|d| header == COLUMNS.join(&d.to_string()))
+            .ok_or_else(|| {
+                invalid_data(&format!(
+                    "Expected the header `{}`, got `{header}`",
+                    COLUMNS.join(",")
+                ))
+            })?;
+        Ok(Self {
+            lines,
+            delimiter,
+            line_num: 1,
+            pending: None,
+        })
+    }
+
+    fn parse_line(&self, line: &str) -> io::Result<(CsvRecord, Option<MossHit>)> {
+        let fields: Vec<&str> = line.trim_end_matches('\r').split(self.delimiter).collect();
+        let [event, offset, unit_id, region, row, column] = fields[..] else {
+            return Err(self.line_error("expected 6 fields"));
+        };
+        let record = CsvRecord {
+            event: self.parse_field(event)?,
+            offset: (!offset.is_empty())
+                .then(|| self.parse_field(offset))
+                .transpose()?,
+            packet: MossPacket::new(self.parse_field(unit_id)?),
+        };
+        let hit = match (region, row, column) {
+            ("", "", "") => None,
+            _ => Some(MossHit {
+                region: self.parse_field(region)?,
+                row: self.parse_field(row)?,
+                column: self.parse_field(column)?,
+            }),
+        };
+        Ok((record, hit))
+    }
+
+    fn parse_field<T: std::str::FromStr>(&self, field: &str) -> io::Result<T> {
+        field
+            .parse()
+            .map_err(|_| self.line_error(&format!("invalid number `{field}`")))
+    }
+
+    fn line_error(&self, msg: &str) -> io::Error {
+        invalid_data(&format!("Line {line}: {msg}", line = self.line_num))
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<CsvRecord>> {
+        while let Some(line) = self.lines.next().transpose()? {
+            self.line_num += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (record, hit) = self.parse_line(&line)?;
+            match &mut self.pending {
+                Some(pending) if pending.event == record.event => {
+                    if pending.packet.unit_id != record.packet.unit_id {
+                        return Err(
+                            self.line_error("unit ID differs from the previous rows of the event")
+                        );
+                    }
+                    pending.packet.hits.extend(hit);
+                }
+                _ => {
+                    let mut record = record;
+                    record.packet.hits.extend(hit);
+                    if let Some(complete) = self.pending.replace(record) {
+                        return Ok(Some(complete));
+                    }
+                }
+            }
+        }
+        Ok(self.pending.take())
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = io::Result<CsvRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Malformed text raises a `ValueError`, other errors an `IOError`.
+#[allow(clippy::needless_pass_by_value)] // Passed to `map_err`
+fn to_py_err(e: io::Error) -> PyErr {
+    match e.kind() {
+        io::ErrorKind::InvalidData => PyValueError::new_err(e.to_string()),
+        io::ErrorKind::NotFound => pyo3::exceptions::PyFileNotFoundError::new_err(e.to_string()),
+        _ => PyIOError::new_err(e.to_string()),
+    }
+}
+
+fn parse_delimiter(delimiter: &str) -> PyResult<char> {
+    match delimiter {
+        "," => Ok(','),
+        "\t" => Ok('\t'),
+        _ => Err(PyValueError::new_err(
+            "delimiter must be \",\" for CSV or \"\\t\" for TSV",
+        )),
+    }
+}
+
+/// Decodes all events from `reader` and writes their rows to `writer` one event at a time, returns the number of decoded events.
+///
+/// An incomplete event at the end of the data is not written, like in [EventIndex](crate::EventIndex).
+pub(crate) fn decode_to_csv<W: Write>(
+    reader: impl io::Read,
+    writer: &mut CsvWriter<W>,
+    options: DecodeOptions,
+) -> PyResult<u64> {
+    source::for_each_packet(reader, options, |event, offset, packet| {
+        writer
+            .write_packet(event, Some(offset), packet)
+            .map_err(to_py_err)
+    })
+}
+
+/// Writes the packets to a CSV file at `path`, or TSV with `delimiter="\t"`, the `event` column is the index in `packets` and `offset` is empty.
+///
+/// With `include_empty`, packets without hits are written as a row with empty `region`, `row` and `column`.
+#[pyfunction]
+#[pyo3(signature = (path, packets, delimiter=",", include_empty=false))]
+#[allow(clippy::needless_pass_by_value)] // PyO3 extracts the Python list into an owned Vec
+pub fn write_csv(
+    path: PathBuf,
+    packets: Vec<PyRef<MossPacket>>,
+    delimiter: &str,
+    include_empty: bool,
+) -> PyResult<()> {
+    let delimiter = parse_delimiter(delimiter)?;
+    let file = File::create(path).map_err(to_py_err)?;
+    let mut writer =
+        CsvWriter::new(BufWriter::new(file), delimiter, include_empty).map_err(to_py_err)?;
+    for (event, packet) in packets.iter().enumerate() {
+        writer
+            .write_packet(event as u64, None, packet)
+            .map_err(to_py_err)?;
+    }
+    writer.finish().map(drop).map_err(to_py_err)
+}
+
+/// Decodes the raw data file at `raw_path` and writes its hits to a CSV file at `csv_path` one event at a time, so files larger than memory can be exported.
+///
+/// The `event` column is the index of the event in the raw data and `offset` the byte offset of its Unit Frame Header.
+/// Optionally drops hits on pixels in the given [PixelMask] and packets rejected by the [DecodeFilter], the event numbers of the other events are unchanged.
+/// Decoding errors are handled according to the [ErrorPolicy], the rows written before an error are kept in the CSV file.
+/// Returns the number of decoded events.
+#[pyfunction]
+#[pyo3(signature = (raw_path, csv_path, delimiter=",", include_empty=false, mask=None, filter=None, error_policy=None))]
+pub fn decode_file_to_csv(
+    raw_path: PathBuf,
+    csv_path: PathBuf,
+    delimiter: &str,
+    include_empty: bool,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+) -> PyResult<u64> {
+    let delimiter = parse_delimiter(delimiter)?;
+    let raw_file = decompress::open_file(raw_path)?;
+    let csv_file = File::create(csv_path).map_err(to_py_err)?;
+    let mut writer =
+        CsvWriter::new(BufWriter::new(csv_file), delimiter, include_empty).map_err(to_py_err)?;
+    let events = decode_to_csv(
+        raw_file,
+        &mut writer,
+        DecodeOptions::new(mask, filter, error_policy, None),
+    );
+    _ = writer.finish().map_err(to_py_err)?;
+    events
+}
+
+/// Like [decode_file_to_csv] but writes to stdout, for the command-line tool.
+pub(crate) fn decode_file_to_stdout(
+    raw_path: PathBuf,
+    delimiter: &str,
+    include_empty: bool,
+) -> PyResult<()> {
+    let delimiter = parse_delimiter(delimiter)?;
+    let raw_file = decompress::open_file(raw_path)?;
+    let mut writer = CsvWriter::new(
+        BufWriter::new(io::stdout().lock()),
+        delimiter,
+        include_empty,
+    )
+    .map_err(to_py_err)?;
+    _ = decode_to_csv(raw_file, &mut writer, DecodeOptions::default())?;
+    writer.finish().map(drop).map_err(to_py_err)
+}
+
+/// Reads the packets from a CSV or TSV file written by [write_csv] or [decode_file_to_csv].
+#[pyfunction]
+pub fn read_csv(path: PathBuf) -> PyResult<Vec<MossPacket>> {
+    let file = File::open(path).map_err(to_py_err)?;
+    CsvReader::new(BufReader::new(file))
+        .and_then(|reader| reader.map(|record| record.map(|r| r.packet)).collect())
+        .map_err(to_py_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::packet_with_hits;
+    use pretty_assertions::assert_eq;
+
+    fn write(packets: &[MossPacket], delimiter: char, include_empty: bool) -> String {
+        let mut writer = CsvWriter::new(Vec::new(), delimiter, include_empty).unwrap();
+        for (event, packet) in packets.iter().enumerate() {
+            writer
+                .write_packet(event as u64, Some(event as u64 * 10), packet)
+                .unwrap();
+        }
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    fn read(text: &str) -> io::Result<Vec<CsvRecord>> {
+        CsvReader::new(text.as_bytes())?.collect()
+    }
+
+    #[test]
+    fn test_csv_layout() {
+        let packets = [
+            packet_with_hits(1, &[(0, 1, 2), (3, 4, 5)]),
+            packet_with_hits(2, &[]),
+        ];
+        assert_eq!(
+            write(&packets, ',', true),
+            "event,offset,unit_id,region,row,column\n0,0,1,0,1,2\n0,0,1,3,4,5\n1,10,2,,,\n"
+        );
+        assert_eq!(
+            write(&packets, '\t', false),
+            "event\toffset\tunit_id\tregion\trow\tcolumn\n0\t0\t1\t0\t1\t2\n0\t0\t1\t3\t4\t5\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let packets = [
+            packet_with_hits(1, &[(0, 1, 2), (3, 4, 5)]),
+            packet_with_hits(2, &[]),
+            packet_with_hits(2, &[(1, 300, 200)]),
+        ];
+        for delimiter in [',', '\t'] {
+            let records = read(&write(&packets, delimiter, true)).unwrap();
+            assert_eq!(
+                records.iter().map(|r| &r.packet).collect::<Vec<_>>(),
+                packets.iter().collect::<Vec<_>>()
+            );
+            assert_eq!(records[2].event, 2);
+            assert_eq!(records[2].offset, Some(20));
+        }
+        // Without the empty event rows only the events with hits are read back
+        let records = read(&write(&packets, ',', false)).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].event, 2);
+    }
+
+    #[test]
+    fn test_csv_invalid() {
+        assert!(read("").is_err());
+        assert!(read("event;offset;unit_id;region;row;column\n").is_err());
+        let header = "event,offset,unit_id,region,row,column\n";
+        assert!(read(&format!("{header}0,,1,0,1\n")).is_err());
+        assert!(read(&format!("{header}0,,1,0,x,2\n")).is_err());
+        assert!(read(&format!("{header}0,,1,0,1,2\n0,,2,0,1,3\n")).is_err());
+        assert_eq!(read(&format!("{header}0,,1,0,1,2\r\n\n")).unwrap().len(), 1);
+    }
+}
@@ -77,22 +77,22 @@ pub(crate) fn debug_decode_event(
         match MossWord::from_byte(*byte) {
             MossWord::Idle => {
                 if !is_moss_packet {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    invalid_words.push(InvalidWordInfo::new(
                         *byte,
                         is_moss_packet,
                         current_region,
                         i,
-                    )));
+                    ));
                 }
             }
             MossWord::UnitFrameHeader => {
                 if is_moss_packet {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    invalid_words.push(InvalidWordInfo::new(
                         *byte,
                         is_moss_packet,
                         current_region,
                         i,
-                    )));
+                    ));
                 } else {
                     is_moss_packet = true;
                     moss_packet.unit_id = *byte & 0x0F
@@ -103,24 +103,24 @@ pub(crate) fn debug_decode_event(
                     trailer_idx = i;
                     break;
                 } else {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    invalid_words.push(InvalidWordInfo::new(
                         *byte,
                         is_moss_packet,
                         current_region,
                         i,
-                    )));
+                    ));
                 }
             }
             MossWord::RegionHeader => {
                 if is_moss_packet {
                     current_region = *byte & 0x03;
                 } else {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    invalid_words.push(InvalidWordInfo::new(
                         *byte,
                         is_moss_packet,
                         current_region,
                         i,
-                    )));
+                    ));
                 }
             }
             MossWord::Data0 => {
@@ -131,59 +131,60 @@ pub(crate) fn debug_decode_event(
                         column: 0,                         // placeholder
                     });
                 } else {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    invalid_words.push(InvalidWordInfo::new(
                         *byte,
                         is_moss_packet,
                         current_region,
                         i,
-                    )));
+                    ));
                 }
             }
             MossWord::Data1 => {
-                if is_moss_packet {
+                // A data word before the first DATA_0 of the event has no hit to add to
+                if let Some(hit) = moss_packet.hits.last_mut().filter(|_| is_moss_packet) {
                     // row position [2:0]
-                    moss_packet.hits.last_mut().unwrap().row |= ((*byte & 0x38) >> 3) as u16;
+                    hit.row |= ((*byte & 0x38) >> 3) as u16;
                     // col position [8:6]
-                    moss_packet.hits.last_mut().unwrap().column = ((*byte & 0x07) as u16) << 6;
+                    hit.column = ((*byte & 0x07) as u16) << 6;
                 } else {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    invalid_words.push(InvalidWordInfo::new(
                         *byte,
                         is_moss_packet,
                         current_region,
                         i,
-                    )));
+                    ));
                 }
             }
             MossWord::Data2 => {
-                if is_moss_packet {
-                    moss_packet.hits.last_mut().unwrap().column |= (*byte & 0x3F) as u16;
+                if let Some(hit) = moss_packet.hits.last_mut().filter(|_| is_moss_packet) {
+                    hit.column |= (*byte & 0x3F) as u16;
                 } else {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    invalid_words.push(InvalidWordInfo::new(
                         *byte,
                         is_moss_packet,
                         current_region,
                         i,
-                    )));
+                    ));
                 }
                 // col position [5:0]
             }
             MossWord::Delimiter => {
                 if is_moss_packet {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    invalid_words.push(InvalidWordInfo::new(
                         *byte,
                         is_moss_packet,
                         current_region,
                         i,
-                    )));
+                    ));
                 }
             }
             MossWord::ProtocolError => {
-                invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                invalid_words.push(InvalidWordInfo::new(
                     *byte,
                     is_moss_packet,
                     current_region,
                     i,
-                )));
+                ));
             }
         }
     }
@@ -210,11 +211,12 @@ pub(crate) fn debug_decode_event(
     }
 }
 
-// Single place to record protocol errors
-// useful for a single place to decide whether to print to stderr or not (or something else in the future)
+/// Single place to record protocol errors, called once the index of the invalid word in the whole buffer is known.
+///
+/// Logs the invalid word at warn level.
 #[inline]
-fn record_protocol_error(new_invalid_word: InvalidWordInfo) -> InvalidWordInfo {
-    //eprintln!("{}", new_invalid_word.to_error_msg());
+pub(crate) fn record_protocol_error(new_invalid_word: InvalidWordInfo) -> InvalidWordInfo {
+    log::warn!("{}", new_invalid_word.to_error_msg());
     new_invalid_word
 }
 
@@ -358,4 +360,24 @@ mod tests {
         assert!(invalid_words[0].in_packet);
         assert!(invalid_words[0].to_error_msg().contains("region unknown"));
     }
+
+    #[test]
+    fn test_debug_decode_data_word_without_data0() {
+        let event_data_packet = vec![
+            UNIT_FRAME_HEADER_1,
+            REGION_HEADER_0,
+            0b0101_0000, // DATA_1 without a preceding DATA_0
+            0b1000_1000, // DATA_2
+            UNIT_FRAME_TRAILER,
+        ];
+
+        let (moss_packet, trailer_idx, invalid_words) =
+            debug_decode_event(&event_data_packet).unwrap();
+
+        assert!(moss_packet.hits.is_empty());
+        assert_eq!(trailer_idx, 4);
+        assert_eq!(invalid_words.len(), 2);
+        assert_eq!(invalid_words[0].index, 2);
+        assert_eq!(invalid_words[1].index, 3);
+    }
 }
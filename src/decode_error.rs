@@ -0,0 +1,89 @@
+//! Error type of the Rust decoding APIs that are not bound to Python, such as the async [DecodeStream](crate::async_decode::DecodeStream).
+use pyo3::exceptions::{PyAssertionError, PyBytesWarning, PyIOError};
+use pyo3::PyErr;
+use std::fmt::{write, Display};
+
+use crate::parse_error::{ParseError, ParseErrorKind};
+
+/// An error encountered while reading or decoding MOSS data.
+///
+/// With the `serde` feature it serializes as `{"Io": "<message>"}` or `{"Parse": {"packet_num": .., "kind": .., "message": ..}}`.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DecodeError {
+    /// Reading from the source failed.
+    Io(#[cfg_attr(feature = "serde", serde(serialize_with = "serialize_io_error"))] std::io::Error),
+    /// Decoding a packet failed.
+    Parse {
+        /// The number of the packet that failed to decode, starting from 1.
+        packet_num: usize,
+        /// The kind of decoding error.
+        kind: ParseErrorKind,
+        /// Description of the error with a dump of the bytes around it.
+        message: Box<str>,
+    },
+}
+
+impl DecodeError {
+    pub(crate) fn parse(packet_num: usize, e: &ParseError) -> Self {
+        Self::Parse {
+            packet_num,
+            kind: e.kind(),
+            message: e.to_string().into(),
+        }
+    }
+}
+
+/// `std::io::Error` does not implement `Serialize`, so it is serialized as its message.
+#[cfg(feature = "serde")]
+fn serialize_io_error<S: serde::Serializer>(
+    e: &std::io::Error,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(e)
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write(f, format_args!("Failed reading data: {e}")),
+            Self::Parse {
+                packet_num,
+                message,
+                ..
+            } => write(
+                f,
+                format_args!("Failed decoding packet #{packet_num}: {message}"),
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse { kind, .. } => Some(kind),
+        }
+    }
+}
+
+impl From<std::io::Error> for DecodeError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Maps to the same exceptions as the Python decoding functions, a packet without a trailer raises a `BytesWarning`.
+impl From<DecodeError> for PyErr {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::Io(_) => PyIOError::new_err(e.to_string()),
+            DecodeError::Parse {
+                kind: ParseErrorKind::EndOfBufferNoTrailer,
+                ..
+            } => PyBytesWarning::new_err(e.to_string()),
+            DecodeError::Parse { .. } => PyAssertionError::new_err(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Filtering of decoded packets by unit ID, region and hit multiplicity, applied while decoding so discarded packets never become Python objects.
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::MossPacket;
+
+#[pyclass]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Selects which packets and hits the decode functions return.
+///
+/// Hits outside the accepted regions are dropped first, then packets are dropped if their unit ID is not accepted or their number of hits is outside the limits.
+pub struct DecodeFilter {
+    /// Bit `n` is set if unit ID `n` is accepted.
+    unit_ids: u16,
+    /// Bit `n` is set if region `n` is accepted.
+    regions: u8,
+    min_hits: Option<usize>,
+    max_hits: Option<usize>,
+    drop_empty: bool,
+}
+
+#[pymethods]
+impl DecodeFilter {
+    #[new]
+    #[pyo3(signature = (unit_ids=None, regions=None, min_hits=None, max_hits=None, drop_empty=false))]
+    /// Creates a new filter, `None` accepts all unit IDs, regions or hit counts.
+    pub fn new(
+        unit_ids: Option<Vec<u8>>,
+        regions: Option<Vec<u8>>,
+        min_hits: Option<usize>,
+        max_hits: Option<usize>,
+        drop_empty: bool,
+    ) -> PyResult<Self> {
+        let unit_ids = match unit_ids {
+            Some(unit_ids) => to_bits(&unit_ids, 15, "unit ID")?,
+            None => u16::MAX,
+        };
+        let regions = match regions {
+            Some(regions) => to_bits(&regions, 3, "region")? as u8,
+            None => 0b1111,
+        };
+        if min_hits.zip(max_hits).is_some_and(|(min, max)| min > max) {
+            return Err(PyValueError::new_err(
+                "min_hits must not be greater than max_hits",
+            ));
+        }
+        Ok(Self {
+            unit_ids,
+            regions,
+            min_hits,
+            max_hits,
+            drop_empty,
+        })
+    }
+
+    /// The accepted unit IDs.
+    #[getter]
+    pub fn unit_ids(&self) -> Vec<u8> {
+        (0..16)
+            .filter(|id| self.unit_ids & (1 << id) != 0)
+            .collect()
+    }
+
+    /// The accepted regions.
+    #[getter]
+    pub fn regions(&self) -> Vec<u8> {
+        (0..4).filter(|r| self.regions & (1 << r) != 0).collect()
+    }
+
+    /// The minimum number of hits in a packet, if any.
+    #[getter]
+    pub fn min_hits(&self) -> Option<usize> {
+        self.min_hits
+    }
+
+    /// The maximum number of hits in a packet, if any.
+    #[getter]
+    pub fn max_hits(&self) -> Option<usize> {
+        self.max_hits
+    }
+
+    /// Whether packets without hits are dropped.
+    #[getter]
+    pub fn drop_empty(&self) -> bool {
+        self.drop_empty
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "DecodeFilter(unit_ids={:?}, regions={:?}, min_hits={}, max_hits={}, drop_empty={})",
+            self.unit_ids(),
+            self.regions(),
+            py_optional(self.min_hits),
+            py_optional(self.max_hits),
+            if self.drop_empty { "True" } else { "False" }
+        )
+    }
+}
+
+impl DecodeFilter {
+    /// Drops the hits outside the accepted regions from the packet, returns `true` if the packet should be kept.
+    #[inline]
+    pub fn apply(&self, packet: &mut MossPacket) -> bool {
+        if !self.accepts_unit(packet.unit_id) {
+            return false;
+        }
+        if self.regions != 0b1111 {
+            packet.hits.retain(|hit| self.accepts_region(hit.region));
+        }
+        self.accepts_hit_count(packet.hits.len())
+    }
+
+    /// Returns `true` if packets from the unit are accepted.
+    #[inline]
+    pub(crate) fn accepts_unit(&self, unit_id: u8) -> bool {
+        self.unit_ids & (1 << (unit_id & 0xF)) != 0
+    }
+
+    /// Returns `true` if hits in the region are kept.
+    #[inline]
+    pub(crate) fn accepts_region(&self, region: u8) -> bool {
+        region < 4 && self.regions & (1 << region) != 0
+    }
+
+    /// Returns `true` if a packet with `hit_count` hits in the accepted regions is kept.
+    #[inline]
+    pub(crate) fn accepts_hit_count(&self, hit_count: usize) -> bool {
+        !(self.drop_empty && hit_count == 0)
+            && self.min_hits.is_none_or(|min| hit_count >= min)
+            && self.max_hits.is_none_or(|max| hit_count <= max)
+    }
+}
+
+/// Formats an optional value the way Python prints it.
+fn py_optional(value: Option<usize>) -> String {
+    value.map_or_else(|| "None".to_owned(), |v| v.to_string())
+}
+
+/// Converts a list of IDs to a bit set, returns an error if an ID is greater than `max`.
+fn to_bits(ids: &[u8], max: u8, name: &str) -> PyResult<u16> {
+    ids.iter().try_fold(0, |bits, &id| {
+        if id > max {
+            Err(PyValueError::new_err(format!(
+                "Invalid {name} {id}, must be at most {max}"
+            )))
+        } else {
+            Ok(bits | 1 << id)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::packet_with_hits;
+    use pretty_assertions::assert_eq;
+
+    /// Creates a packet with a hit in each of the given regions.
+    fn packet(unit_id: u8, regions: &[u8]) -> MossPacket {
+        let hits: Vec<_> = regions.iter().map(|&region| (region, 1, 2)).collect();
+        packet_with_hits(unit_id, &hits)
+    }
+
+    #[test]
+    fn test_filter_unit_ids_and_regions() {
+        let filter =
+            DecodeFilter::new(Some(vec![1, 9]), Some(vec![0, 2]), None, None, true).unwrap();
+
+        let mut accepted = packet(9, &[0, 1, 2, 3, 2]);
+        assert!(filter.apply(&mut accepted));
+        assert_eq!(
+            accepted.hits.iter().map(|h| h.region).collect::<Vec<_>>(),
+            [0, 2, 2]
+        );
+        assert!(!filter.apply(&mut packet(2, &[0])));
+        // Empty after dropping the hits outside the regions
+        assert!(!filter.apply(&mut packet(1, &[1, 3])));
+        assert_eq!(filter.unit_ids(), [1, 9]);
+        assert_eq!(filter.regions(), [0, 2]);
+    }
+
+    #[test]
+    fn test_filter_hit_multiplicity() {
+        let filter = DecodeFilter::new(None, None, Some(2), Some(3), false).unwrap();
+        assert!(!filter.apply(&mut packet(1, &[0])));
+        assert!(filter.apply(&mut packet(1, &[0, 1])));
+        assert!(filter.apply(&mut packet(1, &[0, 1, 2])));
+        assert!(!filter.apply(&mut packet(1, &[0, 1, 2, 3])));
+
+        let keep_empty = DecodeFilter::new(None, None, None, None, false).unwrap();
+        assert!(keep_empty.apply(&mut packet(1, &[])));
+    }
+
+    #[test]
+    fn test_invalid_filter() {
+        pyo3::prepare_freethreaded_python();
+        assert!(DecodeFilter::new(Some(vec![16]), None, None, None, false).is_err());
+        assert!(DecodeFilter::new(None, Some(vec![4]), None, None, false).is_err());
+        assert!(DecodeFilter::new(None, None, Some(3), Some(2), false).is_err());
+    }
+}
@@ -80,18 +80,39 @@ const REGION_HEADER3: u8 = 0xC3;
 /// Returns all the decoded [MossHit]s if any.
 #[inline]
 pub(crate) fn extract_hits<'a>(
-    bytes: &mut (impl Iterator<Item = &'a u8>
-              + std::iter::DoubleEndedIterator
-              + std::iter::ExactSizeIterator),
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
 ) -> Result<Vec<MossHit>, ParseError> {
+    let mut hits = Vec::<MossHit>::new();
+    visit_hits(bytes, |hit| hits.push(hit))?;
+    Ok(hits)
+}
+
+/// Like [extract_hits] but calls `on_hit` with each decoded [MossHit] instead of collecting them, nothing is allocated.
+///
+/// If an error is returned, `on_hit` may already have been called for the hits before the error.
+#[inline]
+pub(crate) fn visit_hits<'a>(
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
+    mut on_hit: impl FnMut(MossHit),
+) -> Result<(), ParseError> {
     let total_bytes = bytes.len();
     let mut sm = MossDataFSM::Machine::new(_UNIT_FRAME_HEADER_).as_enum();
-    let mut hits = Vec::<MossHit>::new();
+    // The hit being decoded, it is complete after the DATA_2 word
+    let mut hit = MossHit {
+        region: 0,
+        row: 0,
+        column: 0,
+    };
 
     let mut is_trailer_seen = false;
     let mut current_region = 0xff;
+    // Checked once per event to keep the disabled logging out of the loop
+    let trace = log::log_enabled!(log::Level::Trace);
 
     for (i, b) in bytes.enumerate() {
+        if trace {
+            log::trace!("0x{b:02X} at index={i} in state {}", state_name(&sm));
+        }
         sm = match sm {
             Initial_UNIT_FRAME_HEADER_(st) => match *b {
                 REGION_HEADER0 => {
@@ -136,7 +157,7 @@ pub(crate) fn extract_hits<'a>(
                     st.transition(_RegionHeader3).as_enum()
                 }
                 b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
+                    add_data0(&mut hit, b, current_region);
                     st.transition(_Data).as_enum()
                 }
                 MossWord::UNIT_FRAME_TRAILER => {
@@ -153,7 +174,7 @@ pub(crate) fn extract_hits<'a>(
             },
             DATA0_By_Data(st) => {
                 if MossWord::DATA_1_RANGE.contains(b) {
-                    add_data1(&mut hits, *b);
+                    add_data1(&mut hit, *b);
                     st.transition(_Data).as_enum()
                 } else {
                     return Err(ParseError::new(
@@ -165,7 +186,8 @@ pub(crate) fn extract_hits<'a>(
             }
             DATA1_By_Data(st) => {
                 if MossWord::DATA_2_RANGE.contains(b) {
-                    add_data2(&mut hits, *b);
+                    add_data2(&mut hit, *b);
+                    on_hit(hit);
                     st.transition(_Data).as_enum()
                 } else {
                     return Err(ParseError::new(
@@ -177,7 +199,7 @@ pub(crate) fn extract_hits<'a>(
             }
             DATA2_By_Data(st) => match *b {
                 b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
+                    add_data0(&mut hit, b, current_region);
                     st.transition(_Data).as_enum()
                 }
                 MossWord::IDLE_NO_BACKBONE => st.transition(_Idle).as_enum(),
@@ -210,7 +232,7 @@ pub(crate) fn extract_hits<'a>(
             },
             IDLE_By_Idle(st) => match *b {
                 b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
+                    add_data0(&mut hit, b, current_region);
                     st.transition(_Data).as_enum()
                 }
                 REGION_HEADER1 => {
@@ -251,7 +273,7 @@ pub(crate) fn extract_hits<'a>(
                     st.transition(_RegionHeader3).as_enum()
                 }
                 b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
+                    add_data0(&mut hit, b, current_region);
                     st.transition(_Data).as_enum()
                 }
                 MossWord::UNIT_FRAME_TRAILER => {
@@ -272,7 +294,7 @@ pub(crate) fn extract_hits<'a>(
                     st.transition(_RegionHeader3).as_enum()
                 }
                 b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
+                    add_data0(&mut hit, b, current_region);
                     st.transition(_Data).as_enum()
                 }
                 MossWord::UNIT_FRAME_TRAILER => {
@@ -289,7 +311,7 @@ pub(crate) fn extract_hits<'a>(
             },
             REGION_HEADER3_By_RegionHeader3(st) => match *b {
                 b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
+                    add_data0(&mut hit, b, current_region);
                     st.transition(_Data).as_enum()
                 }
                 MossWord::UNIT_FRAME_TRAILER => {
@@ -299,7 +321,7 @@ pub(crate) fn extract_hits<'a>(
                 _ => {
                     return Err(ParseError::new(
                         ParseErrorKind::ProtocolError,
-                        "Expected UNIT_FRAME_TRAILER/DATA_0/UNIT_FRAME_TRAILER",
+                        "Expected DATA_0/UNIT_FRAME_TRAILER",
                         i,
                     ))
                 }
@@ -311,45 +333,50 @@ pub(crate) fn extract_hits<'a>(
     }
 
     if is_trailer_seen {
-        if hits.is_empty() {
-            Ok(Vec::with_capacity(0))
-        } else {
-            Ok(hits)
-        }
+        Ok(())
     } else {
         Err(ParseError::new(
             ParseErrorKind::EndOfBufferNoTrailer,
             "Reached end with no UNIT_FRAME_TRAILER",
-            total_bytes - 1,
+            total_bytes.saturating_sub(1),
         ))
     }
 }
 
+/// The name of the state in trace logs.
+fn state_name(sm: &Variant) -> &'static str {
+    match sm {
+        Initial_UNIT_FRAME_HEADER_(_) => "UNIT_FRAME_HEADER",
+        REGION_HEADER0_By_RegionHeader0(_) => "REGION_HEADER0",
+        REGION_HEADER1_By_RegionHeader1(_) => "REGION_HEADER1",
+        REGION_HEADER2_By_RegionHeader2(_) => "REGION_HEADER2",
+        REGION_HEADER3_By_RegionHeader3(_) => "REGION_HEADER3",
+        DATA0_By_Data(_) => "DATA0",
+        DATA1_By_Data(_) => "DATA1",
+        DATA2_By_Data(_) => "DATA2",
+        IDLE_By_Idle(_) => "IDLE",
+        FRAME_TRAILER_By_FrameTrailer(_) => "FRAME_TRAILER",
+    }
+}
+
 #[inline]
-fn add_data0(moss_hits: &mut Vec<MossHit>, data0: u8, region: u8) {
-    moss_hits.push(MossHit {
+pub(crate) fn add_data0(hit: &mut MossHit, data0: u8, region: u8) {
+    *hit = MossHit {
         region,                            // region id
         row: ((data0 & 0x3F) as u16) << 3, // row position [8:3]
         column: 0,                         // placeholder
-    })
+    }
 }
 
 #[inline]
-fn add_data1(moss_hits: &mut [MossHit], data1: u8) {
-    moss_hits
-        .last_mut()
-        .unwrap() // row position [2:0]
-        .row |= ((data1 & 0x38) >> 3) as u16;
-
-    moss_hits
-        .last_mut()
-        .unwrap() // col position [8:6]
-        .column = ((data1 & 0x07) as u16) << 6;
+pub(crate) fn add_data1(hit: &mut MossHit, data1: u8) {
+    hit.row |= ((data1 & 0x38) >> 3) as u16; // row position [2:0]
+    hit.column = ((data1 & 0x07) as u16) << 6; // col position [8:6]
 }
 
 #[inline]
-fn add_data2(moss_hits: &mut [MossHit], data2: u8) {
-    moss_hits.last_mut().unwrap().column |= (data2 & 0x3F) as u16;
+pub(crate) fn add_data2(hit: &mut MossHit, data2: u8) {
+    hit.column |= (data2 & 0x3F) as u16;
 }
 
 #[cfg(test)]
@@ -434,7 +461,7 @@ mod tests {
         let p = extract_packet_from_buf(&packet, None);
         println!("{p:?}");
         assert!(p.is_ok());
-        let (p, trailer_idx) = p.unwrap();
+        let (p, _, trailer_idx) = p.unwrap();
         assert_eq!(p.hits.len(), 4);
         assert_eq!(trailer_idx, 18);
     }
@@ -0,0 +1,327 @@
+//! Contains a table-driven implementation of the MOSS data readout protocol, equivalent to the FSM in [decode_hits_fsm](crate::decode_hits_fsm).
+//!
+//! Each byte is mapped to a byte class by a 256-entry table, and the next state is looked up in a transition table indexed by the current state and the byte class.
+//! Decoding a byte is two table lookups and a match on the byte class, without the typed state conversions of the `sm` crate machine.
+
+use crate::decode_hits_fsm::{add_data0, add_data1, add_data2};
+use crate::moss_protocol::MossWord;
+use crate::parse_error::ParseError;
+use crate::parse_error::ParseErrorKind;
+use crate::MossHit;
+
+/// Byte classes, the columns of [TRANSITIONS].
+mod class {
+    pub(super) const REGION_HEADER0: u8 = 0;
+    pub(super) const REGION_HEADER1: u8 = 1;
+    pub(super) const REGION_HEADER2: u8 = 2;
+    pub(super) const REGION_HEADER3: u8 = 3;
+    pub(super) const DATA0: u8 = 4;
+    pub(super) const DATA1: u8 = 5;
+    pub(super) const DATA2: u8 = 6;
+    pub(super) const IDLE: u8 = 7;
+    pub(super) const TRAILER: u8 = 8;
+    pub(super) const OTHER: u8 = 9;
+    pub(super) const COUNT: usize = 10;
+}
+
+/// Decoder states, the rows of [TRANSITIONS], followed by the two final states.
+mod state {
+    pub(super) const UNIT_FRAME_HEADER: u8 = 0;
+    pub(super) const REGION_HEADER0: u8 = 1;
+    pub(super) const REGION_HEADER1: u8 = 2;
+    pub(super) const REGION_HEADER2: u8 = 3;
+    pub(super) const REGION_HEADER3: u8 = 4;
+    pub(super) const DATA0: u8 = 5;
+    pub(super) const DATA1: u8 = 6;
+    pub(super) const DATA2: u8 = 7;
+    pub(super) const IDLE: u8 = 8;
+    pub(super) const COUNT: usize = 9;
+    pub(super) const FRAME_TRAILER: u8 = 9;
+    pub(super) const ERROR: u8 = 10;
+}
+
+const fn in_range(b: u8, range: &std::ops::RangeInclusive<u8>) -> bool {
+    *range.start() <= b && b <= *range.end()
+}
+
+/// The [class] of every byte value.
+#[rustfmt::skip]
+const BYTE_CLASS: [u8; 256] = {
+    let mut table = [class::OTHER; 256];
+    let mut i = 0;
+    while i < table.len() {
+        let b = i as u8;
+        table[i] = match b {
+            0xC0 => class::REGION_HEADER0,
+            0xC1 => class::REGION_HEADER1,
+            0xC2 => class::REGION_HEADER2,
+            0xC3 => class::REGION_HEADER3,
+            MossWord::IDLE_NO_BACKBONE | MossWord::IDLE_FOUR_BIT | MossWord::IDLE_TWO_BIT | MossWord::IDLE_ONE_BIT => class::IDLE,
+            MossWord::UNIT_FRAME_TRAILER => class::TRAILER,
+            _ if in_range(b, &MossWord::DATA_0_RANGE) => class::DATA0,
+            _ if in_range(b, &MossWord::DATA_1_RANGE) => class::DATA1,
+            _ if in_range(b, &MossWord::DATA_2_RANGE) => class::DATA2,
+            _ => class::OTHER,
+        };
+        i += 1;
+    }
+    table
+};
+
+/// The next [state] indexed by the current state and the byte [class].
+#[rustfmt::skip]
+const TRANSITIONS: [[u8; class::COUNT]; state::COUNT] = {
+    const H0: u8 = state::REGION_HEADER0;
+    const H1: u8 = state::REGION_HEADER1;
+    const H2: u8 = state::REGION_HEADER2;
+    const H3: u8 = state::REGION_HEADER3;
+    const D0: u8 = state::DATA0;
+    const D1: u8 = state::DATA1;
+    const D2: u8 = state::DATA2;
+    const I: u8 = state::IDLE;
+    const T: u8 = state::FRAME_TRAILER;
+    const E: u8 = state::ERROR;
+    [
+        // RH0 RH1 RH2 RH3 DATA0 DATA1 DATA2 IDLE TRAILER OTHER
+        [  H0, H1, H2, H3, E,    E,    E,    E,   T,      E], // UNIT_FRAME_HEADER
+        [  E,  H1, H2, H3, D0,   E,    E,    E,   T,      E], // REGION_HEADER0
+        [  E,  E,  H2, H3, D0,   E,    E,    E,   T,      E], // REGION_HEADER1
+        [  E,  E,  E,  H3, D0,   E,    E,    E,   T,      E], // REGION_HEADER2
+        [  E,  E,  E,  E,  D0,   E,    E,    E,   T,      E], // REGION_HEADER3
+        [  E,  E,  E,  E,  E,    D1,   E,    E,   E,      E], // DATA0
+        [  E,  E,  E,  E,  E,    E,    D2,   E,   E,      E], // DATA1
+        [  E,  H1, H2, H3, D0,   E,    E,    I,   T,      E], // DATA2
+        [  E,  H1, H2, H3, D0,   E,    E,    I,   T,      E], // IDLE
+    ]
+};
+
+/// The error message for an unexpected byte in each [state], the same as the FSM's.
+const ERROR_MESSAGES: [&str; state::COUNT] = [
+    "Expected REGION_HEADER_{0-3}/UNIT_FRAME_TRAILER",
+    "Expected REGION_HEADER_{1-3}/DATA_0/UNIT_FRAME_TRAILER",
+    "Expected REGION_HEADER_{2-3}/DATA_0/UNIT_FRAME_TRAILER",
+    "Expected REGION_HEADER_3/DATA_0/UNIT_FRAME_TRAILER",
+    "Expected DATA_0/UNIT_FRAME_TRAILER",
+    "Expected DATA_1",
+    "Expected DATA_2",
+    "Expected REGION_HEADER_{1-3}/DATA_0/IDLE/UNIT_FRAME_TRAILER",
+    "Expected REGION_HEADER_{1-3}/DATA_0/IDLE/UNIT_FRAME_TRAILER",
+];
+
+/// The name of each [state] in trace logs, the same as the FSM's.
+const STATE_NAMES: [&str; state::COUNT] = [
+    "UNIT_FRAME_HEADER",
+    "REGION_HEADER0",
+    "REGION_HEADER1",
+    "REGION_HEADER2",
+    "REGION_HEADER3",
+    "DATA0",
+    "DATA1",
+    "DATA2",
+    "IDLE",
+];
+
+/// Table-driven equivalent of [extract_hits](crate::decode_hits_fsm::extract_hits).
+#[inline]
+pub(crate) fn extract_hits<'a>(
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
+) -> Result<Vec<MossHit>, ParseError> {
+    let mut hits = Vec::<MossHit>::new();
+    visit_hits(bytes, |hit| hits.push(hit))?;
+    Ok(hits)
+}
+
+/// Table-driven equivalent of [visit_hits](crate::decode_hits_fsm::visit_hits), decodes the same hits and returns the same errors.
+#[inline]
+pub(crate) fn visit_hits<'a>(
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
+    on_hit: impl FnMut(MossHit),
+) -> Result<(), ParseError> {
+    visit_hits_correcting(bytes, on_hit, |_, _| None)
+}
+
+/// Like [visit_hits], but on a protocol error `on_error` is called with the error and the bytes that are one bit flip away from the offending byte and legal in the current state.
+///
+/// If `on_error` returns a byte, it is decoded in place of the offending byte and decoding continues, otherwise the error is returned.
+#[inline]
+pub(crate) fn visit_hits_correcting<'a>(
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
+    mut on_hit: impl FnMut(MossHit),
+    mut on_error: impl FnMut(&ParseError, &[u8]) -> Option<u8>,
+) -> Result<(), ParseError> {
+    let total_bytes = bytes.len();
+    let mut current_state = state::UNIT_FRAME_HEADER;
+    // The hit being decoded, it is complete after the DATA_2 word
+    let mut hit = MossHit {
+        region: 0,
+        row: 0,
+        column: 0,
+    };
+    let mut current_region = 0xff;
+    // Checked once per event to keep the disabled logging out of the loop
+    let trace = log::log_enabled!(log::Level::Trace);
+
+    for (i, b) in bytes.enumerate() {
+        let mut b = *b;
+        if trace {
+            log::trace!(
+                "0x{b:02X} at index={i} in state {}",
+                STATE_NAMES[current_state as usize]
+            );
+        }
+        let mut byte_class = BYTE_CLASS[b as usize];
+        let mut next_state = TRANSITIONS[current_state as usize][byte_class as usize];
+        if next_state == state::ERROR {
+            let err = ParseError::new(
+                ParseErrorKind::ProtocolError,
+                ERROR_MESSAGES[current_state as usize],
+                i,
+            );
+            let Some(corrected) = on_error(&err, &single_bit_flips(current_state, b)) else {
+                return Err(err);
+            };
+            b = corrected;
+            byte_class = BYTE_CLASS[b as usize];
+            next_state = TRANSITIONS[current_state as usize][byte_class as usize];
+            if next_state == state::ERROR {
+                return Err(err);
+            }
+        }
+        if next_state == state::FRAME_TRAILER {
+            return Ok(());
+        }
+        match byte_class {
+            class::REGION_HEADER0..=class::REGION_HEADER3 => current_region = byte_class,
+            class::DATA0 => add_data0(&mut hit, b, current_region),
+            class::DATA1 => add_data1(&mut hit, b),
+            class::DATA2 => {
+                add_data2(&mut hit, b);
+                on_hit(hit);
+            }
+            _ => (),
+        }
+        current_state = next_state;
+    }
+
+    Err(ParseError::new(
+        ParseErrorKind::EndOfBufferNoTrailer,
+        "Reached end with no UNIT_FRAME_TRAILER",
+        total_bytes.saturating_sub(1),
+    ))
+}
+
+/// Returns the bytes that differ from `byte` in a single bit and are legal in `current_state`, in order of the flipped bit.
+#[cold]
+fn single_bit_flips(current_state: u8, byte: u8) -> Vec<u8> {
+    (0..8)
+        .map(|bit| byte ^ (1 << bit))
+        .filter(|b| {
+            TRANSITIONS[current_state as usize][BYTE_CLASS[*b as usize] as usize] != state::ERROR
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode_hits_fsm;
+    use crate::moss_protocol::test_util::*;
+    use pretty_assertions::assert_eq;
+
+    /// Decodes the event after the first header in `bytes` with both decoders and checks that the results and the remaining bytes are the same.
+    fn assert_same_as_fsm(bytes: &[u8]) -> Option<usize> {
+        let header_idx = crate::scan::find_header(bytes)?;
+        let mut fsm_iter = bytes[header_idx + 1..].iter();
+        let mut fsm_hits = Vec::new();
+        let fsm_result = decode_hits_fsm::visit_hits(&mut fsm_iter, |hit| fsm_hits.push(hit));
+
+        let mut table_iter = bytes[header_idx + 1..].iter();
+        let mut table_hits = Vec::new();
+        let table_result = visit_hits(&mut table_iter, |hit| table_hits.push(hit));
+
+        assert_eq!(
+            table_result.map_err(|e| (e.kind(), e.to_string(), e.err_index())),
+            fsm_result.map_err(|e| (e.kind(), e.to_string(), e.err_index()))
+        );
+        assert_eq!(table_hits, fsm_hits);
+        assert_eq!(table_iter.len(), fsm_iter.len());
+        Some(bytes.len() - fsm_iter.len())
+    }
+
+    #[test]
+    fn test_byte_classes() {
+        assert_eq!(BYTE_CLASS[0x00], class::DATA0);
+        assert_eq!(BYTE_CLASS[0x28], class::DATA0);
+        assert_eq!(BYTE_CLASS[0x29], class::OTHER);
+        assert_eq!(BYTE_CLASS[0x7D], class::DATA1);
+        assert_eq!(BYTE_CLASS[0x7E], class::OTHER);
+        assert_eq!(BYTE_CLASS[0xBF], class::DATA2);
+        assert_eq!(BYTE_CLASS[0xC3], class::REGION_HEADER3);
+        assert_eq!(BYTE_CLASS[0xD1], class::OTHER);
+        assert_eq!(BYTE_CLASS[0xE0], class::TRAILER);
+        assert_eq!(BYTE_CLASS[0xF0], class::IDLE);
+        assert_eq!(BYTE_CLASS[0xFA], class::OTHER);
+    }
+
+    #[test]
+    fn test_fake_events_same_as_fsm() {
+        for event in [
+            fake_event_simple(),
+            fake_multiple_events(),
+            fake_event_protocol_error(),
+            fake_event_protocol_error_fb_in_idle(),
+            fake_event_simple()[..10].to_vec(),
+        ] {
+            assert!(assert_same_as_fsm(&event).is_some());
+        }
+    }
+
+    #[test]
+    fn test_test_data_same_as_fsm() {
+        let mut files = 0;
+        for entry in std::fs::read_dir("tests/test-data").unwrap() {
+            let bytes = std::fs::read(entry.unwrap().path()).unwrap();
+            let mut pos = 0;
+            while let Some(consumed) = assert_same_as_fsm(&bytes[pos..]) {
+                pos += consumed;
+            }
+            files += 1;
+        }
+        assert!(files > 0);
+    }
+
+    #[test]
+    fn test_error_after_region_header_3() {
+        let event = [UNIT_FRAME_HEADER_1, REGION_HEADER_3, REGION_HEADER_0];
+        let err = visit_hits(&mut event[1..].iter(), |_| {}).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Expected DATA_0/UNIT_FRAME_TRAILER"));
+        assert!(assert_same_as_fsm(&event).is_some());
+    }
+
+    #[test]
+    fn test_single_bit_flips() {
+        // A trailer with bit 3 flipped after a hit, the flipped region header 0xC9 is not legal after DATA_2
+        assert_eq!(single_bit_flips(state::DATA2, 0xE8), vec![0xE0]);
+        // Both idle words 0xF0 and 0xFC are legal after DATA_2
+        assert_eq!(single_bit_flips(state::DATA2, 0xF4), vec![0xF0, 0xFC]);
+        assert_eq!(single_bit_flips(state::DATA0, 0x00), vec![0x40]);
+        // A DATA_1 word is two bit flips away from any DATA_2 word
+        assert!(single_bit_flips(state::DATA1, 0x40).is_empty());
+    }
+
+    #[test]
+    fn test_corrupted_bytes_same_as_fsm() {
+        // Flip bytes of a valid event at pseudo-random positions to every possible value, so both decoders see all kinds of protocol errors
+        let event = fake_multiple_events();
+        let mut seed: u32 = 0x1234_5678;
+        for _ in 0..2000 {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let mut corrupted = event.clone();
+            let idx = (seed >> 8) as usize % corrupted.len();
+            corrupted[idx] = (seed >> 24) as u8;
+            _ = assert_same_as_fsm(&corrupted);
+        }
+    }
+}
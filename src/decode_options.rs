@@ -0,0 +1,39 @@
+//! Options taken by the `*_with` decode functions, e.g. [decode_all_events_with](crate::decode_all_events_with).
+use crate::{DecodeFilter, ErrorPolicy, FpgaPrefix, PixelMask};
+
+/// Selects the hits and packets a decode function returns and how it handles errors.
+///
+/// The default decodes all hits of all events and raises the first error, like the functions without options.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DecodeOptions<'a> {
+    /// Drops hits on the masked pixels, the mask is applied before the filter.
+    pub mask: Option<&'a PixelMask>,
+    /// Drops packets and hits that are not selected, the event numbers of `take`, `skip` and errors count all decoded events.
+    pub filter: Option<&'a DecodeFilter>,
+    /// How decoding and I/O errors are handled when decoding from a file or another reader, in-memory decoding raises the first error.
+    pub policy: ErrorPolicy,
+    /// Layout of the FPGA prefix preceding each event, `None` if the events are not prefixed.
+    pub prefix: Option<&'a FpgaPrefix>,
+}
+
+impl<'a> DecodeOptions<'a> {
+    /// Collects the optional arguments of the Python decode functions, the policy defaults to [ErrorPolicy::Strict].
+    pub(crate) fn new(
+        mask: Option<&'a PixelMask>,
+        filter: Option<&'a DecodeFilter>,
+        error_policy: Option<ErrorPolicy>,
+        prefix: Option<&'a FpgaPrefix>,
+    ) -> Self {
+        Self {
+            mask,
+            filter,
+            policy: error_policy.unwrap_or_default(),
+            prefix,
+        }
+    }
+
+    /// Length of the FPGA prefix preceding each event, 0 if the events are not prefixed.
+    pub(crate) fn prefix_len(&self) -> usize {
+        self.prefix.map_or(0, FpgaPrefix::length)
+    }
+}
@@ -0,0 +1,196 @@
+//! Transparent decompression of compressed raw MOSS data files, the compression is detected by the magic bytes at the start of the file.
+//!
+//! Each codec is behind a cargo feature so the default build stays lean: `gzip`, `zstd` and `xz` (also handles legacy `.lzma` files).
+use pyo3::exceptions::{PyFileNotFoundError, PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::io::{BufRead, Read};
+use std::path::Path;
+
+/// The compression format of a raw data file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Uncompressed raw MOSS data.
+    None,
+    /// gzip, requires the `gzip` feature.
+    Gzip,
+    /// Zstandard, requires the `zstd` feature.
+    Zstd,
+    /// xz, requires the `xz` feature.
+    Xz,
+    /// Legacy LZMA (`.lzma`), requires the `xz` feature.
+    Lzma,
+}
+
+impl Compression {
+    const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+    const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+    const XZ_MAGIC: [u8; 6] = [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00];
+    // Properties byte of the default `lc=3 lp=0 pb=2` settings followed by the start of the dictionary size
+    const LZMA_MAGIC: [u8; 3] = [0x5D, 0x00, 0x00];
+
+    /// Detects the compression from the first bytes of a file.
+    ///
+    /// None of the magic bytes can start valid MOSS data, which always starts with a delimiter or a Unit Frame Header.
+    pub fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(&Self::GZIP_MAGIC) {
+            Self::Gzip
+        } else if magic.starts_with(&Self::ZSTD_MAGIC) {
+            Self::Zstd
+        } else if magic.starts_with(&Self::XZ_MAGIC) {
+            Self::Xz
+        } else if magic.starts_with(&Self::LZMA_MAGIC) {
+            Self::Lzma
+        } else {
+            Self::None
+        }
+    }
+
+    fn name_and_feature(self) -> (&'static str, &'static str) {
+        match self {
+            Self::None => ("not", ""),
+            Self::Gzip => ("gzip", "gzip"),
+            Self::Zstd => ("zstd", "zstd"),
+            Self::Xz => ("xz", "xz"),
+            Self::Lzma => ("lzma", "xz"),
+        }
+    }
+}
+
+/// Wraps `reader` in a decompressor if its first bytes match a compression format, otherwise the data is read as is.
+///
+/// Returns an error if the data is compressed with a format whose cargo feature is not enabled.
+pub fn decompress<'r>(mut reader: impl BufRead + 'r) -> PyResult<Box<dyn Read + 'r>> {
+    let magic = reader
+        .fill_buf()
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    let compression = Compression::detect(magic);
+    match compression {
+        Compression::None => Ok(Box::new(reader)),
+        #[cfg(feature = "gzip")]
+        // Multi-member to decode files made by concatenating gzip files like `gzip -d` does
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(
+            zstd::stream::read::Decoder::with_buffer(reader)
+                .map_err(|e| PyIOError::new_err(e.to_string()))?,
+        )),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Ok(Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader))),
+        #[cfg(feature = "xz")]
+        Compression::Lzma => Ok(Box::new(xz2::bufread::XzDecoder::new_stream(
+            reader,
+            xz2::stream::Stream::new_lzma_decoder(u64::MAX)
+                .map_err(|e| PyIOError::new_err(e.to_string()))?,
+        ))),
+        #[allow(unreachable_patterns)]
+        _ => {
+            let (name, feature) = compression.name_and_feature();
+            Err(PyValueError::new_err(format!(
+                "Data is {name} compressed, but moss_decoder was built without the `{feature}` feature"
+            )))
+        }
+    }
+}
+
+/// Opens a raw data file, compressed files are decompressed on the fly, see [decompress].
+pub fn open_file(path: impl AsRef<Path>) -> PyResult<Box<dyn Read>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+    };
+    decompress(std::io::BufReader::new(file))
+}
+
+/// Reads an entire raw data file into memory, compressed files are decompressed, see [decompress].
+pub fn read_file(path: impl AsRef<Path>) -> PyResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    _ = open_file(path)?
+        .read_to_end(&mut bytes)
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    fn decompress_to_vec(compressed: &[u8]) -> PyResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        _ = decompress(compressed)?.read_to_end(&mut bytes).unwrap();
+        Ok(bytes)
+    }
+
+    #[test]
+    fn test_detect_compression() {
+        assert_eq!(Compression::detect(&fake_event_simple()), Compression::None);
+        assert_eq!(Compression::detect(&[0xFA, 0xFA, 0xD1]), Compression::None);
+        assert_eq!(Compression::detect(&[]), Compression::None);
+        assert_eq!(Compression::detect(&[0x1F, 0x8B, 0x08]), Compression::Gzip);
+        assert_eq!(
+            Compression::detect(&[0x28, 0xB5, 0x2F, 0xFD, 0x00]),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::detect(&[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00, 0x00]),
+            Compression::Xz
+        );
+    }
+
+    #[test]
+    fn test_uncompressed_passthrough() {
+        let bytes = fake_event_simple();
+        assert_eq!(decompress_to_vec(&bytes).unwrap(), bytes);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        use std::io::Write;
+        let bytes = fake_event_simple();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&bytes).unwrap();
+        assert_eq!(
+            decompress_to_vec(&encoder.finish().unwrap()).unwrap(),
+            bytes
+        );
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn test_gzip_without_feature() {
+        pyo3::prepare_freethreaded_python();
+        assert!(decompress_to_vec(&[0x1F, 0x8B, 0x08, 0x00]).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let bytes = fake_event_simple();
+        let compressed = zstd::encode_all(bytes.as_slice(), 0).unwrap();
+        assert_eq!(decompress_to_vec(&compressed).unwrap(), bytes);
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn test_xz_and_lzma() {
+        let bytes = fake_event_simple();
+        let mut compressed = Vec::new();
+        _ = xz2::bufread::XzEncoder::new(bytes.as_slice(), 6)
+            .read_to_end(&mut compressed)
+            .unwrap();
+        assert_eq!(decompress_to_vec(&compressed).unwrap(), bytes);
+
+        let lzma_encoder = xz2::stream::Stream::new_lzma_encoder(
+            &xz2::stream::LzmaOptions::new_preset(6).unwrap(),
+        )
+        .unwrap();
+        let mut compressed = Vec::new();
+        _ = xz2::bufread::XzEncoder::new_stream(bytes.as_slice(), lzma_encoder)
+            .read_to_end(&mut compressed)
+            .unwrap();
+        assert_eq!(Compression::detect(&compressed), Compression::Lzma);
+        assert_eq!(decompress_to_vec(&compressed).unwrap(), bytes);
+    }
+}
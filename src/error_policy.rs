@@ -0,0 +1,60 @@
+//! How the functions that decode a file or another [std::io::Read] source handle decoding and I/O errors, see [source](crate::source).
+use pyo3::prelude::*;
+
+#[pyclass]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+/// What the file decoding functions do when an event fails to decode or reading the data fails.
+pub enum ErrorPolicy {
+    /// Raise the first error, the events decoded before it are discarded.
+    #[default]
+    Strict,
+    /// Skip an event that fails to decode and resume at the next Unit Frame Header, I/O errors are still raised.
+    /// The skipped events are logged at warn level, see [set_log_level](crate::set_log_level).
+    SkipBadEvents,
+    /// Stop at the first error and raise it with the result decoded before the error as the `partial` attribute of the exception.
+    StopAndReturnPartial,
+}
+
+impl ErrorPolicy {
+    /// Attaches the result decoded before the error as the `partial` attribute of the exception if the policy is [ErrorPolicy::StopAndReturnPartial].
+    pub(crate) fn with_partial<T: IntoPy<PyObject>>(
+        self,
+        err: PyErr,
+        partial: impl FnOnce() -> T,
+    ) -> PyErr {
+        if self != Self::StopAndReturnPartial {
+            return err;
+        }
+        Python::with_gil(
+            |py| match err.value(py).setattr("partial", partial().into_py(py)) {
+                Ok(()) => err,
+                Err(e) => e,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::exceptions::PyAssertionError;
+
+    #[test]
+    fn test_with_partial() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let err = ErrorPolicy::StopAndReturnPartial
+                .with_partial(PyAssertionError::new_err("failed"), || vec![1, 2]);
+            let partial: Vec<u8> = err.value(py).getattr("partial").unwrap().extract().unwrap();
+            assert_eq!(partial, vec![1, 2]);
+            assert!(err.is_instance_of::<PyAssertionError>(py));
+
+            for policy in [ErrorPolicy::Strict, ErrorPolicy::SkipBadEvents] {
+                let err = policy.with_partial(PyAssertionError::new_err("failed"), || -> u64 {
+                    unreachable!("Only evaluated for StopAndReturnPartial")
+                });
+                assert!(!err.value(py).hasattr("partial").unwrap());
+            }
+        });
+    }
+}
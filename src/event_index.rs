@@ -0,0 +1,444 @@
+//! An index of the events in a raw data file for random access, persisted in a sidecar `.idx` file next to the raw file.
+//!
+//! The index is built by decoding the file once and records the byte offset of each Unit Frame Header, the length of the event, its unit ID and its hit count.
+//! Compressed files can't be indexed as the decompressed stream can't be seeked into.
+use pyo3::exceptions::{PyIOError, PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::decompress::Compression;
+use crate::source::EventDecoder;
+use crate::{
+    rust_only, source, DecodeError, DecodeFilter, ErrorPolicy, List_MossPackets, MossPacket,
+    PixelMask,
+};
+
+/// Magic bytes at the start of an index file.
+const MAGIC: [u8; 8] = *b"MOSSIDX\0";
+/// Version of the index file format, incremented on incompatible changes.
+const VERSION: u32 = 2;
+/// Number of bytes at the start and at the end of the indexed file included in [FileFingerprint::edge_hash].
+const EDGE_LEN: u64 = 4096;
+
+/// Identifies the indexed file without reading all of it, an index with a different fingerprint than the file is outdated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct FileFingerprint {
+    len: u64,
+    /// Modification time in nanoseconds since the Unix epoch, 0 if the platform doesn't provide it.
+    mtime_ns: u64,
+    /// FNV-1a hash of the first and last [EDGE_LEN] bytes, catches rewrites that keep the length and modification time.
+    edge_hash: u64,
+}
+
+impl FileFingerprint {
+    /// Computes the fingerprint of `file`, which is rewound afterwards.
+    fn of(file: &mut File) -> std::io::Result<Self> {
+        let metadata = file.metadata()?;
+        let len = metadata.len();
+        let mtime_ns = metadata
+            .modified()
+            .ok()
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_nanos() as u64);
+
+        let mut edges = Vec::with_capacity(2 * EDGE_LEN as usize);
+        file.rewind()?;
+        _ = Read::by_ref(file).take(EDGE_LEN).read_to_end(&mut edges)?;
+        _ = file.seek(SeekFrom::Start(len.saturating_sub(EDGE_LEN)))?;
+        _ = Read::by_ref(file).take(EDGE_LEN).read_to_end(&mut edges)?;
+        file.rewind()?;
+
+        Ok(Self {
+            len,
+            mtime_ns,
+            edge_hash: fnv1a(&edges),
+        })
+    }
+}
+
+/// The 64-bit FNV-1a hash, unlike [std::hash::DefaultHasher] it is stable across Rust versions so it can be stored in files.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[pyclass]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// The byte offset, length, unit ID and hit count of every event in a raw data file.
+pub struct EventIndex {
+    fingerprint: FileFingerprint,
+    offsets: Vec<u64>,
+    lengths: Vec<u32>,
+    unit_ids: Vec<u8>,
+    hit_counts: Vec<u32>,
+}
+
+#[pymethods]
+impl EventIndex {
+    /// Builds the index by decoding the raw data file at `path` once, an incomplete event at the end of the file is not indexed.
+    #[staticmethod]
+    pub fn build(path: PathBuf) -> PyResult<Self> {
+        let mut file = open_uncompressed(path)?;
+        let mut index = Self {
+            fingerprint: FileFingerprint::of(&mut file).map_err(io_error)?,
+            ..Default::default()
+        };
+        let mut decoder = EventDecoder::new(ErrorPolicy::Strict);
+        _ = source::read_chunks(file, None, |bytes_to_decode| {
+            index.extend_from(&mut decoder, bytes_to_decode)
+        })?;
+        Ok(index)
+    }
+
+    /// Loads an index file written by [EventIndex::save].
+    #[staticmethod]
+    pub fn load(path: PathBuf) -> PyResult<Self> {
+        let mut reader = BufReader::new(File::open(path).map_err(io_error)?);
+        Self::read_from(&mut reader).map_err(io_error)
+    }
+
+    /// Writes the index to `path`.
+    pub fn save(&self, path: PathBuf) -> PyResult<()> {
+        let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+        self.write_to(&mut writer)
+            .and_then(|()| writer.flush())
+            .map_err(io_error)
+    }
+
+    /// The path of the sidecar index file of the raw data file at `path`, i.e. `path` with `.idx` appended.
+    #[staticmethod]
+    pub fn sidecar_path(path: PathBuf) -> PathBuf {
+        let mut sidecar = path.into_os_string();
+        sidecar.push(".idx");
+        sidecar.into()
+    }
+
+    /// The byte offset of the Unit Frame Header of each event.
+    #[getter(offsets)]
+    fn py_offsets(&self) -> Vec<u64> {
+        self.offsets.clone()
+    }
+
+    /// The unit ID of each event.
+    #[getter(unit_ids)]
+    fn py_unit_ids(&self) -> Vec<u8> {
+        self.unit_ids.clone()
+    }
+
+    /// The number of hits in each event.
+    #[getter(hit_counts)]
+    fn py_hit_counts(&self) -> Vec<u32> {
+        self.hit_counts.clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("EventIndex({} events)", self.len())
+    }
+}
+
+impl EventIndex {
+    /// Number of indexed events.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns `true` if no events are indexed.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The byte offset of the Unit Frame Header of each event.
+    pub fn offsets(&self) -> &[u64] {
+        &self.offsets
+    }
+
+    /// The unit ID of each event.
+    pub fn unit_ids(&self) -> &[u8] {
+        &self.unit_ids
+    }
+
+    /// The number of hits in each event.
+    pub fn hit_counts(&self) -> &[u32] {
+        &self.hit_counts
+    }
+
+    /// Loads the sidecar index of the raw data file at `path` if it exists and is up to date,
+    /// i.e. it was built from a file with the same length, modification time and first and last 4 KiB.
+    pub fn load_sidecar(path: impl AsRef<Path>) -> Option<Self> {
+        let fingerprint = FileFingerprint::of(&mut File::open(path.as_ref()).ok()?).ok()?;
+        Self::load(Self::sidecar_path(path.as_ref().to_path_buf()))
+            .ok()
+            .filter(|index| index.fingerprint == fingerprint)
+    }
+
+    /// Reads event number `event_idx` (starting from 0) directly from the indexed `file`.
+    pub fn read_event(&self, file: &mut File, event_idx: usize) -> PyResult<MossPacket> {
+        let (Some(offset), Some(len)) = (self.offsets.get(event_idx), self.lengths.get(event_idx))
+        else {
+            return Err(PyIndexError::new_err(format!(
+                "Event {event_idx} out of range for an index of {} events",
+                self.len()
+            )));
+        };
+        let mut bytes = vec![0; *len as usize];
+        _ = file.seek(SeekFrom::Start(*offset)).map_err(io_error)?;
+        file.read_exact(&mut bytes).map_err(io_error)?;
+        match rust_only::extract_packet_from_buf(&bytes, None) {
+            Ok((moss_packet, _, _)) => Ok(moss_packet),
+            Err(e) => Err(DecodeError::parse(event_idx + 1, &e).into()),
+        }
+    }
+
+    /// Indexes the complete events in `bytes` decoded by `decoder`, returns the number of bytes consumed like the `decode_chunk` function of [read_chunks](source::read_chunks).
+    fn extend_from(&mut self, decoder: &mut EventDecoder, bytes: &[u8]) -> PyResult<Option<usize>> {
+        decoder.decode_chunk(bytes, |decoded| {
+            self.offsets.push(decoded.offset);
+            self.lengths.push(decoded.bytes.len() as u32);
+            self.unit_ids.push(decoded.packet.unit_id);
+            self.hit_counts.push(decoded.packet.hits.len() as u32);
+            Ok(true)
+        })
+    }
+
+    /// Writes the index in the sidecar file format, see [EventIndex::save].
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&self.fingerprint.len.to_le_bytes())?;
+        writer.write_all(&self.fingerprint.mtime_ns.to_le_bytes())?;
+        writer.write_all(&self.fingerprint.edge_hash.to_le_bytes())?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+        for offset in &self.offsets {
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for len in &self.lengths {
+            writer.write_all(&len.to_le_bytes())?;
+        }
+        writer.write_all(&self.unit_ids)?;
+        for hit_count in &self.hit_counts {
+            writer.write_all(&hit_count.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads an index written by [EventIndex::write_to], returns an `InvalidData` error for data that is not an index in the current format.
+    pub fn read_from(reader: &mut impl Read) -> std::io::Result<Self> {
+        fn read_array<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+            let mut buf = [0; N];
+            reader.read_exact(&mut buf)?;
+            Ok(buf)
+        }
+        let invalid_data = |msg| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+        if read_array::<8>(reader)? != MAGIC {
+            return Err(invalid_data("Not a MOSS event index file"));
+        }
+        if u32::from_le_bytes(read_array(reader)?) != VERSION {
+            return Err(invalid_data("Unsupported MOSS event index version"));
+        }
+        let fingerprint = FileFingerprint {
+            len: u64::from_le_bytes(read_array(reader)?),
+            mtime_ns: u64::from_le_bytes(read_array(reader)?),
+            edge_hash: u64::from_le_bytes(read_array(reader)?),
+        };
+        let event_cnt = u64::from_le_bytes(read_array(reader)?) as usize;
+
+        let mut index = Self {
+            fingerprint,
+            ..Default::default()
+        };
+        for _ in 0..event_cnt {
+            index.offsets.push(u64::from_le_bytes(read_array(reader)?));
+        }
+        for _ in 0..event_cnt {
+            index.lengths.push(u32::from_le_bytes(read_array(reader)?));
+        }
+        // Read through `take` so a corrupt event count can't cause a huge allocation
+        if reader
+            .take(event_cnt as u64)
+            .read_to_end(&mut index.unit_ids)?
+            != event_cnt
+        {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        for _ in 0..event_cnt {
+            index
+                .hit_counts
+                .push(u32::from_le_bytes(read_array(reader)?));
+        }
+        Ok(index)
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)] // Passed to `map_err`
+fn io_error(e: std::io::Error) -> PyErr {
+    PyIOError::new_err(e.to_string())
+}
+
+/// Opens a raw data file, returns an error if it is compressed.
+fn open_uncompressed(path: impl AsRef<Path>) -> PyResult<File> {
+    let mut file = File::open(path)
+        .map_err(|e| pyo3::exceptions::PyFileNotFoundError::new_err(e.to_string()))?;
+    let mut magic = Vec::with_capacity(8);
+    _ = Read::by_ref(&mut file)
+        .take(8)
+        .read_to_end(&mut magic)
+        .map_err(io_error)?;
+    if Compression::detect(&magic) != Compression::None {
+        return Err(PyValueError::new_err(
+            "Compressed files can't be indexed, decompress the file first",
+        ));
+    }
+    file.rewind().map_err(io_error)?;
+    Ok(file)
+}
+
+/// Returns the index of the raw data file at `path`, loaded from its sidecar `.idx` file if it is up to date.
+///
+/// Otherwise the index is built and written to the sidecar file, if the sidecar can't be written, e.g. in a read-only directory, the index is still returned.
+#[pyfunction]
+pub fn index_file(path: PathBuf) -> PyResult<EventIndex> {
+    if let Some(index) = EventIndex::load_sidecar(&path) {
+        return Ok(index);
+    }
+    let index = EventIndex::build(path.clone())?;
+    _ = index.save(EventIndex::sidecar_path(path));
+    Ok(index)
+}
+
+/// Reads the events with the given `indices` (starting from 0) from the raw data file at `path`, seeking directly to each event.
+///
+/// The file is indexed with [index_file] first, which is only slow the first time.
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter], the dropped events leave no gap in the returned list.
+#[pyfunction]
+pub fn read_events(
+    path: PathBuf,
+    indices: Vec<usize>,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+) -> PyResult<List_MossPackets> {
+    let index = index_file(path.clone())?;
+    let mut file = File::open(path).map_err(io_error)?;
+    let mut moss_packets = Vec::with_capacity(indices.len());
+    for event_idx in indices {
+        let moss_packet = index.read_event(&mut file, event_idx)?;
+        rust_only::push_packet(&mut moss_packets, moss_packet, mask, filter);
+    }
+    Ok(moss_packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_index_split_across_chunks() {
+        let event = fake_event_simple();
+        let bytes = event.repeat(3);
+        let mut index = EventIndex::default();
+        let mut decoder = EventDecoder::new(ErrorPolicy::Strict);
+        // Split in the middle of the second event
+        let split = event.len() + 5;
+        let consumed = index.extend_from(&mut decoder, &bytes[..split]).unwrap();
+        assert_eq!(consumed, Some(event.len()));
+        assert_eq!(
+            index
+                .extend_from(&mut decoder, &bytes[event.len()..])
+                .unwrap(),
+            Some(bytes.len() - event.len())
+        );
+
+        let header_idx = crate::scan::find_header(&event).unwrap() as u64;
+        let event_len = event.len() as u64;
+        assert_eq!(
+            index.offsets(),
+            [
+                header_idx,
+                event_len + header_idx,
+                2 * event_len + header_idx
+            ]
+        );
+        assert_eq!(index.unit_ids(), [1, 1, 1]);
+        assert_eq!(index.hit_counts(), [4, 4, 4]);
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        pyo3::prepare_freethreaded_python();
+        let mut index = EventIndex {
+            fingerprint: FileFingerprint {
+                len: 57,
+                mtime_ns: 1_700_000_000_000_000_000,
+                edge_hash: fnv1a(b"MOSS"),
+            },
+            ..Default::default()
+        };
+        _ = index
+            .extend_from(
+                &mut EventDecoder::new(ErrorPolicy::Strict),
+                &fake_event_simple().repeat(3),
+            )
+            .unwrap();
+
+        let mut bytes = Vec::new();
+        index.write_to(&mut bytes).unwrap();
+        assert_eq!(EventIndex::read_from(&mut bytes.as_slice()).unwrap(), index);
+
+        bytes[0] = b'X';
+        assert!(EventIndex::read_from(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_sidecar_outdated_with_same_length() {
+        pyo3::prepare_freethreaded_python();
+        let path = std::env::temp_dir().join("moss_decoder_test_sidecar_same_length.raw");
+        let sidecar = EventIndex::sidecar_path(path.clone());
+        let bytes = fake_event_simple().repeat(3);
+        std::fs::write(&path, &bytes).unwrap();
+        let index = index_file(path.clone()).unwrap();
+        assert_eq!(EventIndex::load_sidecar(&path), Some(index));
+        let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        // Same length and modification time, different content
+        let mut rewritten = bytes.clone();
+        rewritten[2] ^= 0x01;
+        std::fs::write(&path, &rewritten).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert_eq!(EventIndex::load_sidecar(&path), None);
+
+        // Same content, different modification time
+        std::fs::write(&path, &bytes).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(EventIndex::load_sidecar(&path), None);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&sidecar).unwrap();
+    }
+}
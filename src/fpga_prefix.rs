@@ -0,0 +1,187 @@
+//! Optional framing layer where the readout FPGA prepends a fixed-length prefix to each event.
+//!
+//! The prefix is placed before the delimiter bytes preceding the Unit Frame Header, i.e. an event is framed as `<prefix> 0xFA.. <header> .. <trailer>`.
+//! The prefix can contain a timestamp and a trigger ID at configurable positions.
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Layout of the FPGA prefix preceding each event.
+///
+/// The fields are only set by [FpgaPrefix::new], which checks that they fit in the prefix.
+pub struct FpgaPrefix {
+    /// Total length of the prefix in bytes.
+    length: usize,
+    /// Byte offset of the timestamp in the prefix.
+    timestamp_offset: usize,
+    /// Length of the timestamp in bytes (0-8), 0 if the prefix has no timestamp.
+    timestamp_len: usize,
+    /// Byte offset of the trigger ID in the prefix.
+    trigger_id_offset: usize,
+    /// Length of the trigger ID in bytes (0-4), 0 if the prefix has no trigger ID.
+    trigger_id_len: usize,
+    /// Byte order of the timestamp and trigger ID, little-endian if `false`.
+    big_endian: bool,
+}
+
+#[pymethods]
+impl FpgaPrefix {
+    #[new]
+    #[pyo3(signature = (length, timestamp_offset=0, timestamp_len=0, trigger_id_offset=0, trigger_id_len=0, big_endian=false))]
+    /// Creates a new prefix layout, returns an error if the fields don't fit in the prefix.
+    pub fn new(
+        length: usize,
+        timestamp_offset: usize,
+        timestamp_len: usize,
+        trigger_id_offset: usize,
+        trigger_id_len: usize,
+        big_endian: bool,
+    ) -> PyResult<Self> {
+        // The decoder adds the prefix length to byte indices, so a prefix plus an event must be addressable
+        if length.checked_add(crate::MINIMUM_EVENT_SIZE + 1).is_none() {
+            return Err(PyValueError::new_err(
+                "length is too large to fit a prefix and an event",
+            ));
+        }
+        if timestamp_len > 8 {
+            return Err(PyValueError::new_err(
+                "timestamp_len must be at most 8 bytes",
+            ));
+        }
+        if trigger_id_len > 4 {
+            return Err(PyValueError::new_err(
+                "trigger_id_len must be at most 4 bytes",
+            ));
+        }
+        // Huge offsets from Python must not wrap around and pass the check
+        if timestamp_len > 0
+            && timestamp_offset
+                .checked_add(timestamp_len)
+                .is_none_or(|end| end > length)
+        {
+            return Err(PyValueError::new_err(
+                "timestamp does not fit in the prefix",
+            ));
+        }
+        if trigger_id_len > 0
+            && trigger_id_offset
+                .checked_add(trigger_id_len)
+                .is_none_or(|end| end > length)
+        {
+            return Err(PyValueError::new_err(
+                "trigger ID does not fit in the prefix",
+            ));
+        }
+        Ok(Self {
+            length,
+            timestamp_offset,
+            timestamp_len,
+            trigger_id_offset,
+            trigger_id_len,
+            big_endian,
+        })
+    }
+
+    /// Total length of the prefix in bytes.
+    #[getter]
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Byte offset of the timestamp in the prefix.
+    #[getter]
+    pub fn timestamp_offset(&self) -> usize {
+        self.timestamp_offset
+    }
+
+    /// Length of the timestamp in bytes, 0 if the prefix has no timestamp.
+    #[getter]
+    pub fn timestamp_len(&self) -> usize {
+        self.timestamp_len
+    }
+
+    /// Byte offset of the trigger ID in the prefix.
+    #[getter]
+    pub fn trigger_id_offset(&self) -> usize {
+        self.trigger_id_offset
+    }
+
+    /// Length of the trigger ID in bytes, 0 if the prefix has no trigger ID.
+    #[getter]
+    pub fn trigger_id_len(&self) -> usize {
+        self.trigger_id_len
+    }
+
+    /// Whether the timestamp and trigger ID are big-endian.
+    #[getter]
+    pub fn big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl FpgaPrefix {
+    /// Parses the timestamp and trigger ID from the prefix bytes, a field is `None` if it is not part of the layout.
+    ///
+    /// `prefix` must be at least [FpgaPrefix::length] bytes.
+    #[inline]
+    pub(crate) fn parse(&self, prefix: &[u8]) -> (Option<u64>, Option<u32>) {
+        let timestamp = (self.timestamp_len > 0)
+            .then(|| self.read_uint(&prefix[self.timestamp_offset..][..self.timestamp_len]));
+        let trigger_id = (self.trigger_id_len > 0).then(|| {
+            self.read_uint(&prefix[self.trigger_id_offset..][..self.trigger_id_len]) as u32
+        });
+        (timestamp, trigger_id)
+    }
+
+    #[inline]
+    fn read_uint(&self, bytes: &[u8]) -> u64 {
+        if self.big_endian {
+            bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u64)
+        } else {
+            bytes.iter().rev().fold(0, |acc, b| (acc << 8) | *b as u64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_prefix() {
+        let prefix = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0xAA, 0xBB];
+
+        let little_endian = FpgaPrefix::new(8, 0, 6, 6, 2, false).unwrap();
+        assert_eq!(
+            little_endian.parse(&prefix),
+            (Some(0x06_05_04_03_02_01), Some(0xBBAA))
+        );
+
+        let big_endian = FpgaPrefix::new(8, 0, 6, 6, 2, true).unwrap();
+        assert_eq!(
+            big_endian.parse(&prefix),
+            (Some(0x01_02_03_04_05_06), Some(0xAABB))
+        );
+
+        let no_fields = FpgaPrefix::new(8, 0, 0, 0, 0, false).unwrap();
+        assert_eq!(no_fields.parse(&prefix), (None, None));
+    }
+
+    #[test]
+    fn test_invalid_prefix_layout() {
+        pyo3::prepare_freethreaded_python();
+        assert!(FpgaPrefix::new(4, 0, 9, 0, 0, false).is_err());
+        assert!(FpgaPrefix::new(4, 2, 4, 0, 0, false).is_err());
+        assert!(FpgaPrefix::new(4, 0, 0, 0, 5, false).is_err());
+        assert!(FpgaPrefix::new(4, 0, 0, 3, 2, false).is_err());
+        assert!(FpgaPrefix::new(4, usize::MAX, 8, 0, 0, false).is_err());
+        assert!(FpgaPrefix::new(4, 0, 0, usize::MAX, 4, false).is_err());
+        assert!(FpgaPrefix::new(usize::MAX, 0, 0, 0, 0, false).is_err());
+    }
+}
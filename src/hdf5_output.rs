@@ -0,0 +1,251 @@
+//! Output of decoded runs to HDF5, for analysis with h5py, ROOT or other HDF5 tools. Experimental, the file layout may still change.
+//!
+//! The file has two groups of one-dimensional datasets, all chunked and compressed:
+//! - `/hits` with `region`, `row` and `column`, one entry per hit in event order.
+//! - `/events` with `event`, `unit_id`, `hit_offset`, `hit_count` and `byte_offset`, one entry per written event.
+//!   The hits of an event are at `hit_offset..hit_offset + hit_count` in the `/hits` datasets,
+//!   `event` is the index of the event in the raw data and `byte_offset` the byte offset of its Unit Frame Header.
+//!
+//! The datasets are resizable and are appended to in chunks as the raw data is decoded, so runs larger than memory can be written.
+use hdf5::{Dataset, Group, H5Type};
+use ndarray::ArrayView1;
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::{decompress, source, DecodeFilter, DecodeOptions, ErrorPolicy, MossPacket, PixelMask};
+
+/// Number of entries in a chunk of a dataset, entries are buffered and appended one chunk at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Deflate compression level of the datasets.
+const COMPRESSION_LEVEL: u8 = 4;
+
+/// A resizable dataset and the entries not yet appended to it.
+#[derive(Debug)]
+struct Column<T> {
+    dataset: Dataset,
+    buffer: Vec<T>,
+}
+
+impl<T: H5Type> Column<T> {
+    fn create(group: &Group, name: &str) -> hdf5::Result<Self> {
+        let dataset = group
+            .new_dataset::<T>()
+            .chunk(CHUNK_SIZE)
+            .shuffle()
+            .deflate(COMPRESSION_LEVEL)
+            .shape(0..)
+            .create(name)?;
+        Ok(Self {
+            dataset,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        })
+    }
+
+    fn push(&mut self, value: T) {
+        self.buffer.push(value);
+    }
+
+    fn is_full(&self) -> bool {
+        self.buffer.len() >= CHUNK_SIZE
+    }
+
+    /// Appends the buffered entries to the dataset.
+    fn flush(&mut self) -> hdf5::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let start = self.dataset.size();
+        let end = start + self.buffer.len();
+        self.dataset.resize(end)?;
+        self.dataset
+            .write_slice(ArrayView1::from(self.buffer.as_slice()), start..end)?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+/// Writes packets to an HDF5 file, see the [module documentation](self) for the layout.
+#[derive(Debug)]
+pub struct Hdf5Writer {
+    file: hdf5::File,
+    region: Column<u8>,
+    row: Column<u16>,
+    column: Column<u16>,
+    event: Column<u64>,
+    unit_id: Column<u8>,
+    hit_offset: Column<u64>,
+    hit_count: Column<u32>,
+    byte_offset: Column<u64>,
+    hit_cnt: u64,
+}
+
+impl Hdf5Writer {
+    /// Creates the file at `path` with empty datasets, an existing file is truncated.
+    pub fn create(path: impl AsRef<Path>) -> hdf5::Result<Self> {
+        let file = hdf5::File::create(path)?;
+        let hits = file.create_group("hits")?;
+        let events = file.create_group("events")?;
+        Ok(Self {
+            region: Column::create(&hits, "region")?,
+            row: Column::create(&hits, "row")?,
+            column: Column::create(&hits, "column")?,
+            event: Column::create(&events, "event")?,
+            unit_id: Column::create(&events, "unit_id")?,
+            hit_offset: Column::create(&events, "hit_offset")?,
+            hit_count: Column::create(&events, "hit_count")?,
+            byte_offset: Column::create(&events, "byte_offset")?,
+            hit_cnt: 0,
+            file,
+        })
+    }
+
+    /// Buffers the packet as event number `event` whose Unit Frame Header is at `byte_offset`, full chunks are appended to the file.
+    pub fn write_packet(
+        &mut self,
+        event: u64,
+        byte_offset: u64,
+        packet: &MossPacket,
+    ) -> hdf5::Result<()> {
+        self.event.push(event);
+        self.unit_id.push(packet.unit_id);
+        self.hit_offset.push(self.hit_cnt);
+        self.hit_count.push(packet.hits.len() as u32);
+        self.byte_offset.push(byte_offset);
+        for hit in &packet.hits {
+            self.region.push(hit.region);
+            self.row.push(hit.row);
+            self.column.push(hit.column);
+            if self.region.is_full() {
+                self.flush_hits()?;
+            }
+        }
+        self.hit_cnt += packet.hits.len() as u64;
+
+        if self.event.is_full() {
+            self.flush_events()?;
+        }
+        Ok(())
+    }
+
+    /// Appends the remaining buffered entries and flushes the file, returns the number of written events and hits.
+    pub fn finish(mut self) -> hdf5::Result<(usize, u64)> {
+        self.flush_hits()?;
+        self.flush_events()?;
+        self.file.flush()?;
+        Ok((self.event.dataset.size(), self.hit_cnt))
+    }
+
+    fn flush_hits(&mut self) -> hdf5::Result<()> {
+        self.region.flush()?;
+        self.row.flush()?;
+        self.column.flush()
+    }
+
+    fn flush_events(&mut self) -> hdf5::Result<()> {
+        self.event.flush()?;
+        self.unit_id.flush()?;
+        self.hit_offset.flush()?;
+        self.hit_count.flush()?;
+        self.byte_offset.flush()
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)] // Passed to `map_err`
+fn to_py_err(e: hdf5::Error) -> PyErr {
+    PyIOError::new_err(e.to_string())
+}
+
+/// Decodes the raw data file at `raw_path` and writes its events and hits to an HDF5 file at `h5_path` one chunk at a time, so files larger than memory can be written.
+///
+/// Optionally drops hits on pixels in the given [PixelMask] and packets rejected by the [DecodeFilter], the event numbers of the other events are unchanged.
+/// Decoding errors are handled according to the [ErrorPolicy], the events written before an error are kept in the HDF5 file.
+/// Returns the number of decoded events.
+#[pyfunction]
+#[pyo3(signature = (raw_path, h5_path, mask=None, filter=None, error_policy=None))]
+pub fn decode_file_to_hdf5(
+    raw_path: PathBuf,
+    h5_path: PathBuf,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+) -> PyResult<u64> {
+    let raw_file = decompress::open_file(raw_path)?;
+    let mut writer = Hdf5Writer::create(h5_path).map_err(to_py_err)?;
+    let events = source::for_each_packet(
+        raw_file,
+        DecodeOptions::new(mask, filter, error_policy, None),
+        |event, offset, packet| {
+            writer
+                .write_packet(event, offset, packet)
+                .map_err(to_py_err)
+        },
+    );
+    _ = writer.finish().map_err(to_py_err)?;
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MossHit;
+    use pretty_assertions::assert_eq;
+
+    fn read<T: H5Type>(file: &hdf5::File, name: &str) -> Vec<T> {
+        file.dataset(name).unwrap().read_raw().unwrap()
+    }
+
+    #[test]
+    fn test_write_packets() {
+        let packets = [
+            MossPacket {
+                unit_id: 1,
+                hits: vec![
+                    MossHit {
+                        region: 0,
+                        row: 2,
+                        column: 8,
+                    },
+                    MossHit {
+                        region: 3,
+                        row: 301,
+                        column: 433,
+                    },
+                ],
+            },
+            MossPacket {
+                unit_id: 9,
+                ..Default::default()
+            },
+            MossPacket {
+                unit_id: 4,
+                hits: vec![MossHit {
+                    region: 2,
+                    row: 5,
+                    column: 6,
+                }],
+            },
+        ];
+
+        let path = std::env::temp_dir().join("moss_decoder_test_write_packets.h5");
+        let mut writer = Hdf5Writer::create(&path).unwrap();
+        for (event, packet) in packets.iter().enumerate() {
+            writer
+                .write_packet(event as u64 + 10, event as u64 * 100, packet)
+                .unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), (3, 3));
+
+        let file = hdf5::File::open(&path).unwrap();
+        assert_eq!(read::<u8>(&file, "hits/region"), vec![0, 3, 2]);
+        assert_eq!(read::<u16>(&file, "hits/row"), vec![2, 301, 5]);
+        assert_eq!(read::<u16>(&file, "hits/column"), vec![8, 433, 6]);
+        assert_eq!(read::<u64>(&file, "events/event"), vec![10, 11, 12]);
+        assert_eq!(read::<u8>(&file, "events/unit_id"), vec![1, 9, 4]);
+        assert_eq!(read::<u64>(&file, "events/hit_offset"), vec![0, 2, 2]);
+        assert_eq!(read::<u32>(&file, "events/hit_count"), vec![2, 0, 1]);
+        assert_eq!(read::<u64>(&file, "events/byte_offset"), vec![0, 100, 200]);
+        drop(file);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+//! Selection of the hit decoder used by all decoding functions.
+//!
+//! The default is the `sm` crate FSM, or the table-driven decoder if the crate is built with the `table-decoder` feature.
+//! Both decoders produce the same hits and errors, the selection can be changed at runtime with [set_hit_decoder].
+use pyo3::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::parse_error::ParseError;
+use crate::{decode_hits_fsm, decode_hits_table, MossHit};
+
+static USE_TABLE_DECODER: AtomicBool = AtomicBool::new(cfg!(feature = "table-decoder"));
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The implementation used to decode the hits of an event.
+pub enum HitDecoder {
+    /// The state machine generated by the `sm` crate.
+    Fsm,
+    /// A state machine driven by a byte class table and a transition table.
+    Table,
+}
+
+/// Sets the hit decoder used by all decoding functions in the process.
+#[pyfunction]
+pub fn set_hit_decoder(decoder: HitDecoder) {
+    USE_TABLE_DECODER.store(decoder == HitDecoder::Table, Ordering::Relaxed);
+}
+
+/// Returns the hit decoder used by all decoding functions.
+#[pyfunction]
+pub fn get_hit_decoder() -> HitDecoder {
+    if USE_TABLE_DECODER.load(Ordering::Relaxed) {
+        HitDecoder::Table
+    } else {
+        HitDecoder::Fsm
+    }
+}
+
+/// Decodes the hits of an event with the selected [HitDecoder] and collects them, see [visit_hits].
+#[inline]
+pub(crate) fn extract_hits<'a>(
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
+) -> Result<Vec<MossHit>, ParseError> {
+    match get_hit_decoder() {
+        HitDecoder::Fsm => decode_hits_fsm::extract_hits(bytes),
+        HitDecoder::Table => decode_hits_table::extract_hits(bytes),
+    }
+}
+
+/// Decodes the hits of an event with the selected [HitDecoder], `bytes` should be advanced to the position after the Unit Frame Header.
+#[inline]
+pub(crate) fn visit_hits<'a>(
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
+    on_hit: impl FnMut(MossHit),
+) -> Result<(), ParseError> {
+    match get_hit_decoder() {
+        HitDecoder::Fsm => decode_hits_fsm::visit_hits(bytes, on_hit),
+        HitDecoder::Table => decode_hits_table::visit_hits(bytes, on_hit),
+    }
+}
@@ -0,0 +1,239 @@
+//! Validation of the order and uniqueness of the hits in MOSS events.
+//!
+//! The MOSS readout emits region headers in ascending order, and within a region hits are read out in ascending row then column order.
+//! A pixel can only be read out once per frame, so any repeated pixel within an event indicates a problem in the peripheral logic.
+use pyo3::prelude::*;
+use std::collections::HashSet;
+use std::fmt::{write, Display};
+
+use crate::{moss_protocol::MossWord, MossHit};
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The type of issue found by the hit validation.
+pub enum HitDiagnosticKind {
+    /// The same pixel was read out more than once within an event.
+    DuplicatePixel,
+    /// A region header was seen after a region header with a higher or equal region ID.
+    RegionHeaderOutOfOrder,
+    /// A hit was read out after a hit with a higher row/column in the same region.
+    HitOutOfOrder,
+}
+
+#[pyclass(get_all)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A structured diagnostic describing an issue found by the hit validation.
+pub struct HitDiagnostic {
+    /// The type of issue.
+    pub kind: HitDiagnosticKind,
+    /// The index of the event in the byte stream, starting from 0.
+    pub packet_idx: usize,
+    /// The index of the byte that completed the offending word (the region header or the last data word of the hit).
+    pub index: usize,
+    /// The unit ID of the event.
+    pub unit_id: u8,
+    /// The region ID of the region header or hit.
+    pub region: u8,
+    /// The offending hit, `None` for region header issues.
+    pub hit: Option<MossHit>,
+}
+
+#[pymethods]
+impl HitDiagnostic {
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+
+    /// Returns a string representation of the [HitDiagnostic] instance.
+    pub fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for HitDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self.kind {
+            HitDiagnosticKind::DuplicatePixel => "Duplicate pixel",
+            HitDiagnosticKind::RegionHeaderOutOfOrder => "Region header out of order",
+            HitDiagnosticKind::HitOutOfOrder => "Hit out of readout order",
+        };
+        write(
+            f,
+            format_args!(
+                "{description} at index={i} in packet #{packet} unit {unit_id}, region {region}",
+                i = self.index,
+                packet = self.packet_idx + 1,
+                unit_id = self.unit_id,
+                region = self.region,
+            ),
+        )?;
+        if let Some(hit) = self.hit {
+            write(f, format_args!(" ({hit})"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates the hits of all events in the given bytes and returns a diagnostic for each issue found.
+///
+/// Words are classified one at a time like the debug decoder, so events that fail strict decoding are also validated.
+pub(crate) fn validate_events(bytes: &[u8]) -> Vec<HitDiagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let mut in_packet = false;
+    let mut packet_idx = 0;
+    let mut unit_id = 0;
+    let mut current_region: Option<u8> = None;
+    let mut partial_hit: Option<MossHit> = None;
+    let mut last_hit_in_region: Option<MossHit> = None;
+    let mut seen_pixels: HashSet<MossHit> = HashSet::new();
+
+    for (i, byte) in bytes.iter().enumerate() {
+        match MossWord::from_byte(*byte) {
+            MossWord::UnitFrameHeader => {
+                in_packet = true;
+                unit_id = *byte & 0x0F;
+                current_region = None;
+                partial_hit = None;
+                last_hit_in_region = None;
+                seen_pixels.clear();
+            }
+            MossWord::UnitFrameTrailer if in_packet => {
+                in_packet = false;
+                packet_idx += 1;
+            }
+            MossWord::RegionHeader if in_packet => {
+                let region = *byte & 0x03;
+                if current_region.is_some_and(|prev_region| region <= prev_region) {
+                    diagnostics.push(HitDiagnostic {
+                        kind: HitDiagnosticKind::RegionHeaderOutOfOrder,
+                        packet_idx,
+                        index: i,
+                        unit_id,
+                        region,
+                        hit: None,
+                    });
+                }
+                current_region = Some(region);
+                partial_hit = None;
+                last_hit_in_region = None;
+            }
+            MossWord::Data0 if in_packet => {
+                partial_hit = Some(MossHit {
+                    region: current_region.unwrap_or(0xFF),
+                    row: ((*byte & 0x3F) as u16) << 3,
+                    column: 0,
+                });
+            }
+            MossWord::Data1 if in_packet => {
+                if let Some(hit) = partial_hit.as_mut() {
+                    hit.row |= ((*byte & 0x38) >> 3) as u16;
+                    hit.column = ((*byte & 0x07) as u16) << 6;
+                }
+            }
+            MossWord::Data2 if in_packet => {
+                if let Some(mut hit) = partial_hit.take() {
+                    hit.column |= (*byte & 0x3F) as u16;
+
+                    if !seen_pixels.insert(hit) {
+                        diagnostics.push(HitDiagnostic {
+                            kind: HitDiagnosticKind::DuplicatePixel,
+                            packet_idx,
+                            index: i,
+                            unit_id,
+                            region: hit.region,
+                            hit: Some(hit),
+                        });
+                    } else if last_hit_in_region
+                        .is_some_and(|prev| (hit.row, hit.column) < (prev.row, prev.column))
+                    {
+                        diagnostics.push(HitDiagnostic {
+                            kind: HitDiagnosticKind::HitOutOfOrder,
+                            packet_idx,
+                            index: i,
+                            unit_id,
+                            region: hit.region,
+                            hit: Some(hit),
+                        });
+                    }
+                    last_hit_in_region = Some(hit);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_validate_valid_events() {
+        let mut bytes = fake_event_simple();
+        bytes.extend(fake_multiple_events());
+
+        assert_eq!(validate_events(&bytes), vec![]);
+    }
+
+    #[test]
+    fn test_validate_duplicate_and_out_of_order_hits() {
+        let bytes = vec![
+            UNIT_FRAME_HEADER_1,
+            REGION_HEADER_0,
+            0x00,
+            0b0100_1000, // row 1
+            0b1000_0001, // col 1
+            0x00,
+            0b0100_0000, // row 0 -> out of order
+            0b1000_0010, // col 2
+            0x00,
+            0b0100_1000, // row 1
+            0b1000_0001, // col 1 -> duplicate
+            UNIT_FRAME_TRAILER,
+        ];
+
+        let diagnostics = validate_events(&bytes);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].kind, HitDiagnosticKind::HitOutOfOrder);
+        assert_eq!(diagnostics[0].index, 7);
+        assert_eq!(diagnostics[0].hit.unwrap().column, 2);
+        assert_eq!(diagnostics[1].kind, HitDiagnosticKind::DuplicatePixel);
+        assert_eq!(diagnostics[1].index, 10);
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "Duplicate pixel at index=10 in packet #1 unit 1, region 0 (reg: 0 row: 1 col: 1)"
+        );
+    }
+
+    #[test]
+    fn test_validate_region_header_out_of_order() {
+        let mut bytes = fake_event_simple();
+        bytes.extend(vec![
+            0xD2,
+            REGION_HEADER_2,
+            REGION_HEADER_1,
+            REGION_HEADER_1,
+            UNIT_FRAME_TRAILER,
+        ]);
+
+        let diagnostics = validate_events(&bytes);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.kind == HitDiagnosticKind::RegionHeaderOutOfOrder
+                && d.packet_idx == 1
+                && d.unit_id == 2
+                && d.region == 1));
+        assert_eq!(diagnostics[0].index, 21);
+        assert_eq!(diagnostics[1].index, 22);
+    }
+}
@@ -0,0 +1,77 @@
+//! JSON conversion of decoded packets for Python, backed by the `serde` implementations of the packet types.
+//!
+//! A [MossPacket] is serialized as `{"unit_id": 1, "hits": [{"region": 0, "row": 2, "column": 3}]}`.
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::MossPacket;
+
+/// Serializes `value` to a JSON string.
+pub(crate) fn to_json(value: &impl Serialize) -> PyResult<String> {
+    serde_json::to_string(value).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Deserializes a value from a JSON string, raises a `ValueError` if the JSON is invalid or has the wrong layout.
+pub(crate) fn from_json<T: DeserializeOwned>(json: &str) -> PyResult<T> {
+    serde_json::from_str(json).map_err(|e| PyValueError::new_err(format!("Invalid JSON: {e}")))
+}
+
+/// Serializes a list of [MossPacket]s to a JSON array.
+#[pyfunction]
+#[allow(clippy::needless_pass_by_value)] // PyO3 extracts the Python list into an owned Vec
+pub fn packets_to_json(packets: Vec<PyRef<MossPacket>>) -> PyResult<String> {
+    to_json(&packets.iter().map(|p| &**p).collect::<Vec<_>>())
+}
+
+/// Deserializes a list of [MossPacket]s from a JSON array as written by [packets_to_json].
+#[pyfunction]
+pub fn packets_from_json(json: &str) -> PyResult<Vec<MossPacket>> {
+    from_json(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DecodeError, MossHit, ParseErrorKind};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_packet_json_layout() {
+        let mut packet = MossPacket::new(3);
+        packet.hits.push(MossHit {
+            region: 1,
+            row: 2,
+            column: 3,
+        });
+
+        let json = to_json(&packet).unwrap();
+        assert_eq!(
+            json,
+            r#"{"unit_id":3,"hits":[{"region":1,"row":2,"column":3}]}"#
+        );
+        assert_eq!(from_json::<MossPacket>(&json).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_decode_error_json_layout() {
+        let io_error = DecodeError::from(std::io::Error::other("disconnected"));
+        assert_eq!(to_json(&io_error).unwrap(), r#"{"Io":"disconnected"}"#);
+        let parse_error = DecodeError::Parse {
+            packet_num: 2,
+            kind: ParseErrorKind::ProtocolError,
+            message: "bad word".into(),
+        };
+        assert_eq!(
+            to_json(&parse_error).unwrap(),
+            r#"{"Parse":{"packet_num":2,"kind":"ProtocolError","message":"bad word"}}"#
+        );
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        pyo3::prepare_freethreaded_python();
+        assert!(from_json::<MossPacket>(r#"{"unit_id":1}"#).is_err());
+        assert!(from_json::<MossHit>(r#"{"region":1,"row":-2,"column":3}"#).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 //! A Python module for decoding raw MOSS data implemented in Rust.
-#![forbid(unused_extern_crates)]
+#![deny(unused_extern_crates)] // Not `forbid`, the `serde` derives allow it locally
 #![deny(missing_docs)]
 #![warn(missing_copy_implementations)]
 #![warn(trivial_casts, trivial_numeric_casts)]
@@ -20,34 +20,144 @@
     clippy::mem_forget,
     clippy::maybe_infinite_iter
 )]
+// PyO3 0.20 macros expand to impl blocks inside functions
+#![allow(non_local_definitions)]
 
 pub use moss_protocol::MossPacket;
-use parse_error::ParseErrorKind;
-use parse_util::find_trailer_n_idx;
-use pyo3::exceptions::{PyAssertionError, PyBytesWarning, PyFileNotFoundError, PyValueError};
+pub use moss_protocol::{DmuMode, DmuModeChange, IdleCounts};
+pub use parse_error::ParseErrorKind;
+use parse_util::{find_prefixed_event_n_end, find_trailer_n_idx};
+use pyo3::exceptions::{PyAssertionError, PyBytesWarning, PyValueError};
 use pyo3::prelude::*;
-use std::io::Read;
 
 pub mod moss_protocol;
 pub use moss_protocol::MossHit;
 mod debug_decode;
 pub mod decode_hits_fsm;
+pub mod decode_hits_table;
+pub mod hit_decoder;
+pub use hit_decoder::{get_hit_decoder, set_hit_decoder, HitDecoder};
 pub(crate) mod parse_error;
 pub(crate) mod parse_util;
+pub mod pixel_mask;
+pub use pixel_mask::PixelMask;
+pub mod hit_validation;
+pub use hit_validation::{HitDiagnostic, HitDiagnosticKind};
+pub mod logging;
+pub use logging::{get_log_level, set_log_level};
+pub mod bit_flip;
+pub use bit_flip::{
+    diagnose_bit_flips, diagnose_bit_flips_from_file, BitFlipDiagnostic, BitFlipReport,
+};
+pub mod error_policy;
+pub use error_policy::ErrorPolicy;
+pub mod stats;
+pub use stats::RunStats;
+pub mod fpga_prefix;
+pub use fpga_prefix::FpgaPrefix;
+pub mod decode_error;
+pub mod decode_filter;
+pub use decode_filter::DecodeFilter;
+pub mod decode_options;
+pub use decode_options::DecodeOptions;
+pub mod decompress;
+pub mod event_index;
+pub use decode_error::DecodeError;
+pub use event_index::{index_file, read_events, EventIndex};
+#[cfg(feature = "async")]
+pub mod async_decode;
+#[cfg(feature = "hdf5")]
+pub mod hdf5_output;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod net;
+pub mod scan;
+pub mod source;
+pub mod visitor;
+pub use net::{decode_tcp_stream, decode_udp_stream, PacketStream};
+pub use source::PyFileLike;
+pub use visitor::{decode_with, HitVisitor};
+pub mod packet_batch;
+pub use packet_batch::PacketBatch;
+pub mod csv_io;
+pub use csv_io::{decode_file_to_csv, read_csv, write_csv};
+pub mod packet_file;
+pub use packet_file::{load_packets, save_packets, PacketFileReader, PacketFileWriter};
+mod cli;
 
 /// A Python module for decoding raw MOSS data effeciently in Rust.
 #[pymodule]
-fn moss_decoder(_py: Python, m: &PyModule) -> PyResult<()> {
+fn moss_decoder(py: Python, m: &PyModule) -> PyResult<()> {
+    logging::init(py)?;
+
     m.add_function(wrap_pyfunction!(decode_event, m)?)?;
-    m.add_function(wrap_pyfunction!(decode_all_events, m)?)?;
-    m.add_function(wrap_pyfunction!(decode_from_file, m)?)?;
-    m.add_function(wrap_pyfunction!(decode_n_events, m)?)?;
-    m.add_function(wrap_pyfunction!(skip_n_take_all, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_all_events, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_all_events_parallel, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_all_events_with_idle_counts, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_all_events_with_prefix, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_all_events_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_from_file_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_n_events_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(index_file, m)?)?;
+    m.add_function(wrap_pyfunction!(read_events, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_from_reader, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_n_events_from_reader, m)?)?;
+    m.add_function(wrap_pyfunction!(py_skip_n_take_all_from_reader, m)?)?;
+    m.add_function(wrap_pyfunction!(py_decode_n_events, m)?)?;
+    m.add_function(wrap_pyfunction!(py_skip_n_take_all, m)?)?;
+    m.add_function(wrap_pyfunction!(py_skip_n_take_all_from_file, m)?)?;
     m.add_function(wrap_pyfunction!(debug_decode_all_events, m)?)?;
     m.add_function(wrap_pyfunction!(debug_decode_all_events_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_all_events, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_all_events_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(diagnose_bit_flips, m)?)?;
+    m.add_function(wrap_pyfunction!(diagnose_bit_flips_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(run_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(run_stats_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(run_stats_from_reader, m)?)?;
+    m.add_function(wrap_pyfunction!(set_hit_decoder, m)?)?;
+    m.add_function(wrap_pyfunction!(get_hit_decoder, m)?)?;
+    m.add_function(wrap_pyfunction!(set_log_level, m)?)?;
+    m.add_function(wrap_pyfunction!(get_log_level, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_tcp_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_udp_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(save_packets, m)?)?;
+    m.add_function(wrap_pyfunction!(load_packets, m)?)?;
+    m.add_function(wrap_pyfunction!(write_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_file_to_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(read_csv, m)?)?;
+    #[cfg(feature = "hdf5")]
+    m.add_function(wrap_pyfunction!(hdf5_output::decode_file_to_hdf5, m)?)?;
+    #[cfg(feature = "serde")]
+    m.add_function(wrap_pyfunction!(json::packets_to_json, m)?)?;
+    #[cfg(feature = "serde")]
+    m.add_function(wrap_pyfunction!(json::packets_from_json, m)?)?;
+    m.add_function(wrap_pyfunction!(cli::main, m)?)?;
 
     m.add_class::<MossHit>()?;
     m.add_class::<MossPacket>()?;
+    m.add_class::<moss_protocol::moss_packet::MossPacketIter>()?;
+    m.add_class::<IdleCounts>()?;
+    m.add_class::<DmuMode>()?;
+    m.add_class::<DmuModeChange>()?;
+    m.add_class::<PixelMask>()?;
+    m.add_class::<DecodeFilter>()?;
+    m.add_class::<HitDiagnostic>()?;
+    m.add_class::<HitDiagnosticKind>()?;
+    m.add_class::<BitFlipDiagnostic>()?;
+    m.add_class::<BitFlipReport>()?;
+    m.add_class::<RunStats>()?;
+    m.add_class::<FpgaPrefix>()?;
+    m.add_class::<HitDecoder>()?;
+    m.add_class::<ErrorPolicy>()?;
+    m.add_class::<EventIndex>()?;
+    m.add_class::<PacketStream>()?;
+    m.add_class::<PacketBatch>()?;
+    m.add_class::<packet_batch::PacketBatchIter>()?;
+    m.add_class::<packet_batch::BatchArray>()?;
+    m.add_class::<PacketFileWriter>()?;
+    m.add_class::<PacketFileReader>()?;
 
     Ok(())
 }
@@ -64,6 +174,12 @@ type Tuple_MossPacket_LastTrailerIdx = (MossPacket, LastTrailerIdx);
 #[allow(non_camel_case_types)]
 type Tuple_List_MossPackets_LastTrailerIdx = (List_MossPackets, LastTrailerIdx);
 
+#[allow(non_camel_case_types)]
+type List_Tuple_MossPacket_IdleCounts = Vec<(MossPacket, IdleCounts)>;
+
+#[allow(non_camel_case_types)]
+type List_Tuple_MossPacket_Timestamp_TriggerId = Vec<(MossPacket, Option<u64>, Option<u32>)>;
+
 const READER_BUFFER_CAPACITY: usize = 10 * 1024 * 1024; // 10 MiB
 const MINIMUM_EVENT_SIZE: usize = 2;
 
@@ -81,118 +197,478 @@ pub fn decode_event(bytes: &[u8]) -> PyResult<Tuple_MossPacket_LastTrailerIdx> {
     }
 
     match rust_only::extract_packet_from_buf(bytes, None) {
-        Ok((moss_packet, trailer_idx)) => Ok((moss_packet, trailer_idx)),
+        Ok((moss_packet, _, trailer_idx)) => Ok((moss_packet, trailer_idx)),
         Err(e) => Err(PyAssertionError::new_err(format!("Decoding failed: {e}",))),
     }
 }
 
-#[pyfunction]
 /// Decodes as many MOSS events as possible into a list of [MossPacket]s.
 /// Optimized for speed and memory usage.
 pub fn decode_all_events(bytes: &[u8]) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    decode_all_events_with(bytes, DecodeOptions::default())
+}
+
+/// Decodes as many MOSS events as possible like [decode_all_events], dropping hits and packets according to the [DecodeOptions].
+pub fn decode_all_events_with(
+    bytes: &[u8],
+    options: DecodeOptions,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
     let approx_moss_packets = rust_only::calc_prealloc_val(bytes)?;
 
     let mut moss_packets: Vec<MossPacket> = Vec::with_capacity(approx_moss_packets);
 
+    let mut decoded_cnt = 0;
     let mut last_trailer_idx = 0;
-
-    while last_trailer_idx < bytes.len() - MINIMUM_EVENT_SIZE - 1 {
-        match rust_only::extract_packet_from_buf(&bytes[last_trailer_idx..], None) {
-            Ok((moss_packet, trailer_idx)) => {
-                moss_packets.push(moss_packet);
+    let prefix_len = options.prefix_len();
+
+    while bytes.len() - last_trailer_idx > prefix_len + MINIMUM_EVENT_SIZE + 1 {
+        match rust_only::extract_prefixed_packet(&bytes[last_trailer_idx..], None, prefix_len) {
+            Ok((moss_packet, _, trailer_idx)) => {
+                rust_only::push_packet(
+                    &mut moss_packets,
+                    moss_packet,
+                    options.mask,
+                    options.filter,
+                );
+                decoded_cnt += 1;
                 last_trailer_idx += trailer_idx + 1;
             }
             Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
                 return Err(PyBytesWarning::new_err(format!(
                     "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1
+                    packet_cnt = decoded_cnt + 1
                 )));
             }
             Err(e) => {
                 return Err(PyAssertionError::new_err(format!(
                     "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1
+                    packet_cnt = decoded_cnt + 1
                 )))
             }
         }
     }
 
-    if moss_packets.is_empty() {
+    if decoded_cnt == 0 {
         Err(PyAssertionError::new_err("No MOSS Packets in events"))
     } else {
         Ok((moss_packets, last_trailer_idx - 1))
     }
 }
 
-/// Decodes a file containing raw MOSS data into a list of [MossPacket]s.
-///
-/// The file is read in chunks of 10 MiB until the end of the file is reached.
-/// If any errors are encountered while reading the file, an exception is thrown.
-/// There's no attempt to run over errors.
 #[pyfunction]
-pub fn decode_from_file(path: std::path::PathBuf) -> PyResult<List_MossPackets> {
-    // Open file (get file descriptor)
-    let file = match std::fs::File::open(path) {
-        Ok(file) => file,
-        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+#[pyo3(name = "decode_all_events", signature = (bytes, mask=None, filter=None, prefix=None))]
+/// Decodes as many MOSS events as possible into a list of [MossPacket]s.
+/// Optimized for speed and memory usage.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter].
+/// Optionally skips the FPGA prefix with the given [FpgaPrefix] layout preceding each event.
+fn py_decode_all_events(
+    bytes: &[u8],
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    decode_all_events_with(bytes, DecodeOptions::new(mask, filter, None, prefix))
+}
+
+/// Decodes as many MOSS events as possible into a list of [MossPacket]s like [decode_all_events], but on several threads.
+///
+/// The bytes are split into shards at event boundaries which are decoded in parallel without holding the GIL.
+/// `threads` defaults to the number of available CPUs and is capped at that number and at the number of events.
+pub fn decode_all_events_parallel(
+    py: Python,
+    bytes: &[u8],
+    threads: Option<usize>,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    decode_all_events_parallel_with(py, bytes, threads, DecodeOptions::default())
+}
+
+/// Decodes like [decode_all_events_parallel], dropping hits and packets according to the [DecodeOptions].
+///
+/// Data with an FPGA prefix can't be split at event boundaries, as the prefix can contain any byte value, so it is decoded sequentially with [decode_all_events_with].
+pub fn decode_all_events_parallel_with(
+    py: Python,
+    bytes: &[u8],
+    threads: Option<usize>,
+    options: DecodeOptions,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    if options.prefix.is_some() {
+        return decode_all_events_with(bytes, options);
+    }
+    let available_threads =
+        std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+    let threads = match threads {
+        Some(0) => return Err(PyValueError::new_err("threads must be greater than 0")),
+        // More threads than CPUs only adds overhead, and huge values from Python must not spawn a thread per event
+        Some(threads) => threads.min(available_threads),
+        None => available_threads,
     };
+    let shards = scan::shard_events(bytes, threads);
+
+    let shard_results = py.allow_threads(|| {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = shards
+                .iter()
+                .map(|shard| {
+                    s.spawn(|| {
+                        rust_only::decode_shard(
+                            &bytes[shard.clone()],
+                            bytes.len() - shard.end,
+                            options.mask,
+                            options.filter,
+                        )
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Decoding thread panicked"))
+                .collect::<Vec<_>>()
+        })
+    });
+
+    let mut moss_packets: Vec<MossPacket> = Vec::new();
+    let mut decoded_cnt = 0;
+    let mut decoded_end = 0;
+    for (shard, shard_result) in shards.iter().zip(shard_results) {
+        match shard_result {
+            Ok((shard_packets, shard_decoded_cnt, shard_end)) => {
+                if shard_decoded_cnt > 0 {
+                    decoded_end = shard.start + shard_end;
+                }
+                decoded_cnt += shard_decoded_cnt;
+                moss_packets.extend(shard_packets);
+            }
+            // Shards before the failing one decoded fully, so the packet number is the same as with a sequential decode
+            Err((e, shard_decoded_cnt)) => {
+                let packet_cnt = decoded_cnt + shard_decoded_cnt + 1;
+                return Err(if e.kind() == ParseErrorKind::EndOfBufferNoTrailer {
+                    PyBytesWarning::new_err(format!("Failed decoding packet #{packet_cnt}: {e}"))
+                } else {
+                    PyAssertionError::new_err(format!("Failed decoding packet #{packet_cnt}: {e}"))
+                });
+            }
+        }
+    }
 
-    // Create buffered reader with 1MB capacity to minimize syscalls to read
-    let mut reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
+    if decoded_cnt == 0 {
+        Err(PyAssertionError::new_err("No MOSS Packets in events"))
+    } else {
+        Ok((moss_packets, decoded_end - 1))
+    }
+}
 
-    let mut moss_packets = Vec::new();
+#[pyfunction]
+#[pyo3(name = "decode_all_events_parallel", signature = (bytes, mask=None, threads=None, filter=None))]
+/// Decodes as many MOSS events as possible into a list of [MossPacket]s like [decode_all_events], but on several threads.
+///
+/// The bytes are split into shards at event boundaries which are decoded in parallel without holding the GIL.
+/// `threads` defaults to the number of available CPUs and is capped at that number and at the number of events.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter].
+fn py_decode_all_events_parallel(
+    py: Python,
+    bytes: &[u8],
+    mask: Option<&PixelMask>,
+    threads: Option<usize>,
+    filter: Option<&DecodeFilter>,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    decode_all_events_parallel_with(
+        py,
+        bytes,
+        threads,
+        DecodeOptions::new(mask, filter, None, None),
+    )
+}
 
-    let mut buf = vec![0; READER_BUFFER_CAPACITY];
-    let mut bytes_to_decode = Vec::with_capacity(READER_BUFFER_CAPACITY);
-    while let Ok(bytes_read) = reader.read(&mut buf) {
-        if bytes_read == 0 {
-            break;
+/// Decodes as many MOSS events as possible into a list of [MossPacket]s, each paired with the [IdleCounts] of its idle words.
+///
+/// The idle word pattern reveals the DMU readout mode, any packet with mixed idle words or a mode different from the previous packets is reported as a [DmuModeChange].
+/// Counting the idle words requires an extra pass over the bytes of each event, use [decode_all_events] if the idle words are not of interest.
+///
+/// Hits and packets are dropped according to the [DecodeOptions], the packet indices of the mode changes count all decoded packets.
+pub fn decode_all_events_with_idle_counts(
+    bytes: &[u8],
+    options: DecodeOptions,
+) -> PyResult<(
+    List_Tuple_MossPacket_IdleCounts,
+    LastTrailerIdx,
+    Vec<DmuModeChange>,
+)> {
+    let approx_moss_packets = rust_only::calc_prealloc_val(bytes)?;
+
+    let mut moss_packets = Vec::with_capacity(approx_moss_packets);
+    let mut mode_tracker = moss_protocol::idle_counts::DmuModeTracker::default();
+    let mut mode_changes = Vec::new();
+
+    let mut decoded_cnt = 0;
+    let mut last_trailer_idx = 0;
+    let prefix_len = options.prefix_len();
+
+    while bytes.len() - last_trailer_idx > prefix_len + MINIMUM_EVENT_SIZE + 1 {
+        match rust_only::extract_prefixed_packet(&bytes[last_trailer_idx..], None, prefix_len) {
+            Ok((moss_packet, _, trailer_idx)) => {
+                // Anything between the prefix and the header is delimiters, so all idle words are part of the event
+                let idle_counts = IdleCounts::from_event_bytes(
+                    &bytes[last_trailer_idx + prefix_len..last_trailer_idx + trailer_idx],
+                );
+                if let Some(mode_change) = mode_tracker.update(decoded_cnt, &idle_counts) {
+                    mode_changes.push(mode_change);
+                }
+                if let Some(moss_packet) =
+                    rust_only::select_packet(moss_packet, options.mask, options.filter)
+                {
+                    moss_packets.push((moss_packet, idle_counts));
+                }
+                decoded_cnt += 1;
+                last_trailer_idx += trailer_idx + 1;
+            }
+            Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
+                return Err(PyBytesWarning::new_err(format!(
+                    "Failed decoding packet #{packet_cnt}: {e}",
+                    packet_cnt = decoded_cnt + 1
+                )));
+            }
+            Err(e) => {
+                return Err(PyAssertionError::new_err(format!(
+                    "Failed decoding packet #{packet_cnt}: {e}",
+                    packet_cnt = decoded_cnt + 1
+                )))
+            }
         }
+    }
 
-        // Extend bytes_to_decode with the new data
-        bytes_to_decode.extend_from_slice(&buf[..bytes_read]);
+    if decoded_cnt == 0 {
+        Err(PyAssertionError::new_err("No MOSS Packets in events"))
+    } else {
+        Ok((moss_packets, last_trailer_idx - 1, mode_changes))
+    }
+}
 
-        // Decode the bytes one event at a time until there's no more events to decode
-        match rust_only::get_all_packets_from_buf(&bytes_to_decode) {
-            Ok((extracted_packets, last_trailer_idx)) => {
-                moss_packets.extend(extracted_packets);
-                // Remove the processed bytes from bytes_to_decode (it now contains the remaining bytes that could did not form a complete event)
-                bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
+#[pyfunction]
+#[pyo3(name = "decode_all_events_with_idle_counts", signature = (bytes, mask=None, filter=None))]
+/// Decodes as many MOSS events as possible into a list of [MossPacket]s, each paired with the [IdleCounts] of its idle words.
+///
+/// The idle word pattern reveals the DMU readout mode, any packet with mixed idle words or a mode different from the previous packets is reported as a [DmuModeChange].
+/// Counting the idle words requires an extra pass over the bytes of each event, use [decode_all_events] if the idle words are not of interest.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter], the packet indices of the mode changes count all decoded packets.
+fn py_decode_all_events_with_idle_counts(
+    bytes: &[u8],
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+) -> PyResult<(
+    List_Tuple_MossPacket_IdleCounts,
+    LastTrailerIdx,
+    Vec<DmuModeChange>,
+)> {
+    decode_all_events_with_idle_counts(bytes, DecodeOptions::new(mask, filter, None, None))
+}
+
+/// Decodes as many MOSS events as possible from data where each event is preceded by an FPGA prefix with the given [FpgaPrefix] layout.
+///
+/// The prefix comes before the delimiter bytes of the event, each packet is paired with the timestamp and trigger ID from its prefix,
+/// which are `None` if they are not part of the layout.
+///
+/// Hits and packets are dropped according to the [DecodeOptions], the given `prefix` is used instead of the prefix of the options.
+pub fn decode_all_events_with_prefix(
+    bytes: &[u8],
+    prefix: &FpgaPrefix,
+    options: DecodeOptions,
+) -> PyResult<(List_Tuple_MossPacket_Timestamp_TriggerId, LastTrailerIdx)> {
+    let approx_moss_packets = rust_only::calc_prealloc_val(bytes)?;
+
+    let mut moss_packets = Vec::with_capacity(approx_moss_packets);
+
+    let mut decoded_cnt = 0;
+    let mut last_trailer_idx = 0;
+
+    while bytes.len() - last_trailer_idx > prefix.length() + MINIMUM_EVENT_SIZE {
+        let event_start = last_trailer_idx + prefix.length();
+        match rust_only::extract_packet_from_buf(&bytes[event_start..], None) {
+            Ok((moss_packet, _, trailer_idx)) => {
+                let (timestamp, trigger_id) = prefix.parse(&bytes[last_trailer_idx..event_start]);
+                if let Some(moss_packet) =
+                    rust_only::select_packet(moss_packet, options.mask, options.filter)
+                {
+                    moss_packets.push((moss_packet, timestamp, trigger_id));
+                }
+                decoded_cnt += 1;
+                last_trailer_idx = event_start + trailer_idx + 1;
             }
-            Err((e, failed_packet_num)) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
+            Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
                 return Err(PyBytesWarning::new_err(format!(
                     "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
+                    packet_cnt = decoded_cnt + 1
                 )));
             }
-            Err((e, failed_packet_num)) => {
+            Err(e) => {
                 return Err(PyAssertionError::new_err(format!(
                     "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
+                    packet_cnt = decoded_cnt + 1
                 )))
             }
         }
     }
 
-    if moss_packets.is_empty() {
+    if decoded_cnt == 0 {
+        Err(PyAssertionError::new_err("No MOSS Packets in events"))
+    } else {
+        Ok((moss_packets, last_trailer_idx - 1))
+    }
+}
+
+#[pyfunction]
+#[pyo3(name = "decode_all_events_with_prefix", signature = (bytes, prefix, mask=None, filter=None))]
+/// Decodes as many MOSS events as possible from data where each event is preceded by an FPGA prefix with the given [FpgaPrefix] layout.
+///
+/// The prefix comes before the delimiter bytes of the event, each packet is paired with the timestamp and trigger ID from its prefix,
+/// which are `None` if they are not part of the layout.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter].
+fn py_decode_all_events_with_prefix(
+    bytes: &[u8],
+    prefix: &FpgaPrefix,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+) -> PyResult<(List_Tuple_MossPacket_Timestamp_TriggerId, LastTrailerIdx)> {
+    decode_all_events_with_prefix(bytes, prefix, DecodeOptions::new(mask, filter, None, None))
+}
+
+/// Decodes a file containing raw MOSS data into a list of [MossPacket]s.
+///
+/// The file is read in chunks of 10 MiB until the end of the file is reached.
+/// gzip, zstd and xz compressed files are decompressed on the fly if the corresponding cargo feature is enabled, see [decompress].
+pub fn decode_from_file(path: std::path::PathBuf) -> PyResult<List_MossPackets> {
+    decode_from_file_with(path, DecodeOptions::default())
+}
+
+/// Decodes a file like [decode_from_file], dropping hits and packets and handling errors according to the [DecodeOptions].
+pub fn decode_from_file_with(
+    path: std::path::PathBuf,
+    options: DecodeOptions,
+) -> PyResult<List_MossPackets> {
+    // Open file (get file descriptor), compressed files are decompressed on the fly
+    let file = decompress::open_file(path)?;
+    source::decode_all(file, options)
+}
+
+/// Decodes a file containing raw MOSS data into a list of [MossPacket]s.
+///
+/// The file is read in chunks of 10 MiB until the end of the file is reached.
+/// gzip, zstd and xz compressed files are decompressed on the fly if the corresponding cargo feature is enabled, see [decompress].
+/// Decoding and I/O errors are handled according to the [ErrorPolicy], by default the first error is raised.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter].
+/// Optionally skips the FPGA prefix with the given [FpgaPrefix] layout preceding each event.
+#[pyfunction]
+#[pyo3(name = "decode_from_file", signature = (path, mask=None, filter=None, error_policy=None, prefix=None))]
+fn py_decode_from_file(
+    path: std::path::PathBuf,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<List_MossPackets> {
+    decode_from_file_with(path, DecodeOptions::new(mask, filter, error_policy, prefix))
+}
+
+/// Decodes as many MOSS events as possible into a [PacketBatch], which stores the hits of all packets in contiguous arrays.
+/// Returns the batch and the index of the last trailer byte, an incomplete event after the last trailer is not decoded.
+pub fn decode_all_events_batch(bytes: &[u8]) -> PyResult<(PacketBatch, LastTrailerIdx)> {
+    decode_all_events_batch_with(bytes, DecodeOptions::default())
+}
+
+/// Decodes like [decode_all_events_batch], dropping hits and packets according to the [DecodeOptions].
+pub fn decode_all_events_batch_with(
+    bytes: &[u8],
+    options: DecodeOptions,
+) -> PyResult<(PacketBatch, LastTrailerIdx)> {
+    let (batch, consumed) = match options.prefix {
+        Some(prefix) => PacketBatch::decode_prefixed(bytes, prefix, options.mask, options.filter)?,
+        None => PacketBatch::decode(bytes, options.mask, options.filter)?,
+    };
+    if consumed == 0 {
         Err(PyAssertionError::new_err("No MOSS Packets in events"))
     } else {
-        Ok(moss_packets)
+        Ok((batch, consumed - 1))
     }
 }
 
+#[pyfunction]
+#[pyo3(name = "decode_all_events_batch", signature = (bytes, mask=None, filter=None, prefix=None))]
+/// Decodes as many MOSS events as possible into a [PacketBatch], which stores the hits of all packets in contiguous arrays.
+/// Returns the batch and the index of the last trailer byte, an incomplete event after the last trailer is not decoded.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter].
+/// Optionally skips the FPGA prefix with the given [FpgaPrefix] layout preceding each event.
+fn py_decode_all_events_batch(
+    bytes: &[u8],
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<(PacketBatch, LastTrailerIdx)> {
+    decode_all_events_batch_with(bytes, DecodeOptions::new(mask, filter, None, prefix))
+}
+
+/// Decodes a file containing raw MOSS data into a [PacketBatch], see [decode_from_file].
+pub fn decode_from_file_batch(path: std::path::PathBuf) -> PyResult<PacketBatch> {
+    decode_from_file_batch_with(path, DecodeOptions::default())
+}
+
+/// Decodes a file into a [PacketBatch] like [decode_from_file_batch], dropping hits and packets and handling errors according to the [DecodeOptions].
+pub fn decode_from_file_batch_with(
+    path: std::path::PathBuf,
+    options: DecodeOptions,
+) -> PyResult<PacketBatch> {
+    // Open file (get file descriptor), compressed files are decompressed on the fly
+    let file = decompress::open_file(path)?;
+    source::decode_all_batch(file, options)
+}
+
+/// Decodes a file containing raw MOSS data into a [PacketBatch], see [decode_from_file].
+#[pyfunction]
+#[pyo3(name = "decode_from_file_batch", signature = (path, mask=None, filter=None, error_policy=None, prefix=None))]
+fn py_decode_from_file_batch(
+    path: std::path::PathBuf,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<PacketBatch> {
+    decode_from_file_batch_with(path, DecodeOptions::new(mask, filter, error_policy, prefix))
+}
+
 /// Decodes N events from the given bytes.
 /// Optionally allows for either (not both):
 /// - skipping `skip` events before decoding.
 /// - prepending `prepend_buffer` to the bytes before decoding.
-#[pyfunction]
 pub fn decode_n_events(
+    bytes: &[u8],
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    decode_n_events_with(bytes, take, skip, prepend_buffer, DecodeOptions::default())
+}
+
+/// Decodes N events like [decode_n_events], dropping hits and packets according to the [DecodeOptions].
+pub fn decode_n_events_with(
     bytes: &[u8],
     take: usize,
     skip: Option<usize>,
     mut prepend_buffer: Option<Vec<u8>>,
+    options: DecodeOptions,
 ) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
-    let mut moss_packets: Vec<MossPacket> = Vec::with_capacity(take);
+    // There can't be more events than the bytes can hold, even if `take` from the caller is larger
+    let mut moss_packets: Vec<MossPacket> =
+        Vec::with_capacity(take.min(bytes.len() / MINIMUM_EVENT_SIZE));
 
     // Skip N events
     if skip.is_some_and(|s| s == 0) {
@@ -203,23 +679,32 @@ pub fn decode_n_events(
         ));
     }
 
-    let mut last_trailer_idx = if let Some(skip) = skip {
-        find_trailer_n_idx(bytes, skip)?
-    } else {
-        0
+    let prefix_len = options.prefix_len();
+    let mut last_trailer_idx = match skip {
+        Some(skip) if prefix_len > 0 => find_prefixed_event_n_end(bytes, skip, prefix_len)?,
+        Some(skip) => find_trailer_n_idx(bytes, skip)?,
+        None => 0,
     };
 
     for i in 0..take {
-        match rust_only::extract_packet_from_buf(&bytes[last_trailer_idx..], prepend_buffer.take())
-        {
-            Ok((moss_packet, trailer_idx)) => {
-                moss_packets.push(moss_packet);
+        match rust_only::extract_prefixed_packet(
+            &bytes[last_trailer_idx..],
+            prepend_buffer.take(),
+            prefix_len,
+        ) {
+            Ok((moss_packet, _, trailer_idx)) => {
+                rust_only::push_packet(
+                    &mut moss_packets,
+                    moss_packet,
+                    options.mask,
+                    options.filter,
+                );
                 last_trailer_idx += trailer_idx + 1;
             }
             Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
                 return Err(PyBytesWarning::new_err(format!(
                     "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1
+                    packet_cnt = i + 1
                 )))
             }
             Err(e) => {
@@ -231,52 +716,97 @@ pub fn decode_n_events(
         }
     }
 
-    if moss_packets.is_empty() {
+    // Every event is decoded or an error is returned, but the filter may drop all packets
+    if take == 0 {
         Err(PyAssertionError::new_err("No MOSS Packets in events"))
     } else {
         Ok((moss_packets, last_trailer_idx - 1))
     }
 }
 
+/// Decodes N events from the given bytes.
+/// Optionally allows for either (not both):
+/// - skipping `skip` events before decoding.
+/// - prepending `prepend_buffer` to the bytes before decoding.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter], `take` and `skip` count all events in the bytes.
+/// Optionally skips the FPGA prefix with the given [FpgaPrefix] layout preceding each event, the prefix of the first event may be in `prepend_buffer`.
+#[pyfunction]
+#[pyo3(name = "decode_n_events", signature = (bytes, take, skip=None, prepend_buffer=None, mask=None, filter=None, prefix=None))]
+fn py_decode_n_events(
+    bytes: &[u8],
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    decode_n_events_with(
+        bytes,
+        take,
+        skip,
+        prepend_buffer,
+        DecodeOptions::new(mask, filter, None, prefix),
+    )
+}
+
 #[allow(non_camel_case_types)]
 type Remainder_Bytes = Vec<u8>;
 
 /// Skips N events in the given bytes and decode as many packets as possible until end of buffer,
 /// If any packets are decoded, they are returned as a list of MOSS Packets.
 /// if the end of the buffer contains a partial event, those bytes are returned as a remainder.
-///
-/// Arguments: bytes: `bytes`, skip: `int`
-///
-/// Returns: `Tuple[Optional[List[MossPacket]], Optional[bytes]]`
-#[pyfunction]
 pub fn skip_n_take_all(
     bytes: &[u8],
     skip: usize,
+) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
+    skip_n_take_all_with(bytes, skip, DecodeOptions::default())
+}
+
+/// Skips N events and decodes the rest like [skip_n_take_all], dropping hits and packets according to the [DecodeOptions].
+pub fn skip_n_take_all_with(
+    bytes: &[u8],
+    skip: usize,
+    options: DecodeOptions,
 ) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
     let mut moss_packets: Vec<MossPacket> = Vec::new();
     let mut remainder: Option<Vec<u8>> = None;
 
     // Skip N events
-    let mut last_trailer_idx = if skip > 0 {
-        find_trailer_n_idx(bytes, skip)?
-    } else {
+    let mut decoded_cnt = 0;
+    let prefix_len = options.prefix_len();
+    let mut last_trailer_idx = if skip == 0 {
         0
+    } else if prefix_len > 0 {
+        find_prefixed_event_n_end(bytes, skip, prefix_len)?
+    } else {
+        find_trailer_n_idx(bytes, skip)?
     };
 
-    while last_trailer_idx < bytes.len() - MINIMUM_EVENT_SIZE - 1 {
-        match rust_only::extract_packet_from_buf(&bytes[last_trailer_idx..], None) {
-            Ok((moss_packet, trailer_idx)) => {
-                moss_packets.push(moss_packet);
+    while last_trailer_idx < bytes.len() {
+        match rust_only::extract_prefixed_packet(&bytes[last_trailer_idx..], None, prefix_len) {
+            Ok((moss_packet, _, trailer_idx)) => {
+                rust_only::push_packet(
+                    &mut moss_packets,
+                    moss_packet,
+                    options.mask,
+                    options.filter,
+                );
+                decoded_cnt += 1;
                 last_trailer_idx += trailer_idx + 1;
             }
             Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
                 remainder = Some(bytes[last_trailer_idx..].to_vec());
                 break;
             }
+            // Only delimiters are left
+            Err(e) if e.kind() == ParseErrorKind::NoHeaderFound => break,
             Err(e) => {
                 return Err(PyAssertionError::new_err(format!(
                     "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1
+                    packet_cnt = decoded_cnt + 1
                 )))
             }
         }
@@ -289,183 +819,258 @@ pub fn skip_n_take_all(
     }
 }
 
+/// Skips N events in the given bytes and decode as many packets as possible until end of buffer,
+/// If any packets are decoded, they are returned as a list of MOSS Packets.
+/// if the end of the buffer contains a partial event, those bytes are returned as a remainder.
+///
+/// Arguments: bytes: `bytes`, skip: `int`, mask: `Optional[PixelMask]`, filter: `Optional[DecodeFilter]`, prefix: `Optional[FpgaPrefix]`
+///
+/// Returns: `Tuple[Optional[List[MossPacket]], Optional[bytes]]`
+#[pyfunction]
+#[pyo3(name = "skip_n_take_all", signature = (bytes, skip, mask=None, filter=None, prefix=None))]
+fn py_skip_n_take_all(
+    bytes: &[u8],
+    skip: usize,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
+    skip_n_take_all_with(bytes, skip, DecodeOptions::new(mask, filter, None, prefix))
+}
+
 /// Decodes N events from the given file.
 /// Optionally allows for either (not both):
 /// - skipping `skip` events before decoding.
 /// - prepending `prepend_buffer` to the bytes before decoding.
 ///
-/// Arguments: path: `str`, take: `int`, skip: `Optional[int]`, prepend_buffer: `Optional[bytes]`
-/// Returns: `List[MossPacket]`
-#[pyfunction]
+/// If the file has an up to date [EventIndex] sidecar file, see [index_file], the skipped events are not decoded,
+/// instead the file is read from the first event to decode.
 pub fn decode_n_events_from_file(
     path: std::path::PathBuf,
     take: usize,
     skip: Option<usize>,
-    mut prepend_buffer: Option<Vec<u8>>,
+    prepend_buffer: Option<Vec<u8>>,
 ) -> PyResult<List_MossPackets> {
-    // Skip N events
-    if skip.is_some_and(|s| s == 0) {
-        return Err(PyValueError::new_err("skip value must be greater than 0"));
-    } else if skip.is_some() && prepend_buffer.is_some() {
-        return Err(PyValueError::new_err(
-            "skip and prepend_buffer cannot be used together",
-        ));
-    }
-    // Open file (get file descriptor)
-    let file = match std::fs::File::open(path) {
-        Ok(file) => file,
-        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
-    };
-
-    // Create buffered reader with 1MB capacity to minimize syscalls to read
-    let mut reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
-
-    let mut moss_packets: Vec<MossPacket> = Vec::with_capacity(take);
+    decode_n_events_from_file_with(path, take, skip, prepend_buffer, DecodeOptions::default())
+}
 
-    let mut buf = vec![0; READER_BUFFER_CAPACITY];
-    let mut bytes_to_decode = Vec::with_capacity(READER_BUFFER_CAPACITY);
-    if let Some(prepend_buffer) = prepend_buffer.take() {
-        bytes_to_decode.extend_from_slice(&prepend_buffer);
+/// Decodes N events from a file like [decode_n_events_from_file], dropping hits and packets and handling errors according to the [DecodeOptions].
+pub fn decode_n_events_from_file_with(
+    path: std::path::PathBuf,
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+    options: DecodeOptions,
+) -> PyResult<List_MossPackets> {
+    // The offsets of the index point at the Unit Frame Headers, so they can't be used for prefixed events
+    if let Some(skip) =
+        skip.filter(|s| *s > 0 && prepend_buffer.is_none() && options.prefix.is_none())
+    {
+        if let Some(offset) =
+            EventIndex::load_sidecar(&path).and_then(|index| index.offsets().get(skip).copied())
+        {
+            let mut file = std::fs::File::open(&path)
+                .map_err(|e| pyo3::exceptions::PyFileNotFoundError::new_err(e.to_string()))?;
+            _ = std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(offset))
+                .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+            return source::decode_n(file, take, None, None, options);
+        }
     }
-    let mut packets_to_skip = skip.unwrap_or(0);
+    // Open file (get file descriptor), compressed files are decompressed on the fly
+    let file = decompress::open_file(path)?;
+    source::decode_n(file, take, skip, prepend_buffer, options)
+}
 
-    while let Ok(bytes_read) = reader.read(&mut buf) {
-        if bytes_read == 0 {
-            break;
-        }
+/// Decodes N events from the given file.
+/// Optionally allows for either (not both):
+/// - skipping `skip` events before decoding.
+/// - prepending `prepend_buffer` to the bytes before decoding.
+///
+/// If the file has an up to date [EventIndex] sidecar file, see [index_file], the skipped events are not decoded,
+/// instead the file is read from the first event to decode.
+///
+/// Arguments: path: `str`, take: `int`, skip: `Optional[int]`, prepend_buffer: `Optional[bytes]`, mask: `Optional[PixelMask]`, filter: `Optional[DecodeFilter]`, error_policy: `Optional[ErrorPolicy]`, prefix: `Optional[FpgaPrefix]`
+/// Returns: `List[MossPacket]`
+#[pyfunction]
+#[pyo3(name = "decode_n_events_from_file", signature = (path, take, skip=None, prepend_buffer=None, mask=None, filter=None, error_policy=None, prefix=None))]
+#[allow(clippy::too_many_arguments)] // One argument per Python keyword argument
+fn py_decode_n_events_from_file(
+    path: std::path::PathBuf,
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<List_MossPackets> {
+    decode_n_events_from_file_with(
+        path,
+        take,
+        skip,
+        prepend_buffer,
+        DecodeOptions::new(mask, filter, error_policy, prefix),
+    )
+}
 
-        // Extend bytes_to_decode with the new data
-        bytes_to_decode.extend_from_slice(&buf[..bytes_read]);
-
-        // Decode the bytes one event at a time until there's no more events to decode
-        match rust_only::get_all_packets_from_buf(&bytes_to_decode) {
-            Ok((mut extracted_packets, last_trailer_idx)) => {
-                if packets_to_skip > 0 {
-                    if packets_to_skip > extracted_packets.len() {
-                        packets_to_skip -= extracted_packets.len();
-                        bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-                        continue;
-                    } else {
-                        for _ in 0..packets_to_skip {
-                            _ = extracted_packets.remove(0);
-                        }
-                        packets_to_skip = 0;
-                    }
-                }
-                moss_packets.extend(extracted_packets);
-                if moss_packets.len() >= take {
-                    break;
-                }
-                // Remove the processed bytes from bytes_to_decode (it now contains the remaining bytes that could did not form a complete event)
-                bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-            }
-            Err((e, failed_packet_num)) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                return Err(PyBytesWarning::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
-                )));
-            }
-            Err((e, failed_packet_num)) => {
-                return Err(PyAssertionError::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
-                )))
-            }
-        }
-    }
+/// Decodes all events from the given file, skipping the first `skip` events
+///  and returns the remainder bytes if a partial event was found in it.
+pub fn skip_n_take_all_from_file(
+    path: std::path::PathBuf,
+    skip: usize,
+) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
+    skip_n_take_all_from_file_with(path, skip, DecodeOptions::default())
+}
 
-    if moss_packets.is_empty() {
-        Err(PyAssertionError::new_err("No MOSS Packets in events"))
-    } else if moss_packets.len() < take {
-        Err(PyBytesWarning::new_err(format!(
-            "Taking {take} events failed, got {decoded_cnt} events",
-            decoded_cnt = moss_packets.len()
-        )))
-    } else {
-        moss_packets.truncate(take); // Truncate to the requested number of events
-        Ok(moss_packets)
-    }
+/// Skips N events of a file and decodes the rest like [skip_n_take_all_from_file], dropping hits and packets and handling errors according to the [DecodeOptions].
+pub fn skip_n_take_all_from_file_with(
+    path: std::path::PathBuf,
+    skip: usize,
+    options: DecodeOptions,
+) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
+    // Open file (get file descriptor), compressed files are decompressed on the fly
+    let file = decompress::open_file(path)?;
+    source::skip_n_take_all(file, skip, options)
 }
 
 /// Decodes all events from the given file, skipping the first `skip` events
 ///  and returns the remainder bytes if a partial event was found in it.
 ///
-/// Arguments: path: `str`, skip: `Optional[int]`
+/// Arguments: path: `str`, skip: `Optional[int]`, mask: `Optional[PixelMask]`, filter: `Optional[DecodeFilter]`, error_policy: `Optional[ErrorPolicy]`, prefix: `Optional[FpgaPrefix]`
 /// Returns: `Tuple[Optional[List[MossPacket]], Optional[bytes]]`
 #[pyfunction]
-pub fn skip_n_take_all_from_file(
+#[pyo3(name = "skip_n_take_all_from_file", signature = (path, skip, mask=None, filter=None, error_policy=None, prefix=None))]
+fn py_skip_n_take_all_from_file(
     path: std::path::PathBuf,
-    mut skip: usize,
+    skip: usize,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+    prefix: Option<&FpgaPrefix>,
 ) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
-    let mut moss_packets: Vec<MossPacket> = Vec::new();
-    let mut remainder: Option<Vec<u8>> = None;
-    // Open file (get file descriptor)
-    let file = match std::fs::File::open(path) {
-        Ok(file) => file,
-        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
-    };
+    skip_n_take_all_from_file_with(
+        path,
+        skip,
+        DecodeOptions::new(mask, filter, error_policy, prefix),
+    )
+}
 
-    // Create buffered reader with 1MB capacity to minimize syscalls to read
-    let mut reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
+/// Decodes a file-like object, i.e. any object with a `read()` method returning `bytes`, into a list of [MossPacket]s.
+///
+/// Works like [decode_from_file], e.g. with a file opened in binary mode, `io.BytesIO`, `sys.stdin.buffer` or a socket file.
+pub fn decode_from_reader(reader: &PyAny) -> PyResult<List_MossPackets> {
+    decode_from_reader_with(reader, DecodeOptions::default())
+}
 
-    let mut buf = vec![0; READER_BUFFER_CAPACITY];
-    let mut bytes_to_decode = Vec::with_capacity(READER_BUFFER_CAPACITY);
+/// Decodes a file-like object like [decode_from_reader], dropping hits and packets and handling errors according to the [DecodeOptions].
+pub fn decode_from_reader_with(
+    reader: &PyAny,
+    options: DecodeOptions,
+) -> PyResult<List_MossPackets> {
+    source::decode_all(PyFileLike::new(reader.py(), reader.into())?, options)
+}
 
-    while let Ok(bytes_read) = reader.read(&mut buf) {
-        if bytes_read == 0 {
-            break;
-        }
+/// Decodes a file-like object, i.e. any object with a `read()` method returning `bytes`, into a list of [MossPacket]s.
+///
+/// Works like [decode_from_file], e.g. with a file opened in binary mode, `io.BytesIO`, `sys.stdin.buffer` or a socket file.
+#[pyfunction]
+#[pyo3(name = "decode_from_reader", signature = (reader, mask=None, filter=None, error_policy=None, prefix=None))]
+fn py_decode_from_reader(
+    reader: &PyAny,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<List_MossPackets> {
+    decode_from_reader_with(
+        reader,
+        DecodeOptions::new(mask, filter, error_policy, prefix),
+    )
+}
 
-        // Extend bytes_to_decode with the new data
-        bytes_to_decode.extend_from_slice(&buf[..bytes_read]);
-
-        // Decode the bytes one event at a time until there's no more events to decode
-        match rust_only::get_all_packets_from_buf(&bytes_to_decode) {
-            Ok((mut extracted_packets, last_trailer_idx)) => {
-                if skip > 0 {
-                    if skip > extracted_packets.len() {
-                        skip -= extracted_packets.len();
-                        bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-                        continue;
-                    } else {
-                        for _ in 0..skip {
-                            _ = extracted_packets.remove(0);
-                        }
-                        skip = 0;
-                    }
-                }
-                moss_packets.extend(extracted_packets);
-                // Remove the processed bytes from bytes_to_decode (it now contains the remaining bytes that could did not form a complete event)
-                bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-            }
-            Err((e, _)) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                break;
-            }
-            Err((e, _)) if e.kind() == ParseErrorKind::NoHeaderFound => {
-                break;
-            }
-            Err((e, failed_packet_num)) => {
-                return Err(PyAssertionError::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
-                )))
-            }
-        }
-    }
+/// Decodes N events from a file-like object, see [decode_n_events_from_file].
+pub fn decode_n_events_from_reader(
+    reader: &PyAny,
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+) -> PyResult<List_MossPackets> {
+    decode_n_events_from_reader_with(reader, take, skip, prepend_buffer, DecodeOptions::default())
+}
 
-    if !bytes_to_decode.is_empty()
-        && bytes_to_decode
-            .iter()
-            .any(|&b| moss_protocol::MossWord::UNIT_FRAME_HEADER_RANGE.contains(&b))
-    {
-        remainder = Some(bytes_to_decode.to_vec());
-    }
+/// Decodes N events from a file-like object like [decode_n_events_from_reader], dropping hits and packets and handling errors according to the [DecodeOptions].
+pub fn decode_n_events_from_reader_with(
+    reader: &PyAny,
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+    options: DecodeOptions,
+) -> PyResult<List_MossPackets> {
+    source::decode_n(
+        PyFileLike::new(reader.py(), reader.into())?,
+        take,
+        skip,
+        prepend_buffer,
+        options,
+    )
+}
 
-    if moss_packets.is_empty() {
-        Ok((None, remainder))
-    } else {
-        Ok((Some(moss_packets), remainder))
-    }
+/// Decodes N events from a file-like object, see [decode_n_events_from_file].
+#[pyfunction]
+#[pyo3(name = "decode_n_events_from_reader", signature = (reader, take, skip=None, prepend_buffer=None, mask=None, filter=None, error_policy=None, prefix=None))]
+#[allow(clippy::too_many_arguments)] // One argument per Python keyword argument
+fn py_decode_n_events_from_reader(
+    reader: &PyAny,
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<List_MossPackets> {
+    decode_n_events_from_reader_with(
+        reader,
+        take,
+        skip,
+        prepend_buffer,
+        DecodeOptions::new(mask, filter, error_policy, prefix),
+    )
+}
+
+/// Decodes all events from a file-like object after skipping the first `skip` events, see [skip_n_take_all_from_file].
+pub fn skip_n_take_all_from_reader(
+    reader: &PyAny,
+    skip: usize,
+) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
+    skip_n_take_all_from_reader_with(reader, skip, DecodeOptions::default())
+}
+
+/// Skips N events of a file-like object and decodes the rest like [skip_n_take_all_from_reader], dropping hits and packets and handling errors according to the [DecodeOptions].
+pub fn skip_n_take_all_from_reader_with(
+    reader: &PyAny,
+    skip: usize,
+    options: DecodeOptions,
+) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
+    source::skip_n_take_all(PyFileLike::new(reader.py(), reader.into())?, skip, options)
+}
+
+/// Decodes all events from a file-like object after skipping the first `skip` events, see [skip_n_take_all_from_file].
+#[pyfunction]
+#[pyo3(name = "skip_n_take_all_from_reader", signature = (reader, skip, mask=None, filter=None, error_policy=None, prefix=None))]
+fn py_skip_n_take_all_from_reader(
+    reader: &PyAny,
+    skip: usize,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    error_policy: Option<ErrorPolicy>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
+    skip_n_take_all_from_reader_with(
+        reader,
+        skip,
+        DecodeOptions::new(mask, filter, error_policy, prefix),
+    )
 }
 
 #[pyfunction]
@@ -493,7 +1098,7 @@ pub fn debug_decode_all_events(
             Ok((new_moss_packet, trailer_idx, new_invalid_words)) => {
                 new_invalid_words.into_iter().for_each(|mut invalid_word| {
                     invalid_word.set_index_offset(last_trailer_idx);
-                    invalid_words.push(invalid_word);
+                    invalid_words.push(debug_decode::record_protocol_error(invalid_word));
                 });
                 last_trailer_idx += trailer_idx + 1;
                 moss_packets.push(new_moss_packet);
@@ -508,7 +1113,7 @@ pub fn debug_decode_all_events(
             Err((_parse_err, new_invalid_words)) => {
                 new_invalid_words.into_iter().for_each(|mut invalid_word| {
                     invalid_word.set_index_offset(last_trailer_idx);
-                    invalid_words.push(invalid_word);
+                    invalid_words.push(debug_decode::record_protocol_error(invalid_word));
                 });
                 break;
             }
@@ -534,23 +1139,80 @@ pub fn debug_decode_all_events(
 /// Doesn't check for invalid state transitions. Runs over errors when possible and instead returns a list of invalid words.
 ///
 /// Useful for attempting to extract as many packets and debug based on packet analysis.
+/// Takes no [ErrorPolicy] because decoding errors are already returned as invalid words.
 pub fn debug_decode_all_events_from_file(
     path: std::path::PathBuf,
 ) -> PyResult<(List_MossPackets, LastTrailerIdx, InvalidWordMsgs)> {
-    let bytes = std::fs::read(path).unwrap();
+    let bytes = decompress::read_file(path)?;
     debug_decode_all_events(&bytes)
 }
 
+#[pyfunction]
+/// Validates the hits of all MOSS events in the given bytes.
+/// Checks for duplicate pixels within an event, region headers out of order and hits that are not in the expected readout order.
+///
+/// Returns a list of [HitDiagnostic]s, an empty list means no issues were found.
+pub fn validate_all_events(bytes: &[u8]) -> PyResult<Vec<HitDiagnostic>> {
+    if bytes.len() < MINIMUM_EVENT_SIZE {
+        return Err(PyValueError::new_err(
+            "Received less than the minimum event size",
+        ));
+    }
+    Ok(hit_validation::validate_events(bytes))
+}
+
+#[pyfunction]
+/// Validates the hits of all MOSS events in a file, see [validate_all_events].
+///
+/// Takes no [ErrorPolicy] because events that fail strict decoding are still validated word by word.
+pub fn validate_all_events_from_file(path: std::path::PathBuf) -> PyResult<Vec<HitDiagnostic>> {
+    let bytes = decompress::read_file(path)?;
+    validate_all_events(&bytes)
+}
+
+#[pyfunction]
+/// Computes [RunStats] for the given bytes in a single pass.
+///
+/// Decoding errors do not stop the processing, they are counted and decoding resumes at the next Unit Frame Header.
+pub fn run_stats(bytes: &[u8]) -> RunStats {
+    let mut stats = RunStats::default();
+    let consumed = stats.accumulate(
+        &mut source::EventDecoder::new(ErrorPolicy::SkipBadEvents),
+        bytes,
+    );
+    stats.finish(&bytes[consumed..]);
+    stats
+}
+
+#[pyfunction]
+/// Computes [RunStats] for a file in a single pass, see [run_stats].
+///
+/// The file is read in chunks of 10 MiB until the end of the file is reached.
+/// Takes no [ErrorPolicy] because decoding errors are counted and skipped like [ErrorPolicy::SkipBadEvents], I/O errors are raised.
+pub fn run_stats_from_file(path: std::path::PathBuf) -> PyResult<RunStats> {
+    // Open file (get file descriptor), compressed files are decompressed on the fly
+    let file = decompress::open_file(path)?;
+    source::run_stats(file)
+}
+
+#[pyfunction]
+/// Computes [RunStats] for a file-like object in a single pass, see [run_stats].
+pub fn run_stats_from_reader(py: Python, reader: PyObject) -> PyResult<RunStats> {
+    source::run_stats(PyFileLike::new(py, reader)?)
+}
+
 mod rust_only {
     use pyo3::exceptions::PyValueError;
     use pyo3::PyResult;
 
-    use crate::decode_hits_fsm::extract_hits;
+    use crate::hit_decoder::{extract_hits, visit_hits};
+    use crate::logging::log_parse_error;
     use crate::moss_protocol::MossWord;
     use crate::parse_error::{ParseError, ParseErrorKind};
-    use crate::{MossPacket, Tuple_MossPacket_LastTrailerIdx};
+    use crate::scan;
+    use crate::{DecodeFilter, HitVisitor, MossPacket, PixelMask, MINIMUM_EVENT_SIZE};
 
-    /// Functions that are only used in Rust and not exposed to Python.
+    // Functions that are only used in Rust and not exposed to Python.
 
     const MIN_PREALLOC: usize = 10;
     #[inline]
@@ -571,22 +1233,120 @@ mod rust_only {
         Ok(prealloc)
     }
 
+    /// Drops the masked hits from the packet if a mask is given.
+    #[inline]
+    pub(crate) fn apply_mask(mask: Option<&PixelMask>, moss_packet: &mut MossPacket) {
+        if let Some(mask) = mask {
+            mask.apply(moss_packet);
+        }
+    }
+
+    /// Drops the masked hits from the packet if a mask is given, then returns the packet if it is selected by the filter.
+    #[inline]
+    pub(crate) fn select_packet(
+        mut moss_packet: MossPacket,
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) -> Option<MossPacket> {
+        apply_mask(mask, &mut moss_packet);
+        filter
+            .is_none_or(|filter| filter.apply(&mut moss_packet))
+            .then_some(moss_packet)
+    }
+
+    /// Drops the masked hits from the packet if a mask is given, then pushes the packet to `moss_packets` if it is selected by the filter.
+    #[inline]
+    pub(crate) fn push_packet(
+        moss_packets: &mut Vec<MossPacket>,
+        moss_packet: MossPacket,
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) {
+        moss_packets.extend(select_packet(moss_packet, mask, filter));
+    }
+
     /// If a prepend buffer is given, it is prepended to `bytes` and the packet is extracted from the combined buffer.
     /// If no prepend buffer is given, the packet is extracted from `bytes`.
+    ///
+    /// Returns the packet and the indices of its Unit Frame Header and Unit Frame Trailer in `bytes`,
+    /// the header index is 0 if the header is in the prepend buffer.
+    /// Errors are logged with [log_parse_error].
     #[inline]
     pub(crate) fn extract_packet_from_buf(
         bytes: &[u8],
         prepend_bytes: Option<Vec<u8>>,
-    ) -> Result<Tuple_MossPacket_LastTrailerIdx, ParseError> {
-        // Collect bytes from `bytes` until a header is seen
+    ) -> Result<(MossPacket, usize, usize), ParseError> {
+        extract_packet_with_prepend(bytes, prepend_bytes).inspect_err(log_parse_error)
+    }
+
+    /// Like [extract_packet_from_buf], but first skips the `prefix_len` bytes of the FPGA prefix preceding the event,
+    /// the prefix starts in the prepend buffer if one is given.
+    ///
+    /// The returned indices and the index of an error are relative to `bytes`, like with [extract_packet_from_buf].
+    #[inline]
+    pub(crate) fn extract_prefixed_packet(
+        bytes: &[u8],
+        prepend_bytes: Option<Vec<u8>>,
+        prefix_len: usize,
+    ) -> Result<(MossPacket, usize, usize), ParseError> {
+        if prefix_len == 0 {
+            return extract_packet_from_buf(bytes, prepend_bytes);
+        }
+        // The prefix is either in the prepend buffer or continues in `bytes`
+        let (prepend_bytes, prefix_len) = match prepend_bytes {
+            Some(prepend) if prepend.len() > prefix_len => {
+                (Some(prepend[prefix_len..].to_vec()), 0)
+            }
+            Some(prepend) => (None, prefix_len - prepend.len()),
+            None => (None, prefix_len),
+        };
+        let Some(event_bytes) = bytes.get(prefix_len..) else {
+            return Err(ParseError::new(
+                ParseErrorKind::EndOfBufferNoTrailer,
+                "Reached end of buffer in the FPGA prefix",
+                bytes.len(),
+            ))
+            .inspect_err(log_parse_error);
+        };
+        match extract_packet_from_buf(event_bytes, prepend_bytes) {
+            Ok((moss_packet, header_idx, trailer_idx)) => Ok((
+                moss_packet,
+                prefix_len + header_idx,
+                prefix_len + trailer_idx,
+            )),
+            Err(e) => Err(ParseError::new(
+                e.kind(),
+                e.message(),
+                prefix_len + e.err_index(),
+            )),
+        }
+    }
+
+    #[inline]
+    fn extract_packet_with_prepend(
+        bytes: &[u8],
+        prepend_bytes: Option<Vec<u8>>,
+    ) -> Result<(MossPacket, usize, usize), ParseError> {
+        // Complete the event started in `prepend_bytes` with the bytes up to and including the first trailer
         if let Some(mut prepend) = prepend_bytes {
             let prepend_count = prepend.len();
-            prepend.extend(
-                bytes
-                    .iter()
-                    .take_while(|b| **b != MossWord::UNIT_FRAME_TRAILER),
-            );
-            prepend.push(MossWord::UNIT_FRAME_TRAILER); // Add the trailer back since `take_while` is EXCLUSIVE
+            // The first byte may be the trailer of the previous event, like in `find_header_index`
+            if let Some(idx) = prepend
+                .iter()
+                .skip(1)
+                .position(|b| *b == MossWord::UNIT_FRAME_TRAILER)
+            {
+                return Err(ParseError::new(
+                    ParseErrorKind::ProtocolError,
+                    "Prepend buffer contains a Unit Frame Trailer, it can only contain the start of an event",
+                    idx + 1,
+                ));
+            }
+            let event_end = bytes
+                .iter()
+                .position(|b| *b == MossWord::UNIT_FRAME_TRAILER)
+                .map_or(bytes.len(), |trailer_idx| trailer_idx + 1);
+            prepend.extend_from_slice(&bytes[..event_end]);
             extract_packet(&prepend, prepend_count)
         } else {
             extract_packet(bytes, 0)
@@ -599,7 +1359,7 @@ mod rust_only {
     fn extract_packet(
         bytes: &[u8],
         prepend_byte_cnt: usize,
-    ) -> Result<Tuple_MossPacket_LastTrailerIdx, ParseError> {
+    ) -> Result<(MossPacket, usize, usize), ParseError> {
         // Check that everything before the first header is delimiter bytes
         //
         // Takes bytes while they are equal to the delimiter byte
@@ -613,6 +1373,7 @@ mod rust_only {
                     unit_id: bytes[header_idx] & 0xF,
                     hits,
                 },
+                header_idx.saturating_sub(prepend_byte_cnt),
                 bytes.len() - bytes_iter.len() - 1 - prepend_byte_cnt,
             )),
             Err(e) => Err(ParseError::new(
@@ -623,6 +1384,78 @@ mod rust_only {
         }
     }
 
+    /// Decodes the events in a shard of the bytes the same way as [decode_all_events](crate::decode_all_events),
+    /// returns the selected packets, the number of decoded events and the index after the last trailer.
+    ///
+    /// `bytes_after` is the number of bytes following the shard, decoding stops at the same place near the end of all the bytes as [decode_all_events](crate::decode_all_events).
+    /// On error the number of events decoded before the error is returned with it.
+    pub(super) fn decode_shard(
+        bytes: &[u8],
+        bytes_after: usize,
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) -> Result<(Vec<MossPacket>, usize, usize), (ParseError, usize)> {
+        let mut moss_packets = Vec::new();
+        let mut decoded_cnt = 0;
+        let mut last_trailer_idx = 0;
+        while last_trailer_idx < bytes.len()
+            && last_trailer_idx + MINIMUM_EVENT_SIZE + 1 < bytes.len() + bytes_after
+        {
+            match extract_packet_from_buf(&bytes[last_trailer_idx..], None) {
+                Ok((moss_packet, _, trailer_idx)) => {
+                    push_packet(&mut moss_packets, moss_packet, mask, filter);
+                    decoded_cnt += 1;
+                    last_trailer_idx += trailer_idx + 1;
+                }
+                Err(e) => return Err((e, decoded_cnt)),
+            }
+        }
+        Ok((moss_packets, decoded_cnt, last_trailer_idx))
+    }
+
+    /// Like [extract_packet] but passes the unit ID and hits to the `visitor` instead of collecting them in a [MossPacket], returns the index of the trailer byte.
+    ///
+    /// The hits are only visited if the event is complete, i.e. a trailer follows the header.
+    /// Errors are logged with [log_parse_error].
+    #[inline]
+    pub(crate) fn visit_packet(
+        bytes: &[u8],
+        visitor: &mut impl HitVisitor,
+    ) -> Result<usize, ParseError> {
+        visit_complete_packet(bytes, visitor).inspect_err(log_parse_error)
+    }
+
+    #[inline]
+    fn visit_complete_packet(
+        bytes: &[u8],
+        visitor: &mut impl HitVisitor,
+    ) -> Result<usize, ParseError> {
+        let header_idx = find_header_index(bytes)?;
+        // The trailer byte is never a valid data word, so an event without it is incomplete
+        if scan::find_trailer(&bytes[header_idx..]).is_none() {
+            return Err(ParseError::new(
+                ParseErrorKind::EndOfBufferNoTrailer,
+                "Reached end with no UNIT_FRAME_TRAILER",
+                bytes.len() - 1,
+            ));
+        }
+        let unit_id = bytes[header_idx] & 0xF;
+
+        visitor.on_packet_start(unit_id);
+        let mut bytes_iter = bytes.iter().skip(header_idx + 1);
+        match visit_hits(&mut bytes_iter, |hit| visitor.on_hit(unit_id, hit)) {
+            Ok(()) => {
+                visitor.on_packet_end(unit_id);
+                Ok(bytes.len() - bytes_iter.len() - 1)
+            }
+            Err(e) => Err(ParseError::new(
+                e.kind(),
+                &format_error_msg(e.message(), e.err_index() + 1, &bytes[header_idx..]),
+                header_idx + e.err_index() + 1,
+            )),
+        }
+    }
+
     // Check that everything before the first header is delimiter bytes
     //
     // Takes bytes while they are equal to the delimiter byte
@@ -656,6 +1489,10 @@ mod rust_only {
     ///
     /// Also includes a dump of the bytes from the header and 10 bytes past the error.
     fn format_error_msg(err_str: &str, err_idx: usize, bytes: &[u8]) -> String {
+        // The error can be at the end of the buffer, e.g. if it ends right after the header
+        let Some(error_byte) = bytes.get(err_idx) else {
+            return format!("{err_str}, reached end of buffer");
+        };
         format!(
         "{err_str}, got: 0x{error_byte:02X}. Dump from header and 10 bytes past error: {prev} [ERROR = {error_byte:02X}] {next}",
         prev = bytes
@@ -664,7 +1501,6 @@ mod rust_only {
             .map(|b| format!("{b:02X}"))
             .collect::<Vec<_>>()
             .join(" "),
-        error_byte = bytes[err_idx],
         next = bytes
             .iter()
             .skip(err_idx+1)
@@ -678,6 +1514,7 @@ mod rust_only {
     // i.e. if 8 packets are decoded successfully and the 9th packet fails, the error will be returned with 9.
     pub(crate) fn get_all_packets_from_buf(
         buf: &[u8],
+        mask: Option<&PixelMask>,
     ) -> Result<(Vec<MossPacket>, usize), (ParseError, usize)> {
         let prealloc = if buf.len() / 1024 > MIN_PREALLOC {
             buf.len() / 1024
@@ -688,7 +1525,8 @@ mod rust_only {
         let mut last_trailer_idx = 0;
         loop {
             match extract_packet_from_buf(&buf[last_trailer_idx..], None) {
-                Ok((moss_packet, trailer_idx)) => {
+                Ok((mut moss_packet, _, trailer_idx)) => {
+                    apply_mask(mask, &mut moss_packet);
                     moss_packets.push(moss_packet);
                     last_trailer_idx += trailer_idx + 1;
                 }
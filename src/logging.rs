@@ -0,0 +1,119 @@
+//! Logging of decoder internals with the `log` crate, bridged to Python's `logging` module.
+//!
+//! Protocol errors are logged at warn level, incomplete events at the end of a buffer at debug level,
+//! and every word read by the hit decoders together with the current decoder state at trace level.
+//! Decoding errors are logged to the `moss_decoder` logger, the other Python logger names follow the Rust module paths, e.g. `moss_decoder.decode_hits_fsm`.
+//! Rust's trace level is Python level 5.
+//!
+//! Logging is off by default so it costs nothing while decoding, [set_log_level] turns it on.
+use log::LevelFilter;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::parse_error::{ParseError, ParseErrorKind};
+
+/// The logger of decoding errors, the top-level `moss_decoder` logger in Python.
+const ERROR_TARGET: &str = "moss_decoder";
+
+/// Clears the cached Python loggers and levels of the installed logger.
+static RESET_HANDLE: OnceLock<pyo3_log::ResetHandle> = OnceLock::new();
+
+/// Installs a logger that forwards all records to Python's `logging` module, the records are then filtered by the level set with [set_log_level].
+pub(crate) fn init(py: Python) -> PyResult<()> {
+    let logger =
+        pyo3_log::Logger::new(py, pyo3_log::Caching::LoggersAndLevels)?.filter(LevelFilter::Trace);
+    // Installing fails if the module is initialized again, the first logger is kept
+    if let Ok(reset_handle) = logger.install() {
+        _ = RESET_HANDLE.set(reset_handle);
+    }
+    log::set_max_level(LevelFilter::Off);
+    Ok(())
+}
+
+/// A log level given as a name or as a Python `logging` level number.
+#[derive(Debug, FromPyObject)]
+pub enum LogLevel {
+    /// `off`, `error`, `warn`/`warning`, `info`, `debug` or `trace`, case insensitive.
+    Name(String),
+    /// A Python `logging` level, e.g. `logging.DEBUG`, 5 or lower is trace.
+    Number(u32),
+}
+
+impl LogLevel {
+    fn into_level_filter(self) -> PyResult<LevelFilter> {
+        match self {
+            Self::Name(name) if name.eq_ignore_ascii_case("warning") => Ok(LevelFilter::Warn),
+            Self::Name(name) if name.eq_ignore_ascii_case("critical") => Ok(LevelFilter::Error),
+            Self::Name(name) => LevelFilter::from_str(&name)
+                .map_err(|_| PyValueError::new_err(format!("Unknown log level: {name}"))),
+            Self::Number(0..=5) => Ok(LevelFilter::Trace),
+            Self::Number(6..=10) => Ok(LevelFilter::Debug),
+            Self::Number(11..=20) => Ok(LevelFilter::Info),
+            Self::Number(21..=30) => Ok(LevelFilter::Warn),
+            Self::Number(31..=50) => Ok(LevelFilter::Error),
+            Self::Number(_) => Ok(LevelFilter::Off),
+        }
+    }
+}
+
+/// Sets the most verbose level that is logged by the decoder, e.g. `"warn"` for protocol errors or `logging.DEBUG`.
+///
+/// The records are passed to Python's `logging` module, which must also be configured to handle the level.
+/// The effective levels of the Python loggers are cached, call this function again after changing them.
+#[pyfunction]
+pub fn set_log_level(level: LogLevel) -> PyResult<()> {
+    log::set_max_level(level.into_level_filter()?);
+    if let Some(reset_handle) = RESET_HANDLE.get() {
+        reset_handle.reset();
+    }
+    Ok(())
+}
+
+/// Returns the most verbose level that is logged by the decoder, `"OFF"` if logging is off.
+#[pyfunction]
+pub fn get_log_level() -> String {
+    log::max_level().to_string()
+}
+
+/// Logs a decoding error, protocol errors and invalid delimiters at warn level.
+///
+/// Missing headers and trailers are logged at debug level, as they are expected at the end of a buffer that is decoded in chunks.
+pub(crate) fn log_parse_error(e: &ParseError) {
+    match e.kind() {
+        ParseErrorKind::ProtocolError | ParseErrorKind::InvalidDelimiter => {
+            log::warn!(target: ERROR_TARGET, "{e}")
+        }
+        ParseErrorKind::NoHeaderFound | ParseErrorKind::EndOfBufferNoTrailer => {
+            log::debug!(target: ERROR_TARGET, "{e}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_level_into_level_filter() {
+        for (level, expect) in [
+            (LogLevel::Name("OFF".into()), LevelFilter::Off),
+            (LogLevel::Name("warning".into()), LevelFilter::Warn),
+            (LogLevel::Name("Warn".into()), LevelFilter::Warn),
+            (LogLevel::Name("critical".into()), LevelFilter::Error),
+            (LogLevel::Name("trace".into()), LevelFilter::Trace),
+            (LogLevel::Number(5), LevelFilter::Trace),
+            (LogLevel::Number(10), LevelFilter::Debug),
+            (LogLevel::Number(20), LevelFilter::Info),
+            (LogLevel::Number(30), LevelFilter::Warn),
+            (LogLevel::Number(50), LevelFilter::Error),
+            (LogLevel::Number(60), LevelFilter::Off),
+        ] {
+            assert_eq!(level.into_level_filter().unwrap(), expect);
+        }
+        assert!(LogLevel::Name("verbose".into())
+            .into_level_filter()
+            .is_err());
+    }
+}
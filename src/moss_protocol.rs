@@ -1,8 +1,10 @@
 #![allow(dead_code)]
 //! Module containing the MOSS readout protocol and basic structures to analyze the data.
+pub mod idle_counts;
 pub mod moss_hit;
 pub mod moss_packet;
 pub mod test_util;
+pub use idle_counts::{DmuMode, DmuModeChange, IdleCounts};
 pub use moss_hit::MossHit;
 pub use moss_packet::MossPacket;
 
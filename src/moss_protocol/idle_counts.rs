@@ -0,0 +1,234 @@
+//! Accounting of the idle words in a MOSS event, the type of idle word reveals the DMU readout mode of the chip.
+use pyo3::prelude::*;
+use std::fmt::{write, Display};
+
+use super::MossWord;
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The DMU readout mode indicated by the idle word pattern.
+pub enum DmuMode {
+    /// `0xFF` idle words, used in long edge readout (no backbone).
+    NoBackbone,
+    /// `0xF0` idle words, LEC readout in 4-bit DMU mode.
+    FourBit,
+    /// `0xFC` idle words, LEC readout in 2-bit DMU mode.
+    TwoBit,
+    /// `0xFE` idle words, LEC readout in 1-bit DMU mode.
+    OneBit,
+}
+
+#[pyclass(get_all)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The number of idle words of each type in a MOSS event.
+pub struct IdleCounts {
+    /// Number of `0xFF` idle words.
+    pub no_backbone: u32,
+    /// Number of `0xF0` idle words.
+    pub four_bit: u32,
+    /// Number of `0xFC` idle words.
+    pub two_bit: u32,
+    /// Number of `0xFE` idle words.
+    pub one_bit: u32,
+}
+
+#[pymethods]
+impl IdleCounts {
+    /// Total number of idle words.
+    pub fn total(&self) -> u32 {
+        self.no_backbone + self.four_bit + self.two_bit + self.one_bit
+    }
+
+    /// The DMU mode indicated by the idle words, `None` if there are no idle words or if they are mixed.
+    pub fn dmu_mode(&self) -> Option<DmuMode> {
+        let mut observed_modes = self.observed_modes();
+        match (observed_modes.next(), observed_modes.next()) {
+            (Some(mode), None) => Some(mode),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if idle words of more than one type were seen.
+    pub fn is_mixed(&self) -> bool {
+        self.observed_modes().count() > 1
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+
+    /// Returns a string representation of the [IdleCounts] instance.
+    pub fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl IdleCounts {
+    /// Counts the idle words in the bytes of an event.
+    ///
+    /// Idle words are never valid data words, so counting them doesn't require decoding the event.
+    #[inline]
+    pub fn from_event_bytes(bytes: &[u8]) -> Self {
+        let mut counts = Self::default();
+        for b in bytes {
+            counts.add(*b);
+        }
+        counts
+    }
+
+    /// Counts the byte if it is an idle word.
+    #[inline]
+    pub fn add(&mut self, byte: u8) {
+        match byte {
+            MossWord::IDLE_NO_BACKBONE => self.no_backbone += 1,
+            MossWord::IDLE_FOUR_BIT => self.four_bit += 1,
+            MossWord::IDLE_TWO_BIT => self.two_bit += 1,
+            MossWord::IDLE_ONE_BIT => self.one_bit += 1,
+            _ => (),
+        }
+    }
+
+    fn observed_modes(&self) -> impl Iterator<Item = DmuMode> {
+        [
+            (self.no_backbone, DmuMode::NoBackbone),
+            (self.four_bit, DmuMode::FourBit),
+            (self.two_bit, DmuMode::TwoBit),
+            (self.one_bit, DmuMode::OneBit),
+        ]
+        .into_iter()
+        .filter(|(cnt, _)| *cnt > 0)
+        .map(|(_, mode)| mode)
+    }
+}
+
+impl Display for IdleCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "idle 0xFF: {no_backbone} 0xF0: {four_bit} 0xFC: {two_bit} 0xFE: {one_bit}",
+                no_backbone = self.no_backbone,
+                four_bit = self.four_bit,
+                two_bit = self.two_bit,
+                one_bit = self.one_bit,
+            ),
+        )
+    }
+}
+
+#[pyclass(get_all)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A change of DMU mode between two packets, or a packet with mixed idle words.
+pub struct DmuModeChange {
+    /// The index of the packet where the change was observed, starting from 0.
+    pub packet_idx: usize,
+    /// The DMU mode before the packet, `None` if this is the first packet with idle words.
+    pub previous: Option<DmuMode>,
+    /// The DMU mode of the packet, `None` if the packet has mixed idle words.
+    pub new: Option<DmuMode>,
+}
+
+#[pymethods]
+impl DmuModeChange {
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+/// Tracks the DMU mode over a sequence of packets and flags unexpected mode changes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DmuModeTracker {
+    current_mode: Option<DmuMode>,
+}
+
+impl DmuModeTracker {
+    /// Updates the tracked mode with the idle counts of the next packet.
+    ///
+    /// Returns a [DmuModeChange] if the packet has mixed idle words or a different mode than the previous packets.
+    /// Packets without idle words don't change the mode.
+    pub(crate) fn update(
+        &mut self,
+        packet_idx: usize,
+        idle_counts: &IdleCounts,
+    ) -> Option<DmuModeChange> {
+        if idle_counts.is_mixed() {
+            return Some(DmuModeChange {
+                packet_idx,
+                previous: self.current_mode,
+                new: None,
+            });
+        }
+        let new_mode = idle_counts.dmu_mode()?;
+        let previous = self.current_mode.replace(new_mode);
+        match previous {
+            Some(previous_mode) if previous_mode != new_mode => Some(DmuModeChange {
+                packet_idx,
+                previous,
+                new: Some(new_mode),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_idle_counts_mode() {
+        let counts = IdleCounts::from_event_bytes(&[0xD1, 0xC0, 0xFF, 0xFF, 0x00, 0xE0]);
+        assert_eq!(counts.no_backbone, 2);
+        assert_eq!(counts.total(), 2);
+        assert_eq!(counts.dmu_mode(), Some(DmuMode::NoBackbone));
+        assert!(!counts.is_mixed());
+
+        let counts = IdleCounts::from_event_bytes(&[0xD1, 0xF0, 0xFC, 0xE0]);
+        assert_eq!(counts.dmu_mode(), None);
+        assert!(counts.is_mixed());
+
+        assert_eq!(IdleCounts::default().dmu_mode(), None);
+    }
+
+    #[test]
+    fn test_dmu_mode_tracker() {
+        let four_bit = IdleCounts {
+            four_bit: 3,
+            ..Default::default()
+        };
+        let one_bit = IdleCounts {
+            one_bit: 1,
+            ..Default::default()
+        };
+        let mixed = IdleCounts {
+            four_bit: 1,
+            one_bit: 1,
+            ..Default::default()
+        };
+
+        let mut tracker = DmuModeTracker::default();
+        assert_eq!(tracker.update(0, &four_bit), None);
+        assert_eq!(tracker.update(1, &IdleCounts::default()), None);
+        assert_eq!(tracker.update(2, &four_bit), None);
+        assert_eq!(
+            tracker.update(3, &one_bit),
+            Some(DmuModeChange {
+                packet_idx: 3,
+                previous: Some(DmuMode::FourBit),
+                new: Some(DmuMode::OneBit)
+            })
+        );
+        assert_eq!(
+            tracker.update(4, &mixed),
+            Some(DmuModeChange {
+                packet_idx: 4,
+                previous: Some(DmuMode::OneBit),
+                new: None
+            })
+        );
+    }
+}
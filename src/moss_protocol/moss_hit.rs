@@ -1,11 +1,15 @@
 //! struct representation of a single hit from a MOSS region.
 use pyo3::prelude::*;
 use pyo3::pyclass::CompareOp;
+use pyo3::types::PyDict;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::write;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
-#[pyclass(get_all)]
-#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[pyclass(get_all, module = "moss_decoder")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A single hit from a MOSS region.
 pub struct MossHit {
     /// The region ID of the hit.
@@ -43,10 +47,47 @@ impl MossHit {
         self.to_string()
     }
 
+    /// Compares hits by region, then row, then column.
     fn __richcmp__(&self, other: &Self, op: CompareOp) -> bool {
-        op.matches(self.region.cmp(&other.region))
-            && op.matches(self.row.cmp(&other.row))
-            && op.matches(self.column.cmp(&other.column))
+        op.matches(self.cmp(other))
+    }
+
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Pickles the hit as its `(region, row, column)` constructor arguments.
+    fn __reduce__(slf: &PyCell<Self>) -> (PyObject, (u8, u16, u16)) {
+        (slf.get_type().into(), slf.borrow().to_tuple())
+    }
+
+    /// Returns the hit as a `(region, row, column)` tuple.
+    pub fn to_tuple(&self) -> (u8, u16, u16) {
+        (self.region, self.row, self.column)
+    }
+
+    /// Serializes the hit to JSON, requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> PyResult<String> {
+        crate::json::to_json(self)
+    }
+
+    /// Deserializes a hit from JSON as written by [MossHit::to_json], requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    #[staticmethod]
+    pub fn from_json(json: &str) -> PyResult<Self> {
+        crate::json::from_json(json)
+    }
+
+    /// Returns the hit as a dict with the keys `region`, `row` and `column`.
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("region", self.region)?;
+        dict.set_item("row", self.row)?;
+        dict.set_item("column", self.column)?;
+        Ok(dict)
     }
 }
 
@@ -75,4 +116,35 @@ mod tests {
         println!("{moss_hit}");
         println!("{str}", str = moss_hit.__str__());
     }
+
+    #[test]
+    fn test_moss_hit_ordering_is_lexicographic() {
+        let hit = MossHit::new(1, 5, 5);
+        let lower_row_higher_column = MossHit::new(1, 4, 9);
+        assert!(hit.__richcmp__(&lower_row_higher_column, CompareOp::Gt));
+        assert!(lower_row_higher_column.__richcmp__(&hit, CompareOp::Lt));
+        assert!(!hit.__richcmp__(&lower_row_higher_column, CompareOp::Eq));
+        assert!(hit.__richcmp__(&MossHit::new(1, 5, 5), CompareOp::Eq));
+        assert!(hit.__richcmp__(&MossHit::new(0, 9, 9), CompareOp::Ge));
+        assert_eq!(hit.__hash__(), MossHit::new(1, 5, 5).__hash__());
+    }
+
+    #[test]
+    fn test_moss_hit_to_tuple_and_dict() {
+        pyo3::prepare_freethreaded_python();
+        let hit = MossHit::new(2, 100, 200);
+        assert_eq!(hit.to_tuple(), (2, 100, 200));
+        Python::with_gil(|py| {
+            let dict = hit.to_dict(py).unwrap();
+            assert_eq!(dict.len(), 3);
+            assert_eq!(
+                dict.get_item("row")
+                    .unwrap()
+                    .unwrap()
+                    .extract::<u16>()
+                    .unwrap(),
+                100
+            );
+        });
+    }
 }
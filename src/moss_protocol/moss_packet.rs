@@ -1,11 +1,19 @@
 //! MOSS packet structure implementation.
+use pyo3::exceptions::PyIndexError;
+use pyo3::types::{PyDict, PyTuple};
 use pyo3::{prelude::*, pyclass::CompareOp};
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::{write, Display};
+use std::hash::{Hash, Hasher};
 
 use super::MossHit;
 
-#[pyclass(get_all)]
-#[derive(Debug, Default, Clone, PartialEq)]
+/// The pickled state of a [MossPacket] besides the unit ID: the hits as `(region, row, column)` tuples.
+type PacketState = Vec<(u8, u16, u16)>;
+
+#[pyclass(get_all, module = "moss_decoder")]
+#[derive(Debug, Default, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A single MOSS packet with the associated [MossHit]s.
 pub struct MossPacket {
     /// The unit ID of the packet.
@@ -38,8 +46,124 @@ impl MossPacket {
         self.to_string()
     }
 
+    /// Compares packets by unit ID, then lexicographically by their hits, the same as the Rust [Ord].
     fn __richcmp__(&self, other: &Self, op: CompareOp) -> bool {
-        op.matches(self.unit_id.cmp(&other.unit_id)) && op.matches(self.hits.cmp(&other.hits))
+        op.matches(self.cmp(other))
+    }
+
+    /// Hashes the packet with its Rust [Hash], consistent with `==`.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Returns the number of hits in the packet.
+    fn __len__(&self) -> usize {
+        self.hits.len()
+    }
+
+    /// Returns a single hit without copying the others, negative indices count from the end.
+    fn __getitem__(&self, index: isize) -> PyResult<MossHit> {
+        let index = if index < 0 {
+            index + self.hits.len() as isize
+        } else {
+            index
+        };
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.hits.get(index).copied())
+            .ok_or_else(|| PyIndexError::new_err("MossPacket index out of range"))
+    }
+
+    /// Iterates over the hits without copying them into a list first.
+    fn __iter__(slf: PyRef<'_, Self>) -> MossPacketIter {
+        MossPacketIter {
+            packet: slf.into(),
+            index: 0,
+        }
+    }
+
+    /// Pickles the packet as its unit ID and the state from [MossPacket::__getstate__].
+    fn __reduce__(slf: &PyCell<Self>) -> (PyObject, (u8,), PacketState) {
+        let packet = slf.borrow();
+        (
+            slf.get_type().into(),
+            (packet.unit_id,),
+            packet.__getstate__(),
+        )
+    }
+
+    /// Returns the hits as plain tuples, see [PacketState].
+    pub fn __getstate__(&self) -> PacketState {
+        self.hits.iter().map(MossHit::to_tuple).collect()
+    }
+
+    /// Restores the hits returned by [MossPacket::__getstate__].
+    pub fn __setstate__(&mut self, state: PacketState) {
+        self.hits = state
+            .into_iter()
+            .map(|(region, row, column)| MossHit {
+                region,
+                row,
+                column,
+            })
+            .collect();
+    }
+
+    /// Returns the packet as a `(unit_id, hits)` tuple with the hits as a tuple of `(region, row, column)` tuples, so the result is hashable.
+    pub fn to_tuple<'py>(&self, py: Python<'py>) -> (u8, &'py PyTuple) {
+        (
+            self.unit_id,
+            PyTuple::new(py, self.hits.iter().map(MossHit::to_tuple)),
+        )
+    }
+
+    /// Serializes the packet to JSON, requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> PyResult<String> {
+        crate::json::to_json(self)
+    }
+
+    /// Deserializes a packet from JSON as written by [MossPacket::to_json], requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    #[staticmethod]
+    pub fn from_json(json: &str) -> PyResult<Self> {
+        crate::json::from_json(json)
+    }
+
+    /// Returns the packet as a dict with the keys `unit_id` and `hits`, the hits are dicts as returned by [MossHit::to_dict].
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("unit_id", self.unit_id)?;
+        dict.set_item(
+            "hits",
+            self.hits
+                .iter()
+                .map(|hit| hit.to_dict(py))
+                .collect::<PyResult<Vec<_>>>()?,
+        )?;
+        Ok(dict)
+    }
+}
+
+/// Iterator over the [MossHit]s of a [MossPacket].
+#[pyclass]
+pub struct MossPacketIter {
+    packet: Py<MossPacket>,
+    index: usize,
+}
+
+#[pymethods]
+impl MossPacketIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> Option<MossHit> {
+        let hit = self.packet.borrow(py).hits.get(self.index).copied()?;
+        self.index += 1;
+        Some(hit)
     }
 }
 
@@ -60,6 +184,7 @@ impl Display for MossPacket {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::moss_protocol::test_util::packet_with_hits;
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -76,4 +201,38 @@ mod tests {
             assert_eq!(p.unit_id, i as u8);
         });
     }
+
+    #[test]
+    fn test_moss_packet_ordering_is_lexicographic() {
+        let packet = packet_with_hits(1, &[(0, 5, 5), (1, 0, 0)]);
+        let fewer_higher_hits = packet_with_hits(1, &[(0, 6, 0)]);
+        assert!(packet.__richcmp__(&fewer_higher_hits, CompareOp::Lt));
+        assert!(packet.__richcmp__(&fewer_higher_hits, CompareOp::Ne));
+        assert!(packet.__richcmp__(&packet_with_hits(0, &[(3, 9, 9)]), CompareOp::Gt));
+        assert!(packet.__richcmp__(&packet.clone(), CompareOp::Le));
+        assert_eq!(packet.__hash__(), packet.clone().__hash__());
+        assert!(packet < fewer_higher_hits);
+        assert_ne!(packet, fewer_higher_hits);
+    }
+
+    #[test]
+    fn test_moss_packet_state_round_trip() {
+        let packet = packet_with_hits(3, &[(0, 1, 2), (3, 4, 5)]);
+
+        let mut restored = MossPacket::new(packet.unit_id);
+        restored.__setstate__(packet.__getstate__());
+        assert_eq!(restored, packet);
+        assert_eq!(packet.__getstate__(), [(0, 1, 2), (3, 4, 5)]);
+    }
+
+    #[test]
+    fn test_moss_packet_getitem() {
+        pyo3::prepare_freethreaded_python();
+        let packet = packet_with_hits(3, &[(0, 1, 2), (3, 4, 5)]);
+        assert_eq!(packet.__len__(), 2);
+        assert_eq!(packet.__getitem__(0).unwrap(), packet.hits[0]);
+        assert_eq!(packet.__getitem__(-1).unwrap(), packet.hits[1]);
+        assert!(packet.__getitem__(2).is_err());
+        assert!(packet.__getitem__(-3).is_err());
+    }
 }
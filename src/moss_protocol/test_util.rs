@@ -1,5 +1,6 @@
 //! Utility for testing Rust code that uses the MOSS protocol.
 #![allow(dead_code, missing_docs)]
+use crate::{MossHit, MossPacket};
 
 pub const IDLE: u8 = 0xFF;
 pub const UNIT_FRAME_TRAILER: u8 = 0xE0;
@@ -183,3 +184,50 @@ pub fn fake_event_protocol_error_fb_in_idle() -> Vec<u8> {
         UNIT_FRAME_TRAILER, // index 34
     ]
 }
+
+/// Creates a packet with the given `(region, row, column)` hits.
+pub fn packet_with_hits(unit_id: u8, hits: &[(u8, u16, u16)]) -> MossPacket {
+    let mut packet = MossPacket::new(unit_id);
+    packet.hits = hits
+        .iter()
+        .map(|&(region, row, column)| MossHit {
+            region,
+            row,
+            column,
+        })
+        .collect();
+    packet
+}
+
+/// Encodes a hit as the 3 data words `DATA_0`, `DATA_1` and `DATA_2`.
+pub fn encode_hit(hit: &MossHit) -> [u8; 3] {
+    [
+        (hit.row >> 3) as u8,
+        0b0100_0000 | ((hit.row & 0b111) << 3) as u8 | (hit.column >> 6) as u8,
+        0b1000_0000 | (hit.column & 0b11_1111) as u8,
+    ]
+}
+
+/// Encodes a packet as a MOSS event with all 4 region headers, the inverse of decoding.
+///
+/// The hits must be ordered by region, `idles_after_hit[i]` idle words are inserted after hit `i` (none if out of bounds).
+pub fn encode_event(packet: &MossPacket, idles_after_hit: &[usize]) -> Vec<u8> {
+    assert!(
+        packet.hits.windows(2).all(|w| w[0].region <= w[1].region),
+        "hits must be ordered by region"
+    );
+    let mut event = vec![0xD0 | packet.unit_id];
+    let mut hits = packet.hits.iter().enumerate().peekable();
+    for region in 0..4 {
+        event.push(REGION_HEADER_0 | region);
+        while let Some((i, hit)) = hits.next_if(|(_, hit)| hit.region == region) {
+            event.extend_from_slice(&encode_hit(hit));
+            event.extend(std::iter::repeat_n(
+                IDLE,
+                idles_after_hit.get(i).copied().unwrap_or(0),
+            ));
+        }
+    }
+    event.push(UNIT_FRAME_TRAILER);
+    event
+}
@@ -0,0 +1,321 @@
+//! Decoding MOSS data directly from a TCP stream or UDP datagrams sent by the readout FPGA.
+//!
+//! Bytes are buffered and decoded like the chunks of a file, any event split across reads is completed by the following reads.
+use pyo3::exceptions::{PyConnectionError, PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::io::Read;
+use std::net::{TcpStream, UdpSocket};
+use std::time::Duration;
+
+use crate::source::PacketBuffer;
+use crate::{DecodeFilter, FpgaPrefix, MossPacket, PixelMask};
+
+/// Maximum size of a UDP datagram, also used as the size of a single TCP read.
+const READ_BUFFER_SIZE: usize = 65536;
+
+#[derive(Debug)]
+enum NetSource {
+    Tcp(TcpStream),
+    Udp {
+        socket: UdpSocket,
+        header_len: usize,
+    },
+}
+
+impl NetSource {
+    /// Reads the next chunk of MOSS data into `buf`, returns the range of `buf` with the data or `None` at the end of the stream.
+    ///
+    /// The stream ends when the TCP connection is closed or when a read times out, interrupted reads are retried.
+    /// Empty UDP datagrams and datagrams shorter than the header are skipped with a warning, as UDP has no end of stream.
+    fn read(&mut self, buf: &mut [u8]) -> PyResult<Option<std::ops::Range<usize>>> {
+        let result = match self {
+            Self::Tcp(stream) => loop {
+                match stream.read(buf) {
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    result => {
+                        break result.map(|bytes_read| (bytes_read > 0).then_some(0..bytes_read))
+                    }
+                }
+            },
+            Self::Udp { socket, header_len } => loop {
+                match socket.recv(buf) {
+                    Ok(0) => log::warn!("Skipped empty datagram"),
+                    Ok(bytes_read) if bytes_read < *header_len => log::warn!(
+                        "Skipped datagram of {bytes_read} bytes, shorter than the {header_len} byte header"
+                    ),
+                    result => break result.map(|bytes_read| Some(*header_len..bytes_read)),
+                }
+            },
+        };
+        match result {
+            Ok(range) => Ok(range),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(PyIOError::new_err(e.to_string())),
+        }
+    }
+}
+
+/// An iterator over the [MossPacket]s received from a network socket, created by [decode_tcp_stream] or [decode_udp_stream].
+///
+/// Packets are yielded as soon as they are complete, the iteration blocks while waiting for data.
+#[pyclass]
+#[derive(Debug)]
+pub struct PacketStream {
+    source: NetSource,
+    mask: Option<PixelMask>,
+    filter: Option<DecodeFilter>,
+    read_buf: Vec<u8>,
+    packet_buffer: PacketBuffer,
+    finished: bool,
+}
+
+#[pymethods]
+impl PacketStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<MossPacket>> {
+        py.allow_threads(|| self.next_packet())
+    }
+}
+
+impl PacketStream {
+    fn new(
+        source: NetSource,
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+        prefix: Option<&FpgaPrefix>,
+    ) -> Self {
+        Self {
+            source,
+            mask: mask.cloned(),
+            filter: filter.copied(),
+            read_buf: vec![0; READ_BUFFER_SIZE],
+            packet_buffer: PacketBuffer::new(prefix),
+            finished: false,
+        }
+    }
+
+    /// Returns the next packet, reading from the socket until a complete event is received.
+    ///
+    /// Returns `None` at the end of the stream, an incomplete event at the end of the stream is dropped with a warning.
+    pub fn next_packet(&mut self) -> PyResult<Option<MossPacket>> {
+        loop {
+            if let Some(next_packet) = self.packet_buffer.next_packet() {
+                // Decoding stops at the first error
+                self.finished |= next_packet.is_err();
+                return Ok(Some(next_packet?));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            match self.source.read(&mut self.read_buf)? {
+                Some(range) => self.packet_buffer.extend(
+                    &self.read_buf[range],
+                    self.mask.as_ref(),
+                    self.filter.as_ref(),
+                ),
+                None => {
+                    let pending_len = self.packet_buffer.pending_len();
+                    if pending_len > 0 {
+                        log::warn!(
+                            "Dropped incomplete event of {pending_len} bytes at the end of the stream"
+                        );
+                    }
+                    self.finished = true;
+                }
+            }
+        }
+    }
+}
+
+fn timeout_duration(timeout: Option<f64>) -> PyResult<Option<Duration>> {
+    timeout
+        .map(|secs| {
+            Duration::try_from_secs_f64(secs)
+                .ok()
+                .filter(|d| !d.is_zero())
+                .ok_or_else(|| {
+                    PyValueError::new_err("timeout must be a positive number of seconds")
+                })
+        })
+        .transpose()
+}
+
+/// Connects to the TCP server at `address` (e.g. `"192.168.1.10:5000"`) and returns an iterator over the [MossPacket]s received.
+///
+/// The iteration ends when the connection is closed or, if a `timeout` in seconds is given, when no data is received within the timeout.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter].
+/// Optionally skips the FPGA prefix with the given [FpgaPrefix] layout preceding each event.
+#[pyfunction]
+pub fn decode_tcp_stream(
+    address: &str,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    timeout: Option<f64>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<PacketStream> {
+    let stream =
+        TcpStream::connect(address).map_err(|e| PyConnectionError::new_err(e.to_string()))?;
+    stream
+        .set_read_timeout(timeout_duration(timeout)?)
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    Ok(PacketStream::new(
+        NetSource::Tcp(stream),
+        mask,
+        filter,
+        prefix,
+    ))
+}
+
+/// Binds a UDP socket to `address` (e.g. `"0.0.0.0:5000"`) and returns an iterator over the [MossPacket]s received.
+///
+/// The first `header_len` bytes of each datagram are a header added by the sender and are stripped before decoding.
+/// UDP has no end of stream, so the iteration only ends if a `timeout` in seconds is given and no datagram is received within the timeout.
+/// Empty datagrams and datagrams shorter than the header are skipped and logged as warnings.
+///
+/// Optionally drops hits on pixels in the given [PixelMask].
+/// Optionally drops packets and hits that are not selected by the given [DecodeFilter].
+/// Optionally skips the FPGA prefix with the given [FpgaPrefix] layout preceding each event, the prefix follows the datagram header.
+#[pyfunction]
+pub fn decode_udp_stream(
+    address: &str,
+    header_len: usize,
+    mask: Option<&PixelMask>,
+    filter: Option<&DecodeFilter>,
+    timeout: Option<f64>,
+    prefix: Option<&FpgaPrefix>,
+) -> PyResult<PacketStream> {
+    let socket = UdpSocket::bind(address).map_err(|e| PyConnectionError::new_err(e.to_string()))?;
+    socket
+        .set_read_timeout(timeout_duration(timeout)?)
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    Ok(PacketStream::new(
+        NetSource::Udp { socket, header_len },
+        mask,
+        filter,
+        prefix,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use crate::rust_only;
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_tcp_stream_split_events() {
+        pyo3::prepare_freethreaded_python();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let sender = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let events = [fake_event_simple(), fake_event_simple()].concat();
+            // Split the events across several writes
+            for chunk in events.chunks(5) {
+                stream.write_all(chunk).unwrap();
+                stream.flush().unwrap();
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        let mut stream = decode_tcp_stream(&address, None, None, Some(5.0), None).unwrap();
+        let (expect_packet, _, _) =
+            rust_only::extract_packet_from_buf(&fake_event_simple(), None).unwrap();
+        assert_eq!(stream.next_packet().unwrap(), Some(expect_packet.clone()));
+        assert_eq!(stream.next_packet().unwrap(), Some(expect_packet));
+        sender.join().unwrap();
+        assert_eq!(stream.next_packet().unwrap(), None);
+    }
+
+    #[test]
+    fn test_tcp_stream_drops_incomplete_last_event() {
+        pyo3::prepare_freethreaded_python();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let event = fake_event_simple();
+        let truncated_len = event.len() / 2;
+        let sender = std::thread::spawn({
+            let event = event.clone();
+            move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream.write_all(&event).unwrap();
+                stream.write_all(&event[..truncated_len]).unwrap();
+            }
+        });
+
+        let mut stream = decode_tcp_stream(&address, None, None, Some(5.0), None).unwrap();
+        let (expect_packet, _, _) = rust_only::extract_packet_from_buf(&event, None).unwrap();
+        assert_eq!(stream.next_packet().unwrap(), Some(expect_packet));
+        sender.join().unwrap();
+        assert_eq!(stream.next_packet().unwrap(), None);
+        assert_eq!(stream.packet_buffer.pending_len(), truncated_len);
+    }
+
+    #[test]
+    fn test_udp_stream_strips_header() {
+        pyo3::prepare_freethreaded_python();
+        let mut stream = decode_udp_stream("127.0.0.1:0", 4, None, None, Some(0.2), None).unwrap();
+        let NetSource::Udp { socket, .. } = &stream.source else {
+            unreachable!()
+        };
+        let address = socket.local_addr().unwrap();
+
+        let event = fake_event_simple();
+        let (first_half, second_half) = event.split_at(event.len() / 2);
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for (seq_num, payload) in [first_half, second_half].iter().enumerate() {
+            let datagram = [&(seq_num as u32).to_be_bytes()[..], payload].concat();
+            _ = sender.send_to(&datagram, address).unwrap();
+        }
+
+        let (expect_packet, _, _) = rust_only::extract_packet_from_buf(&event, None).unwrap();
+        assert_eq!(stream.next_packet().unwrap(), Some(expect_packet));
+        // Ends after the timeout
+        assert_eq!(stream.next_packet().unwrap(), None);
+    }
+
+    #[test]
+    fn test_udp_stream_skips_empty_and_short_datagrams() {
+        pyo3::prepare_freethreaded_python();
+        let event = fake_event_simple();
+        let (expect_packet, _, _) = rust_only::extract_packet_from_buf(&event, None).unwrap();
+        for header_len in [0, 4] {
+            let mut stream =
+                decode_udp_stream("127.0.0.1:0", header_len, None, None, Some(0.2), None).unwrap();
+            let NetSource::Udp { socket, .. } = &stream.source else {
+                unreachable!()
+            };
+            let address = socket.local_addr().unwrap();
+
+            let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let header = vec![0xAB; header_len];
+            for datagram in [
+                &[][..],
+                &header[..header_len / 2],
+                &[&header[..], &event[..]].concat(),
+            ] {
+                _ = sender.send_to(datagram, address).unwrap();
+            }
+
+            assert_eq!(stream.next_packet().unwrap(), Some(expect_packet.clone()));
+            assert_eq!(stream.next_packet().unwrap(), None);
+        }
+    }
+}
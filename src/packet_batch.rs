@@ -0,0 +1,623 @@
+//! Struct-of-arrays representation of a batch of decoded packets, the hits of all packets are stored in contiguous arrays.
+//!
+//! Decoding into a [PacketBatch] doesn't allocate per packet, and the arrays are exposed to Python via the buffer protocol,
+//! e.g. `numpy.asarray(batch.rows)` is a zero-copy view.
+use pyo3::exceptions::{PyBufferError, PyIndexError};
+use pyo3::prelude::*;
+use std::ffi::CStr;
+use std::os::raw::{c_int, c_void};
+
+use crate::parse_error::ParseErrorKind;
+use crate::{
+    decode_with, rust_only, DecodeError, DecodeFilter, FpgaPrefix, HitVisitor, MossHit, MossPacket,
+    PixelMask,
+};
+
+/// Decoded packets stored as contiguous arrays.
+///
+/// The hits of packet `i` are at indices `packet_offsets[i]..packet_offsets[i + 1]` of the `regions`, `rows` and `columns` arrays.
+///
+/// Frozen so a batch can't be mutated from Python while a [BatchArray] buffer points into its arrays.
+#[pyclass(frozen)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketBatch {
+    packet_offsets: Vec<u64>,
+    unit_ids: Vec<u8>,
+    regions: Vec<u8>,
+    rows: Vec<u16>,
+    columns: Vec<u16>,
+    /// Number of events decoded into the batch, including the events dropped by a [DecodeFilter].
+    decoded_events: usize,
+}
+
+impl Default for PacketBatch {
+    fn default() -> Self {
+        Self {
+            packet_offsets: vec![0],
+            unit_ids: Vec::new(),
+            regions: Vec::new(),
+            rows: Vec::new(),
+            columns: Vec::new(),
+            decoded_events: 0,
+        }
+    }
+}
+
+/// A packet in a [PacketBatch], borrowing the hit arrays of the batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketView<'a> {
+    /// The unit ID of the packet.
+    pub unit_id: u8,
+    /// The regions of the hits in the packet.
+    pub regions: &'a [u8],
+    /// The rows of the hits in the packet.
+    pub rows: &'a [u16],
+    /// The columns of the hits in the packet.
+    pub columns: &'a [u16],
+}
+
+impl PacketView<'_> {
+    /// Iterates over the hits of the packet.
+    pub fn hits(&self) -> impl ExactSizeIterator<Item = MossHit> + '_ {
+        (0..self.regions.len()).map(|i| MossHit {
+            region: self.regions[i],
+            row: self.rows[i],
+            column: self.columns[i],
+        })
+    }
+
+    /// Copies the packet into a [MossPacket].
+    pub fn to_packet(&self) -> MossPacket {
+        let mut moss_packet = MossPacket::new(self.unit_id);
+        moss_packet.hits = self.hits().collect();
+        moss_packet
+    }
+}
+
+impl PacketBatch {
+    /// Decodes all complete events in `bytes` into a new batch, returns the batch and the number of bytes consumed, see [PacketBatch::extend_from].
+    pub fn decode(
+        bytes: &[u8],
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) -> Result<(Self, usize), DecodeError> {
+        let mut batch = Self::default();
+        let consumed = batch.extend_from(bytes, mask, filter)?;
+        Ok((batch, consumed))
+    }
+
+    /// Decodes all complete events in `bytes` and appends them to the batch, returns the number of bytes consumed.
+    ///
+    /// Like [decode_with], bytes after the last complete event are not consumed. On error, the packets before the failing packet are kept.
+    /// Optionally drops hits on pixels in the given [PixelMask], then drops the hits and packets rejected by the given [DecodeFilter].
+    /// The packet number of an error counts all events decoded into the batch, including the events dropped by the filter.
+    pub fn extend_from(
+        &mut self,
+        bytes: &[u8],
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) -> Result<usize, DecodeError> {
+        let events_before = self.decoded_events;
+        let result = decode_with(
+            bytes,
+            &mut BatchBuilder {
+                batch: self,
+                mask,
+                filter,
+                unit_accepted: true,
+            },
+        );
+        if let Err(DecodeError::Parse {
+            packet_num,
+            kind,
+            message,
+        }) = result
+        {
+            // Drop the hits of the failing packet
+            self.truncate_to_complete_packets();
+            return Err(DecodeError::Parse {
+                packet_num: events_before + packet_num,
+                kind,
+                message,
+            });
+        }
+        result
+    }
+
+    /// Decodes all complete events in `bytes`, each preceded by an FPGA prefix with the given [FpgaPrefix] layout, into a new batch,
+    /// returns the batch and the number of bytes consumed, see [PacketBatch::extend_from_prefixed].
+    pub fn decode_prefixed(
+        bytes: &[u8],
+        prefix: &FpgaPrefix,
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) -> Result<(Self, usize), DecodeError> {
+        let mut batch = Self::default();
+        let consumed = batch.extend_from_prefixed(bytes, prefix, mask, filter)?;
+        Ok((batch, consumed))
+    }
+
+    /// Decodes all complete events in `bytes` like [PacketBatch::extend_from], where each event is preceded by an FPGA prefix with the given [FpgaPrefix] layout.
+    ///
+    /// The prefix is skipped, bytes after the last complete event are not consumed, including the prefix of an incomplete event.
+    pub fn extend_from_prefixed(
+        &mut self,
+        bytes: &[u8],
+        prefix: &FpgaPrefix,
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) -> Result<usize, DecodeError> {
+        let mut consumed = 0;
+        while consumed < bytes.len() {
+            match rust_only::extract_prefixed_packet(&bytes[consumed..], None, prefix.length()) {
+                Ok((moss_packet, _, trailer_idx)) => {
+                    self.push(moss_packet, mask, filter);
+                    consumed += trailer_idx + 1;
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ParseErrorKind::EndOfBufferNoTrailer | ParseErrorKind::NoHeaderFound
+                    ) =>
+                {
+                    break
+                }
+                // The packet number counts all events decoded into the batch, like with extend_from
+                Err(e) => return Err(DecodeError::parse(self.decoded_events + 1, &e)),
+            }
+        }
+        Ok(consumed)
+    }
+
+    /// Number of packets in the batch.
+    pub fn len(&self) -> usize {
+        self.unit_ids.len()
+    }
+
+    /// Returns `true` if the batch has no packets.
+    pub fn is_empty(&self) -> bool {
+        self.unit_ids.is_empty()
+    }
+
+    /// Total number of hits in the batch.
+    pub fn hit_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Returns the packet at `index`, or `None` if out of bounds.
+    pub fn get(&self, index: usize) -> Option<PacketView<'_>> {
+        let unit_id = *self.unit_ids.get(index)?;
+        let hits = self.packet_offsets[index] as usize..self.packet_offsets[index + 1] as usize;
+        Some(PacketView {
+            unit_id,
+            regions: &self.regions[hits.clone()],
+            rows: &self.rows[hits.clone()],
+            columns: &self.columns[hits],
+        })
+    }
+
+    /// Iterates over the packets in the batch.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = PacketView<'_>> + '_ {
+        (0..self.len()).map(|i| self.get(i).expect("index is in bounds"))
+    }
+
+    /// Offsets of the hits of each packet, with a trailing offset equal to the total number of hits.
+    pub fn packet_offsets(&self) -> &[u64] {
+        &self.packet_offsets
+    }
+
+    /// The unit ID of each packet.
+    pub fn unit_ids(&self) -> &[u8] {
+        &self.unit_ids
+    }
+
+    /// The region of each hit.
+    pub fn regions(&self) -> &[u8] {
+        &self.regions
+    }
+
+    /// The row of each hit.
+    pub fn rows(&self) -> &[u16] {
+        &self.rows
+    }
+
+    /// The column of each hit.
+    pub fn columns(&self) -> &[u16] {
+        &self.columns
+    }
+
+    /// Number of events decoded into the batch, including the events dropped by a [DecodeFilter].
+    pub(crate) fn decoded_events(&self) -> usize {
+        self.decoded_events
+    }
+
+    /// Drops the masked hits from a decoded packet, then appends it to the batch if it is selected by the filter.
+    pub(crate) fn push(
+        &mut self,
+        mut packet: MossPacket,
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) {
+        self.decoded_events += 1;
+        crate::rust_only::apply_mask(mask, &mut packet);
+        if filter.is_some_and(|filter| !filter.apply(&mut packet)) {
+            return;
+        }
+        self.unit_ids.push(packet.unit_id);
+        for hit in &packet.hits {
+            self.regions.push(hit.region);
+            self.rows.push(hit.row);
+            self.columns.push(hit.column);
+        }
+        self.packet_offsets.push(self.regions.len() as u64);
+    }
+
+    fn truncate_to_complete_packets(&mut self) {
+        let hit_count = *self.packet_offsets.last().expect("always has offset 0") as usize;
+        self.unit_ids.truncate(self.packet_offsets.len() - 1);
+        self.regions.truncate(hit_count);
+        self.rows.truncate(hit_count);
+        self.columns.truncate(hit_count);
+    }
+}
+
+/// Appends the visited packets to the batch.
+struct BatchBuilder<'a> {
+    batch: &'a mut PacketBatch,
+    mask: Option<&'a PixelMask>,
+    filter: Option<&'a DecodeFilter>,
+    /// Whether the unit ID of the current packet is accepted by the filter.
+    unit_accepted: bool,
+}
+
+impl HitVisitor for BatchBuilder<'_> {
+    #[inline]
+    fn on_packet_start(&mut self, unit_id: u8) {
+        self.batch.unit_ids.push(unit_id);
+        self.unit_accepted = self
+            .filter
+            .is_none_or(|filter| filter.accepts_unit(unit_id));
+    }
+
+    #[inline]
+    fn on_hit(&mut self, unit_id: u8, hit: MossHit) {
+        if !self.unit_accepted
+            || self.mask.is_some_and(|mask| mask.is_masked(unit_id, &hit))
+            || self
+                .filter
+                .is_some_and(|filter| !filter.accepts_region(hit.region))
+        {
+            return;
+        }
+        self.batch.regions.push(hit.region);
+        self.batch.rows.push(hit.row);
+        self.batch.columns.push(hit.column);
+    }
+
+    #[inline]
+    fn on_packet_end(&mut self, _unit_id: u8) {
+        self.batch.decoded_events += 1;
+        let hit_count = self.batch.regions.len()
+            - *self
+                .batch
+                .packet_offsets
+                .last()
+                .expect("always has offset 0") as usize;
+        if self.unit_accepted
+            && self
+                .filter
+                .is_none_or(|filter| filter.accepts_hit_count(hit_count))
+        {
+            self.batch
+                .packet_offsets
+                .push(self.batch.regions.len() as u64);
+        } else {
+            self.batch.truncate_to_complete_packets();
+        }
+    }
+}
+
+#[pymethods]
+impl PacketBatch {
+    /// Total number of hits in the batch.
+    #[getter(hit_count)]
+    fn py_hit_count(&self) -> usize {
+        self.hit_count()
+    }
+
+    /// Offsets of the hits of each packet (uint64), with a trailing offset equal to the total number of hits.
+    #[getter(packet_offsets)]
+    fn py_packet_offsets(slf: PyRef<'_, Self>) -> BatchArray {
+        BatchArray::new(slf, BatchField::PacketOffsets)
+    }
+
+    /// The unit ID of each packet (uint8).
+    #[getter(unit_ids)]
+    fn py_unit_ids(slf: PyRef<'_, Self>) -> BatchArray {
+        BatchArray::new(slf, BatchField::UnitIds)
+    }
+
+    /// The region of each hit (uint8).
+    #[getter(regions)]
+    fn py_regions(slf: PyRef<'_, Self>) -> BatchArray {
+        BatchArray::new(slf, BatchField::Regions)
+    }
+
+    /// The row of each hit (uint16).
+    #[getter(rows)]
+    fn py_rows(slf: PyRef<'_, Self>) -> BatchArray {
+        BatchArray::new(slf, BatchField::Rows)
+    }
+
+    /// The column of each hit (uint16).
+    #[getter(columns)]
+    fn py_columns(slf: PyRef<'_, Self>) -> BatchArray {
+        BatchArray::new(slf, BatchField::Columns)
+    }
+
+    /// Copies all packets into a list of [MossPacket]s.
+    fn to_packets(&self) -> Vec<MossPacket> {
+        self.iter().map(|packet| packet.to_packet()).collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+
+    fn __getitem__(&self, index: isize) -> PyResult<MossPacket> {
+        let index = if index < 0 {
+            index + self.len() as isize
+        } else {
+            index
+        };
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.get(index))
+            .map(|packet| packet.to_packet())
+            .ok_or_else(|| PyIndexError::new_err("PacketBatch index out of range"))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PacketBatchIter {
+        PacketBatchIter {
+            batch: slf.into(),
+            index: 0,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PacketBatch({packets} packets, {hits} hits)",
+            packets = self.len(),
+            hits = self.hit_count()
+        )
+    }
+}
+
+/// Iterator over the packets of a [PacketBatch] as [MossPacket]s.
+#[pyclass]
+#[derive(Debug)]
+pub struct PacketBatchIter {
+    batch: Py<PacketBatch>,
+    index: usize,
+}
+
+#[pymethods]
+impl PacketBatchIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<MossPacket> {
+        let packet = self.batch.get().get(self.index)?.to_packet();
+        self.index += 1;
+        Some(packet)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchField {
+    PacketOffsets,
+    UnitIds,
+    Regions,
+    Rows,
+    Columns,
+}
+
+/// A read-only view of one of the arrays of a [PacketBatch], supporting the buffer protocol.
+///
+/// Keeps the batch alive, the arrays of a batch can't be modified after it is returned to Python as [PacketBatch] is frozen.
+#[pyclass(frozen)]
+#[derive(Debug)]
+pub struct BatchArray {
+    batch: Py<PacketBatch>,
+    field: BatchField,
+    // Storage for the `shape` and `strides` pointers of the exported buffer
+    shape: [isize; 1],
+    strides: [isize; 1],
+}
+
+impl BatchArray {
+    fn new(batch: PyRef<'_, PacketBatch>, field: BatchField) -> Self {
+        let (len, itemsize) = match field {
+            BatchField::PacketOffsets => (batch.packet_offsets.len(), std::mem::size_of::<u64>()),
+            BatchField::UnitIds => (batch.unit_ids.len(), std::mem::size_of::<u8>()),
+            BatchField::Regions => (batch.regions.len(), std::mem::size_of::<u8>()),
+            BatchField::Rows => (batch.rows.len(), std::mem::size_of::<u16>()),
+            BatchField::Columns => (batch.columns.len(), std::mem::size_of::<u16>()),
+        };
+        Self {
+            batch: batch.into(),
+            field,
+            shape: [len as isize],
+            strides: [itemsize as isize],
+        }
+    }
+}
+
+#[pymethods]
+impl BatchArray {
+    fn __len__(&self) -> usize {
+        self.shape[0] as usize
+    }
+
+    /// Exports the array as a read-only one-dimensional buffer.
+    ///
+    /// # Safety
+    /// `view` must be a valid pointer to a `Py_buffer`, as guaranteed by the Python buffer protocol.
+    #[allow(clippy::needless_pass_by_value)] // PyO3 requires `__getbuffer__` to take `PyRef` by value
+    unsafe fn __getbuffer__(
+        slf: PyRef<'_, Self>,
+        view: *mut pyo3::ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"));
+        }
+        if (flags & pyo3::ffi::PyBUF_WRITABLE) == pyo3::ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("PacketBatch arrays are read-only"));
+        }
+
+        let batch = slf.batch.get();
+        let (buf, format): (*const c_void, &'static CStr) = match slf.field {
+            BatchField::PacketOffsets => (batch.packet_offsets.as_ptr().cast(), c"Q"),
+            BatchField::UnitIds => (batch.unit_ids.as_ptr().cast(), c"B"),
+            BatchField::Regions => (batch.regions.as_ptr().cast(), c"B"),
+            BatchField::Rows => (batch.rows.as_ptr().cast(), c"H"),
+            BatchField::Columns => (batch.columns.as_ptr().cast(), c"H"),
+        };
+
+        (*view).obj = slf.as_ptr();
+        pyo3::ffi::Py_INCREF((*view).obj);
+        (*view).buf = buf.cast_mut();
+        (*view).len = slf.shape[0] * slf.strides[0];
+        (*view).readonly = 1;
+        (*view).itemsize = slf.strides[0];
+        (*view).format = if (flags & pyo3::ffi::PyBUF_FORMAT) == pyo3::ffi::PyBUF_FORMAT {
+            format.as_ptr().cast_mut()
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).ndim = 1;
+        (*view).shape = if (flags & pyo3::ffi::PyBUF_ND) == pyo3::ffi::PyBUF_ND {
+            slf.shape.as_ptr().cast_mut()
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).strides = if (flags & pyo3::ffi::PyBUF_STRIDES) == pyo3::ffi::PyBUF_STRIDES {
+            slf.strides.as_ptr().cast_mut()
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).suboffsets = std::ptr::null_mut();
+        (*view).internal = std::ptr::null_mut();
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut pyo3::ffi::Py_buffer) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::*;
+    use crate::{DecodeOptions, ParseErrorKind};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_batch_matches_packets() {
+        pyo3::prepare_freethreaded_python();
+        let bytes = [fake_event_simple(), fake_event_simple()].concat();
+        let (batch, consumed) = PacketBatch::decode(&bytes, None, None).unwrap();
+        let (expect_packets, _) = crate::decode_all_events(&bytes).unwrap();
+
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.hit_count(), 8);
+        assert_eq!(batch.packet_offsets(), &[0, 4, 8]);
+        assert_eq!(batch.unit_ids(), &[1, 1]);
+        assert_eq!(batch.rows()[..4], [2, 10, 301, 2]);
+        assert_eq!(batch.to_packets(), expect_packets);
+    }
+
+    #[test]
+    fn test_batch_array_buffer() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let (batch, _) = PacketBatch::decode(&fake_event_simple(), None, None).unwrap();
+            let batch = Py::new(py, batch).unwrap();
+            let rows = Py::new(py, PacketBatch::py_rows(batch.borrow(py))).unwrap();
+            let view = py
+                .eval("memoryview", None, None)
+                .unwrap()
+                .call1((rows,))
+                .unwrap();
+            assert!(view.getattr("readonly").unwrap().extract::<bool>().unwrap());
+            assert_eq!(
+                view.call_method0("tolist")
+                    .unwrap()
+                    .extract::<Vec<u16>>()
+                    .unwrap(),
+                [2, 10, 301, 2]
+            );
+        });
+    }
+
+    #[test]
+    fn test_batch_error_keeps_complete_packets() {
+        let bytes = [fake_event_simple(), fake_event_protocol_error()].concat();
+        let mut batch = PacketBatch::default();
+        match batch.extend_from(&bytes, None, None) {
+            Err(DecodeError::Parse {
+                packet_num, kind, ..
+            }) => {
+                assert_eq!(packet_num, 2);
+                assert_eq!(kind, ParseErrorKind::ProtocolError);
+            }
+            other => panic!("Expected a protocol error, got {other:?}"),
+        }
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch.hit_count(), 4);
+        assert_eq!(batch.packet_offsets(), &[0, 4]);
+    }
+
+    #[test]
+    fn test_batch_filter_matches_packets() {
+        pyo3::prepare_freethreaded_python();
+        let mut second_unit = fake_event_simple();
+        second_unit[0] = UNIT_FRAME_HEADER_1 + 1;
+        let bytes = [fake_event_simple(), second_unit, fake_event_simple()].concat();
+        let filter = DecodeFilter::new(Some(vec![1]), Some(vec![0, 3]), None, None, true).unwrap();
+
+        let (batch, consumed) = PacketBatch::decode(&bytes, None, Some(&filter)).unwrap();
+        let (expect_packets, _) = crate::decode_all_events_with(
+            &bytes,
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.to_packets(), expect_packets);
+    }
+
+    #[test]
+    fn test_batch_error_counts_filtered_events() {
+        let bytes = [
+            fake_event_simple(),
+            fake_event_simple(),
+            fake_event_protocol_error(),
+        ]
+        .concat();
+        let filter = DecodeFilter::new(None, None, Some(5), None, false).unwrap();
+        let mut batch = PacketBatch::default();
+        match batch.extend_from(&bytes, None, Some(&filter)) {
+            Err(DecodeError::Parse { packet_num, .. }) => assert_eq!(packet_num, 3),
+            other => panic!("Expected a protocol error, got {other:?}"),
+        }
+        assert!(batch.is_empty());
+        assert_eq!(batch.packet_offsets(), &[0]);
+    }
+}
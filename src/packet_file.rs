@@ -0,0 +1,481 @@
+//! A compact, versioned binary container for decoded [MossPacket]s, used to store reduced datasets without the raw data.
+//!
+//! The file starts with a header:
+//!
+//! | Field          | Size | Description                                                        |
+//! |----------------|------|--------------------------------------------------------------------|
+//! | magic          | 8    | `MOSSPKT\0`                                                        |
+//! | version        | 2    | Format version, little-endian                                      |
+//! | header length  | 2    | Number of header bytes following this field, little-endian         |
+//! | regions        | 1    | Number of regions per unit                                         |
+//! | row bits       | 1    | Number of bits of the row of a hit                                 |
+//! | column bits    | 1    | Number of bits of the column of a hit                              |
+//!
+//! Readers skip header bytes they don't know, so fields can be appended to the header without a new version.
+//!
+//! The header is followed by one record per packet until the end of the file:
+//! - A byte with the unit ID in the low 4 bits, the high 4 bits are reserved and must be 0.
+//! - The number of hits.
+//! - Each hit as the difference from the previous hit (or 0 for the first hit) of the pixel key `region << (row_bits + column_bits) | row << column_bits | column`, zigzag encoded.
+//!
+//! All numbers in a record are unsigned LEB128 varints, hits in readout order are 1 byte each for neighbouring pixels.
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+
+use crate::{MossHit, MossPacket};
+
+/// Magic bytes at the start of a packet file.
+const MAGIC: [u8; 8] = *b"MOSSPKT\0";
+/// Version of the packet file format, incremented on incompatible changes.
+pub const VERSION: u16 = 1;
+
+const UNIT_ID_MASK: u8 = 0x0F;
+
+/// The protocol configuration stored in the header of a packet file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatConfig {
+    /// Number of regions per unit.
+    pub regions: u8,
+    /// Number of bits of the row of a hit.
+    pub row_bits: u8,
+    /// Number of bits of the column of a hit.
+    pub column_bits: u8,
+}
+
+/// The MOSS protocol encodes the region in 2 bits, and the row and column in 9 bits each.
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            regions: 4,
+            row_bits: 9,
+            column_bits: 9,
+        }
+    }
+}
+
+impl FormatConfig {
+    /// Number of header bytes following the header length field.
+    const LEN: u16 = 3;
+
+    fn pixel_key(&self, hit: &MossHit) -> io::Result<u64> {
+        if hit.region >= self.regions
+            // A shift by 16 bits overflows a u16, all rows and columns fit in 16 bits
+            || hit.row.checked_shr(u32::from(self.row_bits)).unwrap_or(0) != 0
+            || hit.column.checked_shr(u32::from(self.column_bits)).unwrap_or(0) != 0
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Hit {hit} is outside the regions, rows or columns of the format"),
+            ));
+        }
+        Ok(u64::from(hit.region) << (self.row_bits + self.column_bits)
+            | u64::from(hit.row) << self.column_bits
+            | u64::from(hit.column))
+    }
+
+    fn hit(&self, key: u64) -> io::Result<MossHit> {
+        let region = key >> (self.row_bits + self.column_bits);
+        if region >= u64::from(self.regions) {
+            return Err(invalid_data("Hit region out of range"));
+        }
+        Ok(MossHit {
+            region: region as u8,
+            row: (key >> self.column_bits & ((1 << self.row_bits) - 1)) as u16,
+            column: (key & ((1 << self.column_bits) - 1)) as u16,
+        })
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        if self.row_bits > 16 || self.column_bits > 16 {
+            Err(invalid_data("Row and column must fit in 16 bits"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Writes [MossPacket]s to a packet file.
+#[derive(Debug)]
+pub struct PacketWriter<W: Write> {
+    writer: W,
+    config: FormatConfig,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> PacketWriter<W> {
+    /// Writes the header with the default [FormatConfig] to `writer`.
+    pub fn new(writer: W) -> io::Result<Self> {
+        Self::with_config(writer, FormatConfig::default())
+    }
+
+    /// Writes the header with the given [FormatConfig] to `writer`.
+    pub fn with_config(mut writer: W, config: FormatConfig) -> io::Result<Self> {
+        config.validate()?;
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&FormatConfig::LEN.to_le_bytes())?;
+        writer.write_all(&[config.regions, config.row_bits, config.column_bits])?;
+        Ok(Self {
+            writer,
+            config,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Appends a packet record, returns an `InvalidInput` error if the unit ID or a hit doesn't fit the format.
+    pub fn write_packet(&mut self, packet: &MossPacket) -> io::Result<()> {
+        if packet.unit_id > UNIT_ID_MASK {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unit ID {} doesn't fit in 4 bits", packet.unit_id),
+            ));
+        }
+        self.buf.clear();
+        self.buf.push(packet.unit_id);
+        write_varint(&mut self.buf, packet.hits.len() as u64);
+        let mut prev_key = 0;
+        for hit in &packet.hits {
+            let key = self.config.pixel_key(hit)?;
+            write_varint(&mut self.buf, zigzag(key as i64 - prev_key as i64));
+            prev_key = key;
+        }
+        self.writer.write_all(&self.buf)
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads [MossPacket]s from a packet file, returns an `InvalidData` error for malformed data.
+#[derive(Debug)]
+pub struct PacketReader<R: Read> {
+    reader: R,
+    config: FormatConfig,
+}
+
+impl<R: Read> PacketReader<R> {
+    /// Reads and checks the header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let magic: [u8; 8] = read_array(&mut reader)?;
+        if magic != MAGIC {
+            return Err(invalid_data("Not a MOSS packet file"));
+        }
+        let version = u16::from_le_bytes(read_array(&mut reader)?);
+        if version != VERSION {
+            return Err(invalid_data(&format!(
+                "Unsupported packet file version {version}, expected {VERSION}"
+            )));
+        }
+        let header_len = u16::from_le_bytes(read_array(&mut reader)?);
+        if header_len < FormatConfig::LEN {
+            return Err(invalid_data("Packet file header too short"));
+        }
+        let [regions, row_bits, column_bits] = read_array(&mut reader)?;
+        let config = FormatConfig {
+            regions,
+            row_bits,
+            column_bits,
+        };
+        config.validate()?;
+        // Skip header fields added after this version
+        let unknown_len = u64::from(header_len - FormatConfig::LEN);
+        if io::copy(&mut reader.by_ref().take(unknown_len), &mut io::sink())? != unknown_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Self { reader, config })
+    }
+
+    /// The protocol configuration from the header.
+    pub fn config(&self) -> FormatConfig {
+        self.config
+    }
+
+    /// Reads the next packet, `None` at the end of the file.
+    pub fn read_packet(&mut self) -> io::Result<Option<MossPacket>> {
+        let mut unit_id = 0;
+        if self.reader.read(std::slice::from_mut(&mut unit_id))? == 0 {
+            return Ok(None);
+        }
+        if unit_id & !UNIT_ID_MASK != 0 {
+            return Err(invalid_data("Invalid packet record unit ID byte"));
+        }
+        let mut packet = MossPacket::new(unit_id);
+        let hit_cnt = read_varint(&mut self.reader)?;
+        // Every hit takes at least one byte, cap the preallocation so a corrupt count can't cause a huge allocation
+        packet.hits.reserve(hit_cnt.min(4096) as usize);
+        let mut key: u64 = 0;
+        for _ in 0..hit_cnt {
+            key = key.wrapping_add(unzigzag(read_varint(&mut self.reader)?) as u64);
+            packet.hits.push(self.config.hit(key)?);
+        }
+        Ok(Some(packet))
+    }
+}
+
+impl<R: Read> Iterator for PacketReader<R> {
+    type Item = io::Result<MossPacket>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_packet().transpose()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(reader: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let [byte] = read_array(reader)?;
+        value |= u64::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("Varint longer than 64 bits"))
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Malformed data and packets that don't fit the format raise a `ValueError`, other errors an `IOError`.
+#[allow(clippy::needless_pass_by_value)] // Passed to `map_err`
+fn to_py_err(e: io::Error) -> PyErr {
+    match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => {
+            PyValueError::new_err(e.to_string())
+        }
+        io::ErrorKind::NotFound => pyo3::exceptions::PyFileNotFoundError::new_err(e.to_string()),
+        _ => PyIOError::new_err(e.to_string()),
+    }
+}
+
+/// Writes [MossPacket]s to a compact packet file, use as a context manager or call `close` when done.
+#[pyclass]
+pub struct PacketFileWriter {
+    writer: Option<PacketWriter<BufWriter<File>>>,
+}
+
+#[pymethods]
+impl PacketFileWriter {
+    /// Creates the file at `path` and writes the header.
+    #[new]
+    pub fn new(path: PathBuf) -> PyResult<Self> {
+        let file = File::create(path).map_err(to_py_err)?;
+        Ok(Self {
+            writer: Some(PacketWriter::new(BufWriter::new(file)).map_err(to_py_err)?),
+        })
+    }
+
+    /// Appends the packets to the file.
+    #[allow(clippy::needless_pass_by_value)] // PyO3 extracts the Python list into an owned Vec
+    pub fn write(&mut self, packets: Vec<PyRef<MossPacket>>) -> PyResult<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("Write to closed PacketFileWriter"))?;
+        packets
+            .iter()
+            .try_for_each(|packet| writer.write_packet(packet))
+            .map_err(to_py_err)
+    }
+
+    /// Flushes and closes the file, further writes raise a `ValueError`.
+    pub fn close(&mut self) -> PyResult<()> {
+        match self.writer.take() {
+            Some(writer) => writer.finish().map(drop).map_err(to_py_err),
+            None => Ok(()),
+        }
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: Option<&PyAny>,
+        _exc_value: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) -> PyResult<()> {
+        self.close()
+    }
+}
+
+/// Iterates over the [MossPacket]s in a packet file.
+#[pyclass]
+pub struct PacketFileReader {
+    reader: PacketReader<BufReader<File>>,
+}
+
+#[pymethods]
+impl PacketFileReader {
+    /// Opens the file at `path` and checks the header.
+    #[new]
+    pub fn new(path: PathBuf) -> PyResult<Self> {
+        let file = File::open(path).map_err(to_py_err)?;
+        Ok(Self {
+            reader: PacketReader::new(BufReader::new(file)).map_err(to_py_err)?,
+        })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<MossPacket>> {
+        self.reader.read_packet().map_err(to_py_err)
+    }
+}
+
+/// Writes the packets to a compact packet file at `path`.
+#[pyfunction]
+pub fn save_packets(path: PathBuf, packets: Vec<PyRef<MossPacket>>) -> PyResult<()> {
+    let mut writer = PacketFileWriter::new(path)?;
+    writer.write(packets)?;
+    writer.close()
+}
+
+/// Reads all packets from a compact packet file written by [save_packets] or [PacketFileWriter].
+#[pyfunction]
+pub fn load_packets(path: PathBuf) -> PyResult<Vec<MossPacket>> {
+    PacketFileReader::new(path)?
+        .reader
+        .collect::<io::Result<_>>()
+        .map_err(to_py_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::packet_with_hits;
+    use pretty_assertions::assert_eq;
+
+    fn write_all(packets: &[MossPacket]) -> Vec<u8> {
+        let mut writer = PacketWriter::new(Vec::new()).unwrap();
+        for packet in packets {
+            writer.write_packet(packet).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let packets = vec![
+            packet_with_hits(1, &[(0, 1, 2), (0, 1, 3), (1, 100, 4)]),
+            MossPacket::new(2),
+            packet_with_hits(10, &[(3, 511, 511), (0, 0, 0), (3, 511, 510)]),
+        ];
+
+        let bytes = write_all(&packets);
+        let reader = PacketReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.config(), FormatConfig::default());
+        assert_eq!(reader.collect::<io::Result<Vec<_>>>().unwrap(), packets);
+    }
+
+    #[test]
+    fn test_neighbouring_hits_are_one_byte() {
+        let header_len = write_all(&[]).len();
+        let bytes = write_all(&[packet_with_hits(1, &[(0, 5, 5), (0, 5, 6), (0, 5, 7)])]);
+        // Unit ID, hit count, 2 bytes for the first hit, then one byte per hit
+        assert_eq!(bytes.len() - header_len, 6);
+    }
+
+    #[test]
+    fn test_unknown_header_fields_are_skipped() {
+        let mut bytes = write_all(&[packet_with_hits(1, &[(0, 1, 2)])]);
+        bytes[10..12].copy_from_slice(&(FormatConfig::LEN + 2).to_le_bytes());
+        _ = bytes.splice(15..15, [0xAA, 0xBB]);
+        let packets: Vec<_> = PacketReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(packets, [packet_with_hits(1, &[(0, 1, 2)])]);
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let bytes = write_all(&[packet_with_hits(1, &[(0, 1, 2), (2, 3, 4)])]);
+        assert!(PacketReader::new(&bytes[..4]).is_err());
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] = 2;
+        assert_eq!(
+            PacketReader::new(wrong_version.as_slice())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        // Truncated record
+        let mut reader = PacketReader::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(reader.read_packet().is_err());
+        // Reserved bits of the unit ID byte
+        let header_len = write_all(&[]).len();
+        let mut reserved_bits = bytes.clone();
+        reserved_bits[header_len] |= 0x10;
+        let mut reader = PacketReader::new(reserved_bits.as_slice()).unwrap();
+        assert!(reader.read_packet().is_err());
+
+        let mut writer = PacketWriter::new(Vec::new()).unwrap();
+        assert!(writer.write_packet(&packet_with_hits(16, &[])).is_err());
+        assert!(writer
+            .write_packet(&packet_with_hits(1, &[(4, 0, 0)]))
+            .is_err());
+        assert!(writer
+            .write_packet(&packet_with_hits(1, &[(0, 512, 0)]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_16_bit_rows_and_columns() {
+        let config = FormatConfig {
+            regions: 4,
+            row_bits: 16,
+            column_bits: 16,
+        };
+        let packets = vec![packet_with_hits(
+            1,
+            &[(0, 0, 0), (3, u16::MAX, u16::MAX), (1, 1, 2)],
+        )];
+        let mut writer = PacketWriter::with_config(Vec::new(), config).unwrap();
+        for packet in &packets {
+            writer.write_packet(packet).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let reader = PacketReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.config(), config);
+        assert_eq!(reader.collect::<io::Result<Vec<_>>>().unwrap(), packets);
+
+        let too_wide = FormatConfig {
+            row_bits: 17,
+            ..config
+        };
+        assert!(PacketWriter::with_config(Vec::new(), too_wide).is_err());
+    }
+}
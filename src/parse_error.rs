@@ -1,8 +1,14 @@
+/// The kind of error encountered while decoding a MOSS event.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub(crate) enum ParseErrorKind {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseErrorKind {
+    /// No Unit Frame Header was found.
     NoHeaderFound,
+    /// The end of the data was reached before the Unit Frame Trailer.
     EndOfBufferNoTrailer,
+    /// A word that is not valid in the current state of the protocol.
     ProtocolError,
+    /// A byte other than a delimiter was found before the Unit Frame Header.
     InvalidDelimiter,
 }
 
@@ -1,20 +1,14 @@
 use pyo3::{exceptions::PyAssertionError, PyResult};
 
-use crate::moss_protocol::MossWord;
+use crate::scan;
 
 /// Get trailer N's byte index in the given bytes.
 #[inline]
 pub(super) fn find_trailer_n_idx(bytes: &[u8], n: usize) -> PyResult<usize> {
     let mut last_trailer_idx = 0;
     for i in 0..n {
-        if let Some(header_idx) = bytes[last_trailer_idx..]
-            .iter()
-            .position(|b| MossWord::UNIT_FRAME_HEADER_RANGE.contains(b))
-        {
-            if let Some(trailer_idx) = &bytes[last_trailer_idx + header_idx..]
-                .iter()
-                .position(|b| *b == MossWord::UNIT_FRAME_TRAILER)
-            {
+        if let Some(header_idx) = scan::find_header(&bytes[last_trailer_idx..]) {
+            if let Some(trailer_idx) = scan::find_trailer(&bytes[last_trailer_idx + header_idx..]) {
                 last_trailer_idx += header_idx + trailer_idx + 1;
             } else {
                 return Err(PyAssertionError::new_err(format!(
@@ -32,6 +26,37 @@ pub(super) fn find_trailer_n_idx(bytes: &[u8], n: usize) -> PyResult<usize> {
     Ok(last_trailer_idx - 1)
 }
 
+/// Get the byte index after trailer N in bytes where each event is preceded by an FPGA prefix of `prefix_len` bytes,
+/// i.e. the index where the prefix of event N + 1 starts.
+///
+/// The prefix can contain any byte value, so it is skipped before searching for the Unit Frame Header.
+pub(super) fn find_prefixed_event_n_end(
+    bytes: &[u8],
+    n: usize,
+    prefix_len: usize,
+) -> PyResult<usize> {
+    let mut event_end = 0;
+    for i in 0..n {
+        let event_bytes = bytes[event_end..].get(prefix_len..).unwrap_or_default();
+        if let Some(header_idx) = scan::find_header(event_bytes) {
+            if let Some(trailer_idx) = scan::find_trailer(&event_bytes[header_idx..]) {
+                event_end += prefix_len + header_idx + trailer_idx + 1;
+            } else {
+                return Err(PyAssertionError::new_err(format!(
+                    "No Unit Frame Trailer found for packet {packet_cnt}",
+                    packet_cnt = i + 1
+                )));
+            }
+        } else {
+            return Err(PyAssertionError::new_err(format!(
+                "No Unit Frame Header found for packet {packet_cnt}",
+                packet_cnt = i + 1
+            )));
+        }
+    }
+    Ok(event_end)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::moss_protocol::MossWord;
@@ -0,0 +1,261 @@
+//! Masking of noisy pixels, e.g. hot pixels found in a noise run.
+use pyo3::exceptions::{PyFileNotFoundError, PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+
+use crate::{MossHit, MossPacket};
+
+/// A masked pixel: `(unit_id, region, row, column)`
+type MaskedPixel = (u8, u8, u16, u16);
+
+#[pyclass]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// A set of masked pixels identified by `(unit_id, region, row, column)`.
+///
+/// Hits on masked pixels are dropped by the decode functions when a mask is supplied.
+pub struct PixelMask {
+    pixels: HashSet<MaskedPixel>,
+}
+
+#[pymethods]
+impl PixelMask {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pixel to the mask.
+    pub fn add(&mut self, unit_id: u8, region: u8, row: u16, column: u16) {
+        _ = self.pixels.insert((unit_id, region, row, column));
+    }
+
+    /// Remove a pixel from the mask, returns `true` if the pixel was masked.
+    pub fn remove(&mut self, unit_id: u8, region: u8, row: u16, column: u16) -> bool {
+        self.pixels.remove(&(unit_id, region, row, column))
+    }
+
+    /// Returns `true` if the pixel is masked.
+    pub fn contains(&self, unit_id: u8, region: u8, row: u16, column: u16) -> bool {
+        self.pixels.contains(&(unit_id, region, row, column))
+    }
+
+    /// Returns all masked pixels as a sorted list of `(unit_id, region, row, column)`.
+    pub fn pixels(&self) -> Vec<MaskedPixel> {
+        let mut pixels: Vec<MaskedPixel> = self.pixels.iter().copied().collect();
+        pixels.sort_unstable();
+        pixels
+    }
+
+    /// Loads a mask from a text file with one `unit_id region row column` entry per line.
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    #[staticmethod]
+    pub fn load(path: std::path::PathBuf) -> PyResult<Self> {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+        };
+
+        let mut mask = Self::default();
+        for (line_num, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| PyIOError::new_err(e.to_string()))?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (unit_id, region, row, column) = parse_mask_line(line).ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "Invalid pixel mask entry at line {line_no}: '{line}', expected 'unit_id region row column'",
+                    line_no = line_num + 1
+                ))
+            })?;
+            mask.add(unit_id, region, row, column);
+        }
+        Ok(mask)
+    }
+
+    /// Saves the mask to a text file with one `unit_id region row column` entry per line.
+    pub fn save(&self, path: std::path::PathBuf) -> PyResult<()> {
+        let to_py_err = |e: std::io::Error| PyIOError::new_err(e.to_string());
+        let file = std::fs::File::create(path).map_err(to_py_err)?;
+        let mut writer = std::io::BufWriter::new(file);
+
+        writeln!(writer, "# MOSS pixel mask").map_err(to_py_err)?;
+        writeln!(writer, "# unit_id region row column").map_err(to_py_err)?;
+        for (unit_id, region, row, column) in self.pixels() {
+            writeln!(writer, "{unit_id} {region} {row} {column}").map_err(to_py_err)?;
+        }
+        writer.flush().map_err(to_py_err)
+    }
+
+    /// Derives a mask from the packets of a noise run.
+    ///
+    /// A pixel is masked if the fraction of its unit's packets in which it fired exceeds `threshold`.
+    #[staticmethod]
+    #[allow(clippy::needless_pass_by_value)] // PyO3 extracts the Python list into an owned Vec
+    pub fn from_noise_run(packets: Vec<MossPacket>, threshold: f64) -> PyResult<Self> {
+        Self::from_packets(&packets, threshold)
+    }
+
+    /// Drops all masked hits from the given packets.
+    #[pyo3(name = "apply")]
+    fn py_apply(&self, mut packets: Vec<MossPacket>) -> Vec<MossPacket> {
+        packets.iter_mut().for_each(|p| self.apply(p));
+        packets
+    }
+
+    fn __len__(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!(
+            "{} ({} masked pixels)",
+            class_name,
+            slf.borrow().pixels.len()
+        ))
+    }
+}
+
+impl PixelMask {
+    /// Derives a mask from the packets of a noise run, see [PixelMask::from_noise_run].
+    pub fn from_packets(packets: &[MossPacket], threshold: f64) -> PyResult<Self> {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(PyValueError::new_err(
+                "threshold must be a frequency between 0 and 1",
+            ));
+        }
+
+        let mut unit_packet_cnt: HashMap<u8, usize> = HashMap::new();
+        let mut pixel_fire_cnt: HashMap<MaskedPixel, usize> = HashMap::new();
+        for packet in packets {
+            *unit_packet_cnt.entry(packet.unit_id).or_default() += 1;
+            // A pixel only counts once per packet
+            let fired: HashSet<MaskedPixel> = packet
+                .hits
+                .iter()
+                .map(|h| (packet.unit_id, h.region, h.row, h.column))
+                .collect();
+            for pixel in fired {
+                *pixel_fire_cnt.entry(pixel).or_default() += 1;
+            }
+        }
+
+        let pixels = pixel_fire_cnt
+            .into_iter()
+            .filter(|(pixel, fire_cnt)| {
+                *fire_cnt as f64 / unit_packet_cnt[&pixel.0] as f64 > threshold
+            })
+            .map(|(pixel, _)| pixel)
+            .collect();
+
+        Ok(Self { pixels })
+    }
+
+    /// Returns `true` if the hit is on a masked pixel of the given unit.
+    #[inline]
+    pub fn is_masked(&self, unit_id: u8, hit: &MossHit) -> bool {
+        self.pixels
+            .contains(&(unit_id, hit.region, hit.row, hit.column))
+    }
+
+    /// Drops all masked hits from the packet.
+    #[inline]
+    pub fn apply(&self, packet: &mut MossPacket) {
+        if self.pixels.is_empty() {
+            return;
+        }
+        let unit_id = packet.unit_id;
+        packet.hits.retain(|hit| !self.is_masked(unit_id, hit));
+    }
+}
+
+fn parse_mask_line(line: &str) -> Option<MaskedPixel> {
+    let mut fields = line.split_whitespace();
+    let entry = (
+        fields.next()?.parse().ok()?,
+        fields.next()?.parse().ok()?,
+        fields.next()?.parse().ok()?,
+        fields.next()?.parse().ok()?,
+    );
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn hit(region: u8, row: u16, column: u16) -> MossHit {
+        MossHit {
+            region,
+            row,
+            column,
+        }
+    }
+
+    fn noisy_packets() -> Vec<MossPacket> {
+        (0..10)
+            .map(|i| MossPacket {
+                unit_id: 1,
+                hits: if i % 2 == 0 {
+                    vec![hit(0, 2, 8), hit(1, 301, 433)]
+                } else {
+                    vec![hit(0, 2, 8)]
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_from_packets_threshold() {
+        let packets = noisy_packets();
+
+        let mask = PixelMask::from_packets(&packets, 0.6).unwrap();
+        assert_eq!(mask.pixels(), vec![(1, 0, 2, 8)]);
+
+        let mask = PixelMask::from_packets(&packets, 0.4).unwrap();
+        assert_eq!(mask.pixels(), vec![(1, 0, 2, 8), (1, 1, 301, 433)]);
+    }
+
+    #[test]
+    fn test_apply_mask() {
+        let mut mask = PixelMask::default();
+        mask.add(1, 0, 2, 8);
+        mask.add(2, 1, 301, 433); // Other unit, should not mask anything
+
+        let mut packets = noisy_packets();
+        packets.iter_mut().for_each(|p| mask.apply(p));
+
+        assert_eq!(packets[0].hits, vec![hit(1, 301, 433)]);
+        assert!(packets[1].hits.is_empty());
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let mut mask = PixelMask::default();
+        mask.add(1, 0, 2, 8);
+        mask.add(9, 3, 255, 255);
+
+        let path = std::env::temp_dir().join("moss_decoder_test_save_load_roundtrip.mask");
+        mask.save(path.clone()).unwrap();
+        let loaded = PixelMask::load(path.clone()).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded, mask);
+    }
+
+    #[test]
+    fn test_parse_mask_line() {
+        assert_eq!(parse_mask_line("1 0 2 8"), Some((1, 0, 2, 8)));
+        assert_eq!(parse_mask_line("1\t0  2 8"), Some((1, 0, 2, 8)));
+        assert_eq!(parse_mask_line("1 0 2"), None);
+        assert_eq!(parse_mask_line("1 0 2 8 9"), None);
+        assert_eq!(parse_mask_line("1 0 row 8"), None);
+    }
+}
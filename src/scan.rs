@@ -0,0 +1,251 @@
+//! Vectorised scanning for Unit Frame Header and Unit Frame Trailer bytes.
+//!
+//! Neither byte can occur as a data word inside an event, so event boundaries can be found without decoding the hits.
+//! On x86_64 the bytes are compared 16 at a time with SSE2, which is part of the x86_64 baseline, other targets use a portable scalar scan.
+use std::ops::{Range, RangeInclusive};
+
+use crate::moss_protocol::MossWord;
+
+/// Returns the index of the first Unit Frame Trailer byte in `bytes`.
+#[inline]
+pub fn find_trailer(bytes: &[u8]) -> Option<usize> {
+    imp::find_byte(bytes, MossWord::UNIT_FRAME_TRAILER)
+}
+
+/// Returns the index of the first Unit Frame Header byte in `bytes`.
+#[inline]
+pub fn find_header(bytes: &[u8]) -> Option<usize> {
+    imp::find_in_range(bytes, MossWord::UNIT_FRAME_HEADER_RANGE)
+}
+
+/// Returns the index of the Unit Frame Header at which decoding resumes after an error at `err_idx`, or `None` if no header follows the error.
+///
+/// The offending byte is skipped unless it is itself a Unit Frame Header, e.g. the header of the next event after an event without a trailer.
+/// `err_idx` must be after the header of the failed event, so that decoding makes progress.
+pub fn resume_after_error(bytes: &[u8], err_idx: usize) -> Option<usize> {
+    let err_idx = err_idx.min(bytes.len().checked_sub(1)?);
+    if err_idx > 0 && MossWord::UNIT_FRAME_HEADER_RANGE.contains(&bytes[err_idx]) {
+        return Some(err_idx);
+    }
+    find_header(&bytes[err_idx + 1..]).map(|header_idx| err_idx + 1 + header_idx)
+}
+
+/// Returns an iterator over the `(header_idx, trailer_idx)` pairs of the complete events in `bytes`.
+///
+/// The events are not validated, a trailing event without a trailer is not included.
+pub fn event_boundaries(bytes: &[u8]) -> EventBoundaries<'_> {
+    EventBoundaries { bytes, pos: 0 }
+}
+
+/// Iterator over the event boundaries in a buffer, see [event_boundaries].
+#[derive(Debug, Clone)]
+pub struct EventBoundaries<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Iterator for EventBoundaries<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header_idx = self.pos + find_header(&self.bytes[self.pos..])?;
+        let Some(trailer_idx) = find_trailer(&self.bytes[header_idx..]).map(|i| header_idx + i)
+        else {
+            self.pos = self.bytes.len();
+            return None;
+        };
+        self.pos = trailer_idx + 1;
+        Some((header_idx, trailer_idx))
+    }
+}
+
+/// Splits `bytes` into at most `n_shards` contiguous ranges of roughly equal size that only end after a Unit Frame Trailer.
+///
+/// Each range but the last contains only whole events, so the ranges can be decoded independently, e.g. on separate threads.
+/// The last range extends to the end of `bytes` and may end with an incomplete event.
+/// `n_shards` is capped at the number of complete events, so there is never more than one range per event.
+pub fn shard_events(bytes: &[u8], n_shards: usize) -> Vec<Range<usize>> {
+    // Stops counting after `n_shards` events, so a large buffer is not scanned in full
+    let n_shards = event_boundaries(bytes).take(n_shards).count().max(1);
+    let target_len = bytes.len().div_ceil(n_shards);
+    let mut shards = Vec::with_capacity(n_shards);
+    let mut start = 0;
+    while start < bytes.len() {
+        let cut = (start + target_len).min(bytes.len());
+        let end = match find_trailer(&bytes[cut - 1..]) {
+            Some(trailer_idx) if shards.len() + 1 < n_shards => cut + trailer_idx,
+            _ => bytes.len(),
+        };
+        shards.push(start..end);
+        start = end;
+    }
+    shards
+}
+
+#[cfg(target_arch = "x86_64")]
+use sse2 as imp;
+
+#[cfg(not(target_arch = "x86_64"))]
+use scalar as imp;
+
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+mod scalar {
+    use super::*;
+
+    #[inline]
+    pub(super) fn find_byte(bytes: &[u8], needle: u8) -> Option<usize> {
+        bytes.iter().position(|b| *b == needle)
+    }
+
+    #[inline]
+    pub(super) fn find_in_range(bytes: &[u8], range: RangeInclusive<u8>) -> Option<usize> {
+        bytes.iter().position(|b| range.contains(b))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use super::*;
+    use std::arch::x86_64::{
+        __m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_min_epu8, _mm_movemask_epi8, _mm_set1_epi8,
+        _mm_sub_epi8,
+    };
+
+    const LANES: usize = 16;
+
+    /// Scans `bytes` in chunks of 16, `lane_matches` sets every byte of the returned vector that matches to `0xFF`.
+    ///
+    /// The bytes after the last whole chunk are scanned with `byte_matches`.
+    #[inline(always)]
+    fn find(
+        bytes: &[u8],
+        lane_matches: impl Fn(__m128i) -> __m128i,
+        byte_matches: impl Fn(u8) -> bool,
+    ) -> Option<usize> {
+        let chunks = bytes.chunks_exact(LANES);
+        let remainder = chunks.remainder();
+        for (chunk_idx, chunk) in chunks.enumerate() {
+            // SAFETY: `chunk` is exactly 16 bytes and `_mm_loadu_si128` has no alignment requirement.
+            let lanes = unsafe { _mm_loadu_si128(chunk.as_ptr().cast()) };
+            // SAFETY: SSE2 is part of the x86_64 baseline, so it is always available.
+            let mask = unsafe { _mm_movemask_epi8(lane_matches(lanes)) };
+            if mask != 0 {
+                return Some(chunk_idx * LANES + mask.trailing_zeros() as usize);
+            }
+        }
+        remainder
+            .iter()
+            .position(|b| byte_matches(*b))
+            .map(|i| bytes.len() - remainder.len() + i)
+    }
+
+    #[inline]
+    pub(super) fn find_byte(bytes: &[u8], needle: u8) -> Option<usize> {
+        // SAFETY (all blocks): SSE2 is part of the x86_64 baseline, so it is always available.
+        let needles = unsafe { _mm_set1_epi8(needle as i8) };
+        find(
+            bytes,
+            |lanes| unsafe { _mm_cmpeq_epi8(lanes, needles) },
+            |b| b == needle,
+        )
+    }
+
+    #[inline]
+    pub(super) fn find_in_range(bytes: &[u8], range: RangeInclusive<u8>) -> Option<usize> {
+        let (start, span) = (*range.start(), range.end().wrapping_sub(*range.start()));
+        // SAFETY (all blocks): SSE2 is part of the x86_64 baseline, so it is always available.
+        let (starts, spans) = unsafe { (_mm_set1_epi8(start as i8), _mm_set1_epi8(span as i8)) };
+        // A byte is in the range if `byte - start` wraps to at most `span`, i.e. `min(byte - start, span) == byte - start`
+        find(
+            bytes,
+            |lanes| unsafe {
+                let offsets = _mm_sub_epi8(lanes, starts);
+                _mm_cmpeq_epi8(_mm_min_epu8(offsets, spans), offsets)
+            },
+            |b| range.contains(&b),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_find_matches_scalar_at_every_position() {
+        // Every byte value, shifted so the matches land in both the vectorised chunks and the remainder
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        for start in 0..all_bytes.len() {
+            let bytes = &all_bytes[start..];
+            assert_eq!(
+                find_trailer(bytes),
+                scalar::find_byte(bytes, MossWord::UNIT_FRAME_TRAILER)
+            );
+            assert_eq!(
+                find_header(bytes),
+                scalar::find_in_range(bytes, MossWord::UNIT_FRAME_HEADER_RANGE)
+            );
+        }
+        assert_eq!(find_trailer(&[]), None);
+        assert_eq!(find_header(&[0xFA; 100]), None);
+        assert_eq!(find_header(&[0xD0, 0xDB, 0xDA]), Some(2));
+    }
+
+    #[test]
+    fn test_event_boundaries() {
+        let event = fake_event_simple();
+        let trailer_idx = event.len() - 1;
+        let mut bytes = [event.clone(), event.clone()].concat();
+        // Incomplete event at the end
+        bytes.extend_from_slice(&event[..5]);
+
+        let header_idx = find_header(&event).unwrap();
+        assert_eq!(
+            event_boundaries(&bytes).collect::<Vec<_>>(),
+            vec![
+                (header_idx, trailer_idx),
+                (event.len() + header_idx, event.len() + trailer_idx)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resume_after_error() {
+        let event = fake_event_simple();
+        let bytes = [event.clone(), event.clone()].concat();
+        // An invalid word in the first event resumes at the next header
+        assert_eq!(resume_after_error(&bytes, 3), Some(event.len()));
+        // A header in place of the trailer is the start of the next event
+        assert_eq!(resume_after_error(&bytes, event.len()), Some(event.len()));
+        assert_eq!(resume_after_error(&bytes, event.len() + 3), None);
+        assert_eq!(resume_after_error(&bytes, bytes.len() + 10), None);
+        assert_eq!(resume_after_error(&[], 0), None);
+    }
+
+    #[test]
+    fn test_shard_events() {
+        let event = fake_event_simple();
+        let bytes = event.repeat(10);
+        for n_shards in 1..=12 {
+            let shards = shard_events(&bytes, n_shards);
+            assert!(!shards.is_empty() && shards.len() <= n_shards);
+            assert_eq!(shards.first().unwrap().start, 0);
+            assert_eq!(shards.last().unwrap().end, bytes.len());
+            for shard in &shards {
+                assert_eq!(shard.len() % event.len(), 0, "{n_shards} shards");
+            }
+            assert!(shards.windows(2).all(|w| w[0].end == w[1].start));
+        }
+        assert!(shard_events(&[], 4).is_empty());
+
+        // More shards than events gives at most one shard per event
+        for n_shards in [11, 1 << 20, usize::MAX] {
+            let shards = shard_events(&bytes, n_shards);
+            assert_eq!(shards.len(), 10);
+            assert!(shards.iter().all(|shard| shard.len() == event.len()));
+        }
+        assert_eq!(shard_events(&event[..event.len() - 1], usize::MAX).len(), 1);
+    }
+}
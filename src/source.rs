@@ -0,0 +1,1029 @@
+//! Decoding from any [std::io::Read] source, such as files, pipes, stdin, in-memory cursors or decompressors.
+//!
+//! The source is read in chunks of 10 MiB, events split across chunks are completed by the following chunk.
+//! Python file-like objects are supported through [PyFileLike].
+use pyo3::exceptions::{PyAssertionError, PyBytesWarning, PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::collections::VecDeque;
+use std::io::Read;
+
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::{
+    moss_protocol, rust_only, scan, List_MossPackets, MossPacket, PacketBatch, PixelMask,
+    Remainder_Bytes, RunStats, READER_BUFFER_CAPACITY,
+};
+use crate::{DecodeError, DecodeFilter, DecodeOptions, ErrorPolicy, FpgaPrefix};
+
+/// Maximum number of packets preallocated by [decode_n], more are allocated as the events are decoded.
+const MAX_TAKE_PREALLOC: usize = 1 << 16;
+
+/// Reads `reader` in chunks and calls `decode_chunk` with the bytes read so far that are not yet consumed.
+///
+/// `decode_chunk` returns the number of bytes it consumed, or `None` to stop reading.
+/// Returns the bytes that were read but not consumed.
+pub(crate) fn read_chunks(
+    mut reader: impl Read,
+    prepend_buffer: Option<Vec<u8>>,
+    mut decode_chunk: impl FnMut(&[u8]) -> PyResult<Option<usize>>,
+) -> PyResult<Vec<u8>> {
+    let mut buf = vec![0; READER_BUFFER_CAPACITY];
+    let mut bytes_to_decode = prepend_buffer.unwrap_or_default();
+    bytes_to_decode.reserve(READER_BUFFER_CAPACITY);
+    loop {
+        let bytes_read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(io_error_to_pyerr(e)),
+        };
+
+        // Extend bytes_to_decode with the new data
+        bytes_to_decode.extend_from_slice(&buf[..bytes_read]);
+
+        match decode_chunk(&bytes_to_decode)? {
+            // Remove the processed bytes, the remaining bytes could be part of an event that continues in the next chunk
+            Some(consumed) => _ = bytes_to_decode.drain(..consumed),
+            None => break,
+        }
+    }
+    Ok(bytes_to_decode)
+}
+
+/// Converts an I/O error to a Python exception, errors raised by a Python file-like object are passed through unchanged.
+fn io_error_to_pyerr(e: std::io::Error) -> PyErr {
+    if e.get_ref().is_some_and(|inner| inner.is::<PyErr>()) {
+        *e.into_inner()
+            .and_then(|inner| inner.downcast::<PyErr>().ok())
+            .expect("inner error is a PyErr")
+    } else {
+        PyIOError::new_err(e.to_string())
+    }
+}
+
+/// Returns `true` if the bytes could be the start of an event that continues in the next chunk.
+fn needs_more_bytes(kind: ParseErrorKind) -> bool {
+    matches!(
+        kind,
+        ParseErrorKind::EndOfBufferNoTrailer | ParseErrorKind::NoHeaderFound
+    )
+}
+
+/// Converts the error from decoding packet number `packet_cnt` to a Python exception, an incomplete event is a `BytesWarning`.
+fn packet_error(e: &ParseError, packet_cnt: usize) -> PyErr {
+    let msg = format!("Failed decoding packet #{packet_cnt}: {e}");
+    if e.kind() == ParseErrorKind::EndOfBufferNoTrailer {
+        PyBytesWarning::new_err(msg)
+    } else {
+        PyAssertionError::new_err(msg)
+    }
+}
+
+/// Returns the error decoding the bytes left at the end of the data, for data in which no event could be decoded.
+///
+/// `prefix_len` is the length of the FPGA prefix preceding the first event, see [DecodeOptions::prefix].
+fn remaining_bytes_error(remaining_bytes: &[u8], prefix_len: usize) -> Option<PyErr> {
+    if remaining_bytes.is_empty() {
+        return None;
+    }
+    // The data may end in the prefix, then no header is found
+    let event_bytes = remaining_bytes.get(prefix_len..).unwrap_or_default();
+    rust_only::get_all_packets_from_buf(event_bytes, None)
+        .err()
+        .map(|(e, _)| packet_error(&e, 1))
+}
+
+/// An event decoded by [EventDecoder::decode_chunk].
+pub(crate) struct DecodedEvent<'a> {
+    /// Event number, counting the events skipped with [ErrorPolicy::SkipBadEvents].
+    pub(crate) number: u64,
+    /// Byte offset of the Unit Frame Header in the source.
+    pub(crate) offset: u64,
+    /// The bytes between the previous event and the Unit Frame Header.
+    pub(crate) gap: &'a [u8],
+    /// The bytes from the Unit Frame Header to the Unit Frame Trailer.
+    pub(crate) bytes: &'a [u8],
+    /// The decoded packet, no mask or filter is applied.
+    pub(crate) packet: MossPacket,
+}
+
+/// Receives the events decoded by [EventDecoder::visit_chunk].
+pub(crate) trait EventVisitor {
+    /// Called for each decoded event, returns `false` to stop decoding.
+    fn on_event(&mut self, decoded: DecodedEvent) -> PyResult<bool>;
+    /// Called with the bytes skipped with [ErrorPolicy::SkipBadEvents] and the error at which they were skipped,
+    /// the error is `None` for the bytes of a skipped event that continues from the previous chunk.
+    fn on_skip(&mut self, _skipped: &[u8], _error: Option<&ParseError>) {}
+}
+
+/// Decodes the events of the chunks passed by [read_chunks] one at a time, applying an [ErrorPolicy] to events that fail to decode.
+pub(crate) struct EventDecoder {
+    policy: ErrorPolicy,
+    /// Number of events decoded or skipped so far.
+    events: u64,
+    /// Byte offset of the current chunk in the source.
+    chunk_offset: u64,
+    /// A skipped event continues in the current chunk, the bytes up to the next Unit Frame Header belong to it,
+    /// or with a prefix the bytes up to and including the next Unit Frame Trailer.
+    resyncing: bool,
+    /// Length of the FPGA prefix preceding each event, 0 if the events are not prefixed.
+    prefix_len: usize,
+}
+
+impl EventDecoder {
+    pub(crate) fn new(policy: ErrorPolicy) -> Self {
+        Self {
+            policy,
+            events: 0,
+            chunk_offset: 0,
+            resyncing: false,
+            prefix_len: 0,
+        }
+    }
+
+    /// Creates a decoder with the error policy and FPGA prefix of the [DecodeOptions].
+    pub(crate) fn with_options(options: &DecodeOptions) -> Self {
+        Self {
+            prefix_len: options.prefix_len(),
+            ..Self::new(options.policy)
+        }
+    }
+
+    /// Decodes the complete events in `bytes` and calls `on_event` with each [DecodedEvent].
+    ///
+    /// `on_event` returns `false` to stop decoding.
+    /// With [ErrorPolicy::SkipBadEvents] an event that fails to decode is skipped and decoding resumes at the next event, see [EventDecoder::resume_after_error],
+    /// the skipped event still counts in the event numbers. Otherwise the error is returned.
+    /// Returns the number of bytes consumed, or `None` if decoding was stopped, like the `decode_chunk` function of [read_chunks].
+    pub(crate) fn decode_chunk(
+        &mut self,
+        bytes: &[u8],
+        on_event: impl FnMut(DecodedEvent) -> PyResult<bool>,
+    ) -> PyResult<Option<usize>> {
+        struct OnEvent<F>(F);
+        impl<F: FnMut(DecodedEvent) -> PyResult<bool>> EventVisitor for OnEvent<F> {
+            fn on_event(&mut self, decoded: DecodedEvent) -> PyResult<bool> {
+                (self.0)(decoded)
+            }
+        }
+        self.visit_chunk(bytes, &mut OnEvent(on_event))
+    }
+
+    /// Like [EventDecoder::decode_chunk], but also passes the bytes skipped with [ErrorPolicy::SkipBadEvents] to the `visitor`.
+    pub(crate) fn visit_chunk(
+        &mut self,
+        bytes: &[u8],
+        visitor: &mut impl EventVisitor,
+    ) -> PyResult<Option<usize>> {
+        let mut consumed = 0;
+        if self.resyncing {
+            let resume_idx = if self.prefix_len == 0 {
+                scan::find_header(bytes)
+            } else {
+                scan::find_trailer(bytes).map(|trailer_idx| trailer_idx + 1)
+            };
+            match resume_idx {
+                Some(resume_idx) => {
+                    consumed = resume_idx;
+                    self.resyncing = false;
+                }
+                None => consumed = bytes.len(),
+            }
+            visitor.on_skip(&bytes[..consumed], None);
+        }
+        loop {
+            let remaining = &bytes[consumed..];
+            match rust_only::extract_prefixed_packet(remaining, None, self.prefix_len) {
+                Ok((moss_packet, header_idx, trailer_idx)) => {
+                    let decoded = DecodedEvent {
+                        number: self.events,
+                        offset: self.chunk_offset + (consumed + header_idx) as u64,
+                        gap: &remaining[..header_idx],
+                        bytes: &remaining[header_idx..=trailer_idx],
+                        packet: moss_packet,
+                    };
+                    self.events += 1;
+                    consumed += trailer_idx + 1;
+                    if !visitor.on_event(decoded)? {
+                        return Ok(None);
+                    }
+                }
+                // The rest of the bytes could be part of an event that continues in the next chunk
+                Err(e) if needs_more_bytes(e.kind()) => break,
+                Err(e) if self.policy == ErrorPolicy::SkipBadEvents => {
+                    let skip = match self.resume_after_error(remaining, e.err_index()) {
+                        Some(resume_idx) => resume_idx,
+                        None => {
+                            self.resyncing = true;
+                            remaining.len()
+                        }
+                    };
+                    visitor.on_skip(&remaining[..skip], Some(&e));
+                    consumed += skip;
+                    self.events += 1;
+                }
+                Err(e) => return Err(packet_error(&e, self.events as usize + 1)),
+            }
+        }
+        self.chunk_offset += consumed as u64;
+        Ok(Some(consumed))
+    }
+
+    /// Returns the index in `bytes` at which decoding resumes after an error at `err_idx`, or `None` if the failed event continues in the next chunk.
+    ///
+    /// Without a prefix decoding resumes at the next Unit Frame Header after the offending byte.
+    /// The FPGA prefix can contain any byte value, so with a prefix decoding resumes after the Unit Frame Trailer of the failed event, where the prefix of the next event starts.
+    fn resume_after_error(&self, bytes: &[u8], err_idx: usize) -> Option<usize> {
+        if self.prefix_len == 0 {
+            return scan::resume_after_error(bytes, err_idx);
+        }
+        let err_idx = err_idx.min(bytes.len());
+        scan::find_trailer(&bytes[err_idx..]).map(|trailer_idx| err_idx + trailer_idx + 1)
+    }
+}
+
+/// Decodes all events from `reader` into a list of [MossPacket]s, see [decode_from_file](crate::decode_from_file).
+pub fn decode_all(reader: impl Read, options: DecodeOptions) -> PyResult<List_MossPackets> {
+    let mut moss_packets = Vec::new();
+    let mut decoded_cnt = 0;
+    let mut decoder = EventDecoder::with_options(&options);
+
+    let result = read_chunks(reader, None, |bytes_to_decode| {
+        decoder.decode_chunk(bytes_to_decode, |decoded| {
+            decoded_cnt += 1;
+            rust_only::push_packet(
+                &mut moss_packets,
+                decoded.packet,
+                options.mask,
+                options.filter,
+            );
+            Ok(true)
+        })
+    })
+    .and_then(|remaining_bytes| {
+        if decoded_cnt == 0 {
+            Err(
+                remaining_bytes_error(&remaining_bytes, options.prefix_len())
+                    .unwrap_or_else(|| PyAssertionError::new_err("No MOSS Packets in events")),
+            )
+        } else {
+            Ok(())
+        }
+    });
+
+    match result {
+        Ok(()) => Ok(moss_packets),
+        Err(e) => Err(options.policy.with_partial(e, || moss_packets)),
+    }
+}
+
+/// Decodes all events from `reader` one at a time and calls `visit` with the event number, the byte offset of its Unit Frame Header and the packet.
+///
+/// Hits on pixels in the [PixelMask] are dropped and packets rejected by the [DecodeFilter] are not visited, the event numbers of the other events are unchanged.
+/// Events skipped with [ErrorPolicy::SkipBadEvents] also keep their event numbers.
+/// Returns the number of decoded events, with [ErrorPolicy::StopAndReturnPartial] it is the `partial` attribute of the exception.
+pub(crate) fn for_each_packet(
+    reader: impl Read,
+    options: DecodeOptions,
+    mut visit: impl FnMut(u64, u64, &MossPacket) -> PyResult<()>,
+) -> PyResult<u64> {
+    let mut decoded_cnt: u64 = 0;
+    let mut decoder = EventDecoder::with_options(&options);
+    let result = read_chunks(reader, None, |bytes| {
+        decoder.decode_chunk(bytes, |mut decoded| {
+            rust_only::apply_mask(options.mask, &mut decoded.packet);
+            if options
+                .filter
+                .is_none_or(|filter| filter.apply(&mut decoded.packet))
+            {
+                visit(decoded.number, decoded.offset, &decoded.packet)?;
+            }
+            decoded_cnt += 1;
+            Ok(true)
+        })
+    });
+    match result {
+        Ok(_) => Ok(decoded_cnt),
+        Err(e) => Err(options.policy.with_partial(e, || decoded_cnt)),
+    }
+}
+
+/// Decodes N events from `reader`, see [decode_n_events_from_file](crate::decode_n_events_from_file).
+pub fn decode_n(
+    reader: impl Read,
+    take: usize,
+    skip: Option<usize>,
+    prepend_buffer: Option<Vec<u8>>,
+    options: DecodeOptions,
+) -> PyResult<List_MossPackets> {
+    // Skip N events
+    if skip.is_some_and(|s| s == 0) {
+        return Err(PyValueError::new_err("skip value must be greater than 0"));
+    } else if skip.is_some() && prepend_buffer.is_some() {
+        return Err(PyValueError::new_err(
+            "skip and prepend_buffer cannot be used together",
+        ));
+    }
+
+    // `take` comes from the caller and may be far more than the reader holds
+    let mut moss_packets: Vec<MossPacket> = Vec::with_capacity(take.min(MAX_TAKE_PREALLOC));
+    let mut packets_to_skip = skip.unwrap_or(0);
+    let mut decoded_cnt = 0;
+    // Events decoded after the skipped events, including the events dropped by the filter
+    let mut taken_cnt = 0;
+    let mut decoder = EventDecoder::with_options(&options);
+
+    let result = read_chunks(reader, prepend_buffer, |bytes_to_decode| {
+        decoder.decode_chunk(bytes_to_decode, |decoded| {
+            decoded_cnt += 1;
+            if packets_to_skip > 0 {
+                packets_to_skip -= 1;
+            } else {
+                rust_only::push_packet(
+                    &mut moss_packets,
+                    decoded.packet,
+                    options.mask,
+                    options.filter,
+                );
+                taken_cnt += 1;
+            }
+            Ok(taken_cnt < take)
+        })
+    })
+    .and_then(|remaining_bytes| {
+        if decoded_cnt == 0 {
+            if let Some(e) = remaining_bytes_error(&remaining_bytes, options.prefix_len()) {
+                return Err(e);
+            }
+        }
+        if taken_cnt == 0 {
+            Err(PyAssertionError::new_err("No MOSS Packets in events"))
+        } else if taken_cnt < take {
+            Err(PyBytesWarning::new_err(format!(
+                "Taking {take} events failed, got {taken_cnt} events"
+            )))
+        } else {
+            Ok(())
+        }
+    });
+
+    match result {
+        Ok(()) => {
+            moss_packets.truncate(take); // Truncate to the requested number of events
+            Ok(moss_packets)
+        }
+        Err(e) => Err(options.policy.with_partial(e, || moss_packets)),
+    }
+}
+
+/// Decodes all events from `reader` after skipping the first `skip` events, see [skip_n_take_all_from_file](crate::skip_n_take_all_from_file).
+pub fn skip_n_take_all(
+    reader: impl Read,
+    mut skip: usize,
+    options: DecodeOptions,
+) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
+    let mut moss_packets: Vec<MossPacket> = Vec::new();
+    let mut decoder = EventDecoder::with_options(&options);
+
+    let result = read_chunks(reader, None, |bytes_to_decode| {
+        decoder.decode_chunk(bytes_to_decode, |decoded| {
+            if skip > 0 {
+                skip -= 1;
+            } else {
+                rust_only::push_packet(
+                    &mut moss_packets,
+                    decoded.packet,
+                    options.mask,
+                    options.filter,
+                );
+            }
+            Ok(true)
+        })
+    });
+    let moss_packets = (!moss_packets.is_empty()).then_some(moss_packets);
+
+    match result {
+        Ok(bytes_to_decode) => {
+            let remainder = bytes_to_decode
+                .iter()
+                .any(|b| moss_protocol::MossWord::UNIT_FRAME_HEADER_RANGE.contains(b))
+                .then_some(bytes_to_decode);
+            Ok((moss_packets, remainder))
+        }
+        Err(e) => Err(options
+            .policy
+            .with_partial(e, || (moss_packets, None::<Remainder_Bytes>))),
+    }
+}
+
+/// Decodes all events from `reader` into a [PacketBatch], see [decode_from_file_batch](crate::decode_from_file_batch).
+pub fn decode_all_batch(reader: impl Read, options: DecodeOptions) -> PyResult<PacketBatch> {
+    let mut batch = PacketBatch::default();
+    let mut decoder = EventDecoder::with_options(&options);
+    let result = read_chunks(reader, None, |bytes_to_decode| {
+        // Skipping bad events and the FPGA prefix is handled by the EventDecoder
+        if options.policy == ErrorPolicy::SkipBadEvents || options.prefix.is_some() {
+            decoder.decode_chunk(bytes_to_decode, |decoded| {
+                batch.push(decoded.packet, options.mask, options.filter);
+                Ok(true)
+            })
+        } else {
+            // The packets decoded before an error are kept in the batch
+            Ok(Some(batch.extend_from(
+                bytes_to_decode,
+                options.mask,
+                options.filter,
+            )?))
+        }
+    })
+    .and_then(|_| {
+        if batch.decoded_events() == 0 {
+            Err(PyAssertionError::new_err("No MOSS Packets in events"))
+        } else {
+            Ok(())
+        }
+    });
+
+    match result {
+        Ok(()) => Ok(batch),
+        Err(e) => Err(options.policy.with_partial(e, || batch)),
+    }
+}
+
+/// Computes [RunStats] for all bytes from `reader` in a single pass, see [run_stats](crate::run_stats).
+pub fn run_stats(reader: impl Read) -> PyResult<RunStats> {
+    let mut stats = RunStats::default();
+    let mut decoder = EventDecoder::new(ErrorPolicy::SkipBadEvents);
+    let remaining_bytes = read_chunks(reader, None, |bytes_to_decode| {
+        Ok(Some(stats.accumulate(&mut decoder, bytes_to_decode)))
+    })?;
+    stats.finish(&remaining_bytes);
+    Ok(stats)
+}
+
+/// Maximum number of bytes buffered for an incomplete event by [PacketBuffer].
+///
+/// An event with a hit on every pixel is less than 1 MiB, so an incomplete event of this size has lost its trailer.
+pub(crate) const MAX_PENDING_EVENT_LEN: usize = READER_BUFFER_CAPACITY;
+
+/// Buffers bytes received in pieces of any size and decodes the complete events,
+/// an event split across pieces is completed by the following pieces like events split across the chunks of a file.
+///
+/// Only the bytes from the Unit Frame Header, or from the FPGA prefix, of an incomplete event are kept, up to [MAX_PENDING_EVENT_LEN] bytes.
+#[derive(Debug, Default)]
+pub(crate) struct PacketBuffer {
+    bytes_to_decode: Vec<u8>,
+    decoded_packets: VecDeque<MossPacket>,
+    decode_error: Option<DecodeError>,
+    /// Number of decoded events, including the events dropped by a [DecodeFilter].
+    decoded_cnt: usize,
+    /// Length of the FPGA prefix preceding each event, 0 if the events are not prefixed.
+    prefix_len: usize,
+}
+
+impl PacketBuffer {
+    /// Creates a buffer for events preceded by an FPGA prefix with the given [FpgaPrefix] layout, or for events without a prefix.
+    pub(crate) fn new(prefix: Option<&FpgaPrefix>) -> Self {
+        Self {
+            prefix_len: prefix.map_or(0, FpgaPrefix::length),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the next decoded packet, or the decoding error once all packets before it are returned.
+    ///
+    /// Returns `None` if more bytes are needed to complete the next event.
+    pub(crate) fn next_packet(&mut self) -> Option<Result<MossPacket, DecodeError>> {
+        match self.decoded_packets.pop_front() {
+            Some(moss_packet) => Some(Ok(moss_packet)),
+            None => self.decode_error.take().map(Err),
+        }
+    }
+
+    /// Returns the number of buffered bytes of an incomplete event.
+    pub(crate) fn pending_len(&self) -> usize {
+        self.bytes_to_decode.len()
+    }
+
+    /// Adds the received bytes and decodes all complete events, the remaining bytes are kept until the next call.
+    ///
+    /// Decoding stops at the first error, which is returned by [PacketBuffer::next_packet] after the packets decoded before it.
+    /// An incomplete event longer than [MAX_PENDING_EVENT_LEN] is an [ParseErrorKind::EndOfBufferNoTrailer] error.
+    pub(crate) fn extend(
+        &mut self,
+        bytes: &[u8],
+        mask: Option<&PixelMask>,
+        filter: Option<&DecodeFilter>,
+    ) {
+        if self.decode_error.is_some() {
+            return;
+        }
+        self.bytes_to_decode.extend_from_slice(bytes);
+
+        let mut last_trailer_idx = 0;
+        loop {
+            match rust_only::extract_prefixed_packet(
+                &self.bytes_to_decode[last_trailer_idx..],
+                None,
+                self.prefix_len,
+            ) {
+                Ok((mut moss_packet, _, trailer_idx)) => {
+                    self.decoded_cnt += 1;
+                    rust_only::apply_mask(mask, &mut moss_packet);
+                    if filter.is_none_or(|filter| filter.apply(&mut moss_packet)) {
+                        self.decoded_packets.push_back(moss_packet);
+                    }
+                    last_trailer_idx += trailer_idx + 1;
+                }
+                // Not a complete event yet, wait for more data. Without a prefix only delimiters come before the header, so they are dropped
+                Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
+                    if self.prefix_len == 0 {
+                        last_trailer_idx +=
+                            scan::find_header(&self.bytes_to_decode[last_trailer_idx..])
+                                .expect("An event without a trailer has a header");
+                    }
+                    break;
+                }
+                // Only delimiters are left, or a prefix whose event has not been received yet
+                Err(e) if e.kind() == ParseErrorKind::NoHeaderFound => {
+                    if self.prefix_len == 0 {
+                        last_trailer_idx = self.bytes_to_decode.len();
+                    }
+                    break;
+                }
+                Err(e) => {
+                    self.decode_error = Some(DecodeError::parse(self.decoded_cnt + 1, &e));
+                    break;
+                }
+            }
+        }
+        _ = self.bytes_to_decode.drain(..last_trailer_idx);
+
+        if self.decode_error.is_none() && self.bytes_to_decode.len() > MAX_PENDING_EVENT_LEN {
+            self.decode_error = Some(DecodeError::Parse {
+                packet_num: self.decoded_cnt + 1,
+                kind: ParseErrorKind::EndOfBufferNoTrailer,
+                message: format!("No Unit Frame Trailer within {MAX_PENDING_EVENT_LEN} bytes of the Unit Frame Header").into(),
+            });
+        }
+        // No more bytes are decoded after an error
+        if self.decode_error.is_some() {
+            self.bytes_to_decode = Vec::new();
+        }
+    }
+}
+
+/// Adapts a Python object with a `read(size)` method returning `bytes`, e.g. a file opened in binary mode, [io.BytesIO] or a socket file, to [Read].
+///
+/// Exceptions raised by `read` are returned as the source of the [std::io::Error].
+#[derive(Debug)]
+pub struct PyFileLike {
+    inner: PyObject,
+}
+
+impl PyFileLike {
+    /// Wraps the Python object, returns an error if it has no `read` method.
+    pub fn new(py: Python, inner: PyObject) -> PyResult<Self> {
+        if !inner.as_ref(py).hasattr("read")? {
+            return Err(pyo3::exceptions::PyTypeError::new_err(
+                "Expected a file-like object with a read() method",
+            ));
+        }
+        Ok(Self { inner })
+    }
+}
+
+impl Read for PyFileLike {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Python::with_gil(|py| {
+            let data = self.inner.call_method1(py, "read", (buf.len(),))?;
+            let data: &PyBytes = data.as_ref(py).downcast().map_err(PyErr::from)?;
+            let bytes = data.as_bytes();
+            if bytes.len() > buf.len() {
+                return Err(PyValueError::new_err(format!(
+                    "read() returned {got} bytes, more than the {requested} bytes requested",
+                    got = bytes.len(),
+                    requested = buf.len()
+                )));
+            }
+            buf[..bytes.len()].copy_from_slice(bytes);
+            Ok(bytes.len())
+        })
+        .map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::{
+        fake_event_simple, IDLE, REGION_HEADER_0, UNIT_FRAME_HEADER_1,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn with_policy(policy: ErrorPolicy) -> DecodeOptions<'static> {
+        DecodeOptions {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_packet_buffer_keeps_only_incomplete_event() {
+        let mut packet_buffer = PacketBuffer::default();
+        packet_buffer.extend(&[0xFA; 1000], None, None);
+        assert!(packet_buffer.bytes_to_decode.is_empty());
+
+        let event = fake_event_simple();
+        packet_buffer.extend(&[[0xFA; 100].as_slice(), &event[..10]].concat(), None, None);
+        assert_eq!(packet_buffer.bytes_to_decode, event[..10]);
+
+        packet_buffer.extend(&event[10..], None, None);
+        assert!(packet_buffer.bytes_to_decode.is_empty());
+        assert_eq!(packet_buffer.next_packet().unwrap().unwrap().hits.len(), 4);
+        assert!(packet_buffer.next_packet().is_none());
+    }
+
+    #[test]
+    fn test_packet_buffer_caps_incomplete_event() {
+        let mut packet_buffer = PacketBuffer::default();
+        packet_buffer.extend(&fake_event_simple(), None, None);
+        let mut incomplete_event = vec![IDLE; MAX_PENDING_EVENT_LEN];
+        // A hit followed by idle words
+        incomplete_event[..5].copy_from_slice(&[
+            UNIT_FRAME_HEADER_1,
+            REGION_HEADER_0,
+            0x00,
+            0x50,
+            0x88,
+        ]);
+        packet_buffer.extend(&incomplete_event, None, None);
+        // Within the limit
+        assert!(packet_buffer.decode_error.is_none());
+
+        packet_buffer.extend(&[IDLE], None, None);
+        assert!(packet_buffer.bytes_to_decode.is_empty());
+        assert!(packet_buffer.next_packet().unwrap().is_ok());
+        match packet_buffer.next_packet() {
+            Some(Err(DecodeError::Parse {
+                packet_num, kind, ..
+            })) => {
+                assert_eq!(packet_num, 2);
+                assert_eq!(kind, ParseErrorKind::EndOfBufferNoTrailer);
+            }
+            other => panic!("Expected an error for the incomplete event, got {other:?}"),
+        }
+        assert!(packet_buffer.next_packet().is_none());
+    }
+
+    #[test]
+    fn test_decode_all_from_cursor() {
+        let bytes = [fake_event_simple(), fake_event_simple()].concat();
+        let packets = decode_all(std::io::Cursor::new(bytes), DecodeOptions::default()).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0], packets[1]);
+        assert_eq!(packets[0].hits.len(), 4);
+    }
+
+    #[test]
+    fn test_read_error_is_raised() {
+        struct FailingReader;
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disconnected"))
+            }
+        }
+        pyo3::prepare_freethreaded_python();
+        let err = decode_all(FailingReader, DecodeOptions::default()).unwrap_err();
+        Python::with_gil(|py| assert!(err.is_instance_of::<PyIOError>(py)));
+    }
+
+    /// Three events, the second has an invalid data word.
+    fn events_with_bad_second_event() -> Vec<u8> {
+        let mut bad_event = fake_event_simple();
+        bad_event[3] = 0x2F;
+        [fake_event_simple(), bad_event, fake_event_simple()].concat()
+    }
+
+    #[test]
+    fn test_error_policies() {
+        pyo3::prepare_freethreaded_python();
+        let reader = || std::io::Cursor::new(events_with_bad_second_event());
+        let expect_packet = decode_all(
+            std::io::Cursor::new(fake_event_simple()),
+            DecodeOptions::default(),
+        )
+        .unwrap()
+        .remove(0);
+
+        let err = decode_all(reader(), DecodeOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("Failed decoding packet #2"));
+
+        let packets = decode_all(reader(), with_policy(ErrorPolicy::SkipBadEvents)).unwrap();
+        assert_eq!(packets, vec![expect_packet.clone(); 2]);
+        let packets = decode_n(
+            reader(),
+            2,
+            None,
+            None,
+            with_policy(ErrorPolicy::SkipBadEvents),
+        )
+        .unwrap();
+        assert_eq!(packets.len(), 2);
+        let (packets, remainder) =
+            skip_n_take_all(reader(), 1, with_policy(ErrorPolicy::SkipBadEvents)).unwrap();
+        assert_eq!((packets.unwrap().len(), remainder), (1, None));
+        let mut events = Vec::new();
+        let decoded = for_each_packet(
+            reader(),
+            with_policy(ErrorPolicy::SkipBadEvents),
+            |event, _, _| {
+                events.push(event);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!((decoded, events), (2, vec![0, 2]));
+        let batch = decode_all_batch(reader(), with_policy(ErrorPolicy::SkipBadEvents)).unwrap();
+        assert_eq!(batch.get(1).unwrap().to_packet(), expect_packet);
+
+        let err = decode_all(reader(), with_policy(ErrorPolicy::StopAndReturnPartial)).unwrap_err();
+        assert!(err.to_string().contains("Failed decoding packet #2"));
+        Python::with_gil(|py| {
+            let partial: Vec<MossPacket> =
+                err.value(py).getattr("partial").unwrap().extract().unwrap();
+            assert_eq!(partial, vec![expect_packet.clone()]);
+
+            let err = decode_n(
+                reader(),
+                3,
+                None,
+                None,
+                with_policy(ErrorPolicy::StopAndReturnPartial),
+            )
+            .unwrap_err();
+            let partial: Vec<MossPacket> =
+                err.value(py).getattr("partial").unwrap().extract().unwrap();
+            assert_eq!(partial.len(), 1);
+
+            let err = decode_all_batch(reader(), with_policy(ErrorPolicy::StopAndReturnPartial))
+                .unwrap_err();
+            let partial: PacketBatch = err.value(py).getattr("partial").unwrap().extract().unwrap();
+            assert_eq!(partial.len(), 1);
+        });
+    }
+
+    /// Returns the bytes in reads of `piece_size` bytes.
+    struct Pieces<'a>(&'a [u8], usize);
+    impl Read for Pieces<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.1.min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_short_reads_before_first_event() {
+        pyo3::prepare_freethreaded_python();
+        let event = fake_event_simple();
+        let bytes = [&event[..], &event[..]].concat();
+        let (expect_packet, _, _) = rust_only::extract_packet_from_buf(&event, None).unwrap();
+        let expect_packets = vec![expect_packet.clone(), expect_packet];
+        // Every read but the last ends before the first event is complete
+        for piece_size in [1, 2, event.len() - 1] {
+            let packets = decode_all(Pieces(&bytes, piece_size), DecodeOptions::default()).unwrap();
+            assert_eq!(packets, expect_packets, "piece size {piece_size}");
+
+            let packets = decode_n(
+                Pieces(&bytes, piece_size),
+                2,
+                None,
+                None,
+                DecodeOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(packets, expect_packets, "piece size {piece_size}");
+
+            let (packets, remainder) =
+                skip_n_take_all(Pieces(&bytes, piece_size), 0, DecodeOptions::default()).unwrap();
+            assert_eq!(
+                packets,
+                Some(expect_packets.clone()),
+                "piece size {piece_size}"
+            );
+            assert_eq!(remainder, None);
+        }
+    }
+
+    #[test]
+    fn test_skip_bad_event_across_chunks() {
+        let bytes = events_with_bad_second_event();
+        for piece_size in [1, 7, 19, 23, bytes.len()] {
+            let mut events = Vec::new();
+            let decoded = for_each_packet(
+                Pieces(&bytes, piece_size),
+                with_policy(ErrorPolicy::SkipBadEvents),
+                |event, offset, _| {
+                    events.push((event, offset));
+                    Ok(())
+                },
+            )
+            .unwrap();
+            assert_eq!(decoded, 2);
+            assert_eq!(events, vec![(0, 0), (2, 38)], "piece size {piece_size}");
+        }
+    }
+
+    #[test]
+    fn test_skip_event_without_trailer() {
+        let event = fake_event_simple();
+        let truncated = &event[..event.len() - 1];
+        let bytes = [&event, truncated, &event, &event].concat();
+
+        let packets = decode_all(
+            std::io::Cursor::new(&bytes),
+            with_policy(ErrorPolicy::SkipBadEvents),
+        )
+        .unwrap();
+        assert_eq!(packets.len(), 3);
+
+        let mut events = Vec::new();
+        _ = for_each_packet(
+            std::io::Cursor::new(&bytes),
+            with_policy(ErrorPolicy::SkipBadEvents),
+            |event, offset, _| {
+                events.push((event, offset));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(events, vec![(0, 0), (2, 37), (3, 56)]);
+    }
+
+    /// Precedes each event with a 4 byte FPGA prefix made of protocol words.
+    fn prefixed(events: &[Vec<u8>]) -> Vec<u8> {
+        events
+            .iter()
+            .flat_map(|event| [&[0xE0, UNIT_FRAME_HEADER_1, 0xFA, 0x2F][..], event].concat())
+            .collect()
+    }
+
+    #[test]
+    fn test_prefixed_events() {
+        pyo3::prepare_freethreaded_python();
+        let prefix = FpgaPrefix::new(4, 0, 0, 0, 0, false).unwrap();
+        let options = DecodeOptions {
+            prefix: Some(&prefix),
+            ..Default::default()
+        };
+        let event = fake_event_simple();
+        let bytes = prefixed(&[event.clone(), event.clone(), event.clone()]);
+        let (expect_packet, _, _) = rust_only::extract_packet_from_buf(&event, None).unwrap();
+
+        for piece_size in [1, 3, 7, bytes.len()] {
+            let packets = decode_all(Pieces(&bytes, piece_size), options).unwrap();
+            assert_eq!(
+                packets,
+                vec![expect_packet.clone(); 3],
+                "piece size {piece_size}"
+            );
+            let packets = decode_n(Pieces(&bytes, piece_size), 1, Some(2), None, options).unwrap();
+            assert_eq!(
+                packets,
+                vec![expect_packet.clone()],
+                "piece size {piece_size}"
+            );
+            let batch = decode_all_batch(Pieces(&bytes, piece_size), options).unwrap();
+            assert_eq!(batch.len(), 3, "piece size {piece_size}");
+        }
+
+        // The remainder starts with the prefix of the incomplete event
+        let split_idx = bytes.len() - 5;
+        let (packets, remainder) =
+            skip_n_take_all(std::io::Cursor::new(&bytes[..split_idx]), 1, options).unwrap();
+        assert_eq!(packets, Some(vec![expect_packet.clone()]));
+        let remainder = remainder.unwrap();
+        assert_eq!(remainder[..4], bytes[..4]);
+        let packets = decode_n(
+            std::io::Cursor::new(&bytes[split_idx..]),
+            1,
+            None,
+            Some(remainder),
+            options,
+        )
+        .unwrap();
+        assert_eq!(packets, vec![expect_packet]);
+    }
+
+    #[test]
+    fn test_skip_bad_prefixed_event() {
+        let prefix = FpgaPrefix::new(4, 0, 0, 0, 0, false).unwrap();
+        let options = DecodeOptions {
+            policy: ErrorPolicy::SkipBadEvents,
+            prefix: Some(&prefix),
+            ..Default::default()
+        };
+        let mut bad_event = fake_event_simple();
+        bad_event[3] = 0x2F;
+        let bytes = prefixed(&[fake_event_simple(), bad_event, fake_event_simple()]);
+        // The header byte in the prefix of the third event is not mistaken for its header
+        for piece_size in [1, 7, 19, 23, bytes.len()] {
+            let mut events = Vec::new();
+            let decoded =
+                for_each_packet(Pieces(&bytes, piece_size), options, |event, offset, _| {
+                    events.push((event, offset));
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(decoded, 2);
+            assert_eq!(events, vec![(0, 4), (2, 50)], "piece size {piece_size}");
+        }
+    }
+
+    #[test]
+    fn test_packet_buffer_prefixed_events() {
+        let prefix = FpgaPrefix::new(4, 0, 0, 0, 0, false).unwrap();
+        let event = fake_event_simple();
+        let bytes = prefixed(&[event.clone(), event.clone()]);
+        let (expect_packet, _, _) = rust_only::extract_packet_from_buf(&event, None).unwrap();
+        for piece_size in [1, 3, 7, bytes.len()] {
+            let mut packet_buffer = PacketBuffer::new(Some(&prefix));
+            for piece in bytes.chunks(piece_size) {
+                packet_buffer.extend(piece, None, None);
+            }
+            assert_eq!(packet_buffer.pending_len(), 0, "piece size {piece_size}");
+            for _ in 0..2 {
+                assert_eq!(packet_buffer.next_packet().unwrap().unwrap(), expect_packet);
+            }
+            assert!(packet_buffer.next_packet().is_none());
+        }
+    }
+
+    #[test]
+    fn test_read_error_returns_partial() {
+        /// Returns the bytes in one read, then fails.
+        struct FailAfterBytes(Option<Vec<u8>>);
+        impl Read for FailAfterBytes {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let bytes = self
+                    .0
+                    .take()
+                    .ok_or_else(|| std::io::Error::other("disconnected"))?;
+                buf[..bytes.len()].copy_from_slice(&bytes);
+                Ok(bytes.len())
+            }
+        }
+        pyo3::prepare_freethreaded_python();
+        let reader = || FailAfterBytes(Some([fake_event_simple(), fake_event_simple()].concat()));
+
+        let err = decode_all(reader(), with_policy(ErrorPolicy::SkipBadEvents)).unwrap_err();
+        Python::with_gil(|py| {
+            assert!(err.is_instance_of::<PyIOError>(py));
+            assert!(!err.value(py).hasattr("partial").unwrap());
+        });
+
+        let err = decode_all(reader(), with_policy(ErrorPolicy::StopAndReturnPartial)).unwrap_err();
+        Python::with_gil(|py| {
+            assert!(err.is_instance_of::<PyIOError>(py));
+            let partial: Vec<MossPacket> =
+                err.value(py).getattr("partial").unwrap().extract().unwrap();
+            assert_eq!(partial.len(), 2);
+        });
+
+        let err = skip_n_take_all(reader(), 1, with_policy(ErrorPolicy::StopAndReturnPartial))
+            .unwrap_err();
+        Python::with_gil(|py| {
+            let (packets, remainder): (Option<Vec<MossPacket>>, Option<Vec<u8>>) =
+                err.value(py).getattr("partial").unwrap().extract().unwrap();
+            assert_eq!((packets.unwrap().len(), remainder), (1, None));
+        });
+    }
+
+    #[test]
+    fn test_decode_all_from_python_file_like() {
+        pyo3::prepare_freethreaded_python();
+        let bytes = [fake_event_simple(), fake_event_simple()].concat();
+        Python::with_gil(|py| {
+            let bytes_io = py
+                .import("io")
+                .unwrap()
+                .call_method1("BytesIO", (PyBytes::new(py, &bytes),))
+                .unwrap();
+            let reader = PyFileLike::new(py, bytes_io.into()).unwrap();
+            assert_eq!(
+                decode_all(reader, DecodeOptions::default()).unwrap().len(),
+                2
+            );
+
+            // Text mode file-like objects return `str` which is passed through as a TypeError
+            let string_io = py.import("io").unwrap().call_method0("StringIO").unwrap();
+            let reader = PyFileLike::new(py, string_io.into()).unwrap();
+            let err = decode_all(reader, DecodeOptions::default()).unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTypeError>(py));
+
+            assert!(PyFileLike::new(py, 5.into_py(py)).is_err());
+        });
+    }
+}
@@ -0,0 +1,346 @@
+//! Run-level statistics of raw MOSS data, useful as a quick health check of an acquisition.
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::moss_protocol::idle_counts::DmuModeTracker;
+use crate::moss_protocol::{IdleCounts, MossWord};
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::scan;
+use crate::source::{DecodedEvent, EventDecoder, EventVisitor};
+
+#[pyclass]
+#[derive(Debug, Default, Clone, PartialEq)]
+/// Summary statistics of a run of raw MOSS data.
+pub struct RunStats {
+    /// Total number of bytes processed.
+    #[pyo3(get)]
+    pub total_bytes: usize,
+    /// Number of successfully decoded packets.
+    #[pyo3(get)]
+    pub packets: usize,
+    /// Number of decoded packets without any hits.
+    #[pyo3(get)]
+    pub empty_packets: usize,
+    /// Number of hits per unit ID, indexed by region ID.
+    #[pyo3(get)]
+    pub hits_per_unit_region: BTreeMap<u8, [usize; 4]>,
+    /// Number of `0xFF` idle words (no backbone/long edge readout).
+    #[pyo3(get)]
+    pub idle_no_backbone: usize,
+    /// Number of `0xF0` idle words (LEC readout 4-bit DMU mode).
+    #[pyo3(get)]
+    pub idle_four_bit: usize,
+    /// Number of `0xFC` idle words (LEC readout 2-bit DMU mode).
+    #[pyo3(get)]
+    pub idle_two_bit: usize,
+    /// Number of `0xFE` idle words (LEC readout 1-bit DMU mode).
+    #[pyo3(get)]
+    pub idle_one_bit: usize,
+    /// Number of delimiter bytes between events.
+    #[pyo3(get)]
+    pub delimiter_bytes: usize,
+    /// Number of bytes between events (delimiters and any other bytes outside events).
+    #[pyo3(get)]
+    pub bytes_between_events: usize,
+    /// Size in bytes of the largest decoded event (header to trailer).
+    #[pyo3(get)]
+    pub largest_event_size: Option<usize>,
+    /// Size in bytes of the smallest decoded event (header to trailer).
+    #[pyo3(get)]
+    pub smallest_event_size: Option<usize>,
+    /// Number of packets with idle words of more than one type.
+    #[pyo3(get)]
+    pub mixed_idle_packets: usize,
+    /// Number of DMU mode changes between packets, as indicated by the idle words.
+    #[pyo3(get)]
+    pub dmu_mode_changes: usize,
+    dmu_mode_tracker: DmuModeTracker,
+    errors: BTreeMap<ParseErrorKind, usize>,
+}
+
+#[pymethods]
+impl RunStats {
+    /// Total number of hits in all decoded packets.
+    #[getter]
+    pub fn hits(&self) -> usize {
+        self.hits_per_unit_region
+            .values()
+            .map(|regions| regions.iter().sum::<usize>())
+            .sum()
+    }
+
+    /// Number of errors encountered while decoding by error kind.
+    #[getter]
+    pub fn error_counts(&self) -> BTreeMap<String, usize> {
+        self.errors
+            .iter()
+            .map(|(kind, cnt)| (kind.to_string(), *cnt))
+            .collect()
+    }
+
+    /// Returns the statistics as a dictionary.
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("total_bytes", self.total_bytes)?;
+        dict.set_item("packets", self.packets)?;
+        dict.set_item("empty_packets", self.empty_packets)?;
+        dict.set_item("hits", self.hits())?;
+        dict.set_item("hits_per_unit_region", self.hits_per_unit_region.clone())?;
+        dict.set_item("idle_no_backbone", self.idle_no_backbone)?;
+        dict.set_item("idle_four_bit", self.idle_four_bit)?;
+        dict.set_item("idle_two_bit", self.idle_two_bit)?;
+        dict.set_item("idle_one_bit", self.idle_one_bit)?;
+        dict.set_item("delimiter_bytes", self.delimiter_bytes)?;
+        dict.set_item("bytes_between_events", self.bytes_between_events)?;
+        dict.set_item("largest_event_size", self.largest_event_size)?;
+        dict.set_item("smallest_event_size", self.smallest_event_size)?;
+        dict.set_item("mixed_idle_packets", self.mixed_idle_packets)?;
+        dict.set_item("dmu_mode_changes", self.dmu_mode_changes)?;
+        dict.set_item("error_counts", self.error_counts())?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl RunStats {
+    /// Accumulates statistics from all complete events in `bytes` decoded by `decoder` and returns the number of bytes consumed.
+    ///
+    /// `decoder` skips bad events with [ErrorPolicy::SkipBadEvents](crate::ErrorPolicy::SkipBadEvents), the errors are counted.
+    /// Bytes after the last complete event are not consumed as they could be part of an event that continues in the next chunk,
+    /// the bytes left at the end are passed to [RunStats::finish].
+    pub(crate) fn accumulate(&mut self, decoder: &mut EventDecoder, bytes: &[u8]) -> usize {
+        let consumed = decoder
+            .visit_chunk(bytes, self)
+            .expect("Skipping bad events never fails")
+            .expect("Decoding is never stopped");
+        self.total_bytes += consumed;
+        consumed
+    }
+
+    /// Accumulates the bytes left after the last complete event at the end of the data, a partial event is counted as an error.
+    pub(crate) fn finish(&mut self, remaining: &[u8]) {
+        if scan::find_header(remaining).is_some() {
+            self.record_error(ParseErrorKind::EndOfBufferNoTrailer);
+        }
+        self.record_gap(remaining);
+        self.total_bytes += remaining.len();
+    }
+
+    fn record_gap(&mut self, gap: &[u8]) {
+        self.bytes_between_events += gap.len();
+        self.delimiter_bytes += gap.iter().filter(|b| **b == MossWord::DELIMITER).count();
+    }
+
+    fn record_event(&mut self, event: &[u8]) {
+        let idle_counts = IdleCounts::from_event_bytes(event);
+        self.idle_no_backbone += idle_counts.no_backbone as usize;
+        self.idle_four_bit += idle_counts.four_bit as usize;
+        self.idle_two_bit += idle_counts.two_bit as usize;
+        self.idle_one_bit += idle_counts.one_bit as usize;
+        if idle_counts.is_mixed() {
+            self.mixed_idle_packets += 1;
+        } else if self
+            .dmu_mode_tracker
+            .update(self.packets, &idle_counts)
+            .is_some()
+        {
+            self.dmu_mode_changes += 1;
+        }
+
+        let size = event.len();
+        self.largest_event_size = Some(self.largest_event_size.map_or(size, |s| s.max(size)));
+        self.smallest_event_size = Some(self.smallest_event_size.map_or(size, |s| s.min(size)));
+    }
+
+    fn record_error(&mut self, kind: ParseErrorKind) {
+        *self.errors.entry(kind).or_default() += 1;
+    }
+}
+
+impl EventVisitor for RunStats {
+    fn on_event(&mut self, decoded: DecodedEvent) -> PyResult<bool> {
+        self.record_gap(decoded.gap);
+        self.record_event(decoded.bytes);
+
+        self.packets += 1;
+        if decoded.packet.hits.is_empty() {
+            self.empty_packets += 1;
+        }
+        let region_hits = self
+            .hits_per_unit_region
+            .entry(decoded.packet.unit_id)
+            .or_default();
+        decoded
+            .packet
+            .hits
+            .iter()
+            .filter(|hit| hit.region < 4)
+            .for_each(|hit| region_hits[hit.region as usize] += 1);
+        Ok(true)
+    }
+
+    fn on_skip(&mut self, skipped: &[u8], error: Option<&ParseError>) {
+        if let Some(e) = error {
+            self.record_error(e.kind());
+        }
+        self.record_gap(skipped);
+    }
+}
+
+impl Display for RunStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let opt_to_string = |opt: Option<usize>| opt.map_or("-".to_string(), |v| v.to_string());
+        let rows: Vec<(String, String)> = [
+            ("Total bytes", self.total_bytes.to_string()),
+            ("Packets", self.packets.to_string()),
+            ("Empty packets", self.empty_packets.to_string()),
+            ("Hits", self.hits().to_string()),
+            ("Idle 0xFF (no backbone)", self.idle_no_backbone.to_string()),
+            ("Idle 0xF0 (4-bit DMU)", self.idle_four_bit.to_string()),
+            ("Idle 0xFC (2-bit DMU)", self.idle_two_bit.to_string()),
+            ("Idle 0xFE (1-bit DMU)", self.idle_one_bit.to_string()),
+            ("Delimiter bytes", self.delimiter_bytes.to_string()),
+            (
+                "Bytes between events",
+                self.bytes_between_events.to_string(),
+            ),
+            ("Largest event size", opt_to_string(self.largest_event_size)),
+            (
+                "Smallest event size",
+                opt_to_string(self.smallest_event_size),
+            ),
+            ("Mixed idle packets", self.mixed_idle_packets.to_string()),
+            ("DMU mode changes", self.dmu_mode_changes.to_string()),
+        ]
+        .into_iter()
+        .map(|(name, val)| (name.to_string(), val))
+        .chain(self.hits_per_unit_region.iter().map(|(unit_id, regions)| {
+            (
+                format!("Hits unit {unit_id} (region 0/1/2/3)"),
+                regions
+                    .iter()
+                    .map(|cnt| cnt.to_string())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            )
+        }))
+        .chain(
+            self.errors
+                .iter()
+                .map(|(kind, cnt)| (format!("Errors {kind}"), cnt.to_string())),
+        )
+        .collect();
+
+        let name_width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let table = rows
+            .iter()
+            .map(|(name, val)| format!("{name:<name_width$} : {val}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        write!(f, "{table}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Read;
+
+    #[test]
+    fn test_run_stats_multiple_events() {
+        let mut bytes = vec![0xFA, 0xFA];
+        bytes.extend(fake_event_simple());
+        bytes.extend(vec![0xFA, 0xFA, 0xFA]);
+        bytes.extend(fake_multiple_events());
+
+        let stats = crate::run_stats(&bytes);
+        println!("{stats}");
+
+        assert_eq!(stats.total_bytes, bytes.len());
+        // The empty unit 2 event has an IDLE after a region header which is a protocol error
+        assert_eq!(stats.packets, 4);
+        assert_eq!(stats.empty_packets, 0);
+        assert_eq!(stats.hits(), 4 + 3 + 3 + 4);
+        assert_eq!(stats.hits_per_unit_region[&1], [4, 3, 0, 3]);
+        assert_eq!(stats.hits_per_unit_region[&3], [1, 1, 1, 1]);
+        assert_eq!(stats.idle_no_backbone, 1 + 3 + 1);
+        assert_eq!(stats.delimiter_bytes, 5);
+        assert_eq!(stats.bytes_between_events, 5 + 7);
+        assert_eq!(stats.largest_event_size, Some(19));
+        assert_eq!(stats.smallest_event_size, Some(15));
+        assert_eq!(
+            stats.error_counts(),
+            BTreeMap::from([("ProtocolError".to_string(), 1)])
+        );
+    }
+
+    #[test]
+    fn test_run_stats_partial_chunks() {
+        let mut bytes = fake_event_simple();
+        bytes.extend(fake_event_simple());
+
+        let mut stats = RunStats::default();
+        let mut decoder = EventDecoder::new(crate::ErrorPolicy::SkipBadEvents);
+        let consumed = stats.accumulate(&mut decoder, &bytes[..25]);
+        assert_eq!(consumed, 19);
+        assert_eq!(stats.packets, 1);
+
+        let consumed = stats.accumulate(&mut decoder, &bytes[consumed..]);
+        assert_eq!(consumed, 19);
+        stats.finish(&[]);
+        assert_eq!(stats.packets, 2);
+        assert_eq!(stats.total_bytes, bytes.len());
+        // Read in the same two chunks
+        assert_eq!(
+            crate::source::run_stats(bytes[..25].chain(&bytes[25..])).unwrap(),
+            stats
+        );
+    }
+
+    #[test]
+    fn test_run_stats_errors() {
+        let mut bytes = fake_event_protocol_error();
+        bytes.extend(fake_event_simple());
+        bytes.extend(vec![UNIT_FRAME_HEADER_1, REGION_HEADER_0]); // Partial event
+
+        let stats = crate::run_stats(&bytes);
+
+        println!("{stats}");
+        assert_eq!(stats.packets, 1);
+        assert_eq!(stats.total_bytes, bytes.len());
+        assert_eq!(
+            stats.error_counts(),
+            BTreeMap::from([
+                ("EndOfBufferNoTrailer".to_string(), 1),
+                ("ProtocolError".to_string(), 1)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_run_stats_event_without_trailer() {
+        let event = fake_event_simple();
+        let truncated = &event[..event.len() - 1];
+        let bytes = [&event, truncated, &event, &event].concat();
+
+        let stats = crate::run_stats(&bytes);
+
+        assert_eq!(stats.packets, 3);
+        assert_eq!(stats.hits(), 3 * 4);
+        assert_eq!(
+            stats.error_counts(),
+            BTreeMap::from([("ProtocolError".to_string(), 1)])
+        );
+    }
+}
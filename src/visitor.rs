@@ -0,0 +1,132 @@
+//! Allocation-free decoding where the hits are passed to a [HitVisitor] instead of being collected in [MossPacket](crate::MossPacket)s.
+//!
+//! Walks the same FSM as the other decoding functions, useful for consumers like histogrammers that process hits in place at high rates.
+use crate::parse_error::ParseErrorKind;
+use crate::{rust_only, DecodeError, MossHit};
+
+/// Receives the packets and hits decoded by [decode_with].
+///
+/// Any `FnMut(u8, MossHit)` closure taking the unit ID and a hit is a [HitVisitor].
+pub trait HitVisitor {
+    /// Called at the Unit Frame Header of a packet, before any of its hits.
+    fn on_packet_start(&mut self, _unit_id: u8) {}
+    /// Called for each decoded hit in the packet from the unit with `unit_id`.
+    fn on_hit(&mut self, unit_id: u8, hit: MossHit);
+    /// Called at the Unit Frame Trailer of a packet, after all of its hits.
+    fn on_packet_end(&mut self, _unit_id: u8) {}
+}
+
+impl<F: FnMut(u8, MossHit)> HitVisitor for F {
+    #[inline]
+    fn on_hit(&mut self, unit_id: u8, hit: MossHit) {
+        self(unit_id, hit)
+    }
+}
+
+/// Decodes all complete events in `bytes` and passes the packets and hits to the `visitor`, returns the number of bytes consumed.
+///
+/// Bytes after the last complete event are not consumed, and their hits are not visited, so they can be prepended to the next chunk of data.
+/// On error, the hits of the failing packet before the error may already have been visited, but [HitVisitor::on_packet_end] is not called for it.
+///
+/// # Example
+/// ```
+/// # let bytes = [0xD1, 0xC0, 0x00, 0x50, 0x88, 0xE0];
+/// let mut hits_per_region = [0usize; 4];
+/// let consumed = moss_decoder::decode_with(&bytes, &mut |_unit_id, hit: moss_decoder::MossHit| {
+///     hits_per_region[hit.region as usize] += 1
+/// })
+/// .unwrap();
+/// assert_eq!(consumed, bytes.len());
+/// assert_eq!(hits_per_region, [1, 0, 0, 0]);
+/// ```
+pub fn decode_with(bytes: &[u8], visitor: &mut impl HitVisitor) -> Result<usize, DecodeError> {
+    let mut consumed = 0;
+    let mut packet_cnt = 0;
+    while consumed < bytes.len() {
+        match rust_only::visit_packet(&bytes[consumed..], visitor) {
+            Ok(trailer_idx) => {
+                packet_cnt += 1;
+                consumed += trailer_idx + 1;
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ParseErrorKind::EndOfBufferNoTrailer | ParseErrorKind::NoHeaderFound
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(DecodeError::parse(packet_cnt + 1, &e)),
+        }
+    }
+    Ok(consumed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::*;
+    use crate::{decode_all_events, MossPacket};
+    use pretty_assertions::assert_eq;
+
+    /// Rebuilds the packets from the visited hits to compare with the regular decoder.
+    #[derive(Default)]
+    struct PacketCollector {
+        packets: Vec<MossPacket>,
+        packet_ends: usize,
+    }
+
+    impl HitVisitor for PacketCollector {
+        fn on_packet_start(&mut self, unit_id: u8) {
+            self.packets.push(MossPacket::new(unit_id));
+        }
+        fn on_hit(&mut self, unit_id: u8, hit: MossHit) {
+            let packet = self.packets.last_mut().unwrap();
+            assert_eq!(packet.unit_id, unit_id);
+            packet.hits.push(hit);
+        }
+        fn on_packet_end(&mut self, _unit_id: u8) {
+            self.packet_ends += 1;
+        }
+    }
+
+    #[test]
+    fn test_visitor_matches_decoder() {
+        pyo3::prepare_freethreaded_python();
+        let bytes = [fake_event_simple(), fake_event_simple()].concat();
+        let mut collector = PacketCollector::default();
+
+        assert_eq!(decode_with(&bytes, &mut collector).unwrap(), bytes.len());
+        let (expect_packets, _) = decode_all_events(&bytes).unwrap();
+        assert_eq!(collector.packets, expect_packets);
+        assert_eq!(collector.packet_ends, 2);
+    }
+
+    #[test]
+    fn test_partial_event_not_visited() {
+        let event = fake_event_simple();
+        let bytes = [&event[..], &event[..event.len() - 1]].concat();
+        let mut hit_cnt = 0;
+
+        let consumed = decode_with(&bytes, &mut |_, _| hit_cnt += 1).unwrap();
+        assert_eq!(consumed, event.len());
+        assert_eq!(hit_cnt, 4);
+    }
+
+    #[test]
+    fn test_protocol_error() {
+        let bytes = [fake_event_simple(), fake_event_protocol_error()].concat();
+        let mut collector = PacketCollector::default();
+
+        match decode_with(&bytes, &mut collector) {
+            Err(DecodeError::Parse {
+                packet_num, kind, ..
+            }) => {
+                assert_eq!(packet_num, 2);
+                assert_eq!(kind, ParseErrorKind::ProtocolError);
+            }
+            other => panic!("Expected a protocol error, got {other:?}"),
+        }
+        assert_eq!(collector.packet_ends, 1);
+    }
+}
@@ -1,5 +1,6 @@
 """Integration tests. Uses the `moss_decoder` package
 from python and allows benchmarks."""
+import pickle
 import sys  # Don't want to depend on `argparse`
 import time
 from pathlib import Path
@@ -338,6 +339,33 @@ def test_fundamental_class_comparisons():
     print("\n==> MossPacket is OK\n\n")
 
 
+def test_pickle_hash_and_sequence_protocols(file_path: Path):
+    """Test pickling, hashing, ordering and the sequence protocol of MossHit and MossPacket"""
+
+    print("=== Pickling, hashing and ordering ===\n")
+    packets = moss_decoder.decode_from_file(file_path)
+    assert pickle.loads(pickle.dumps(packets)) == packets
+    assert pickle.loads(pickle.dumps(packets[0].hits[0])) == packets[0].hits[0]
+    print("\tpickle is OK")
+
+    assert len(set(packets)) == len({p.to_tuple() for p in packets})
+    assert {MossHit(0, 1, 2): "hit"}[MossHit(0, 1, 2)] == "hit"
+    print("\t__hash__ is OK")
+
+    assert MossHit(1, 5, 5) > MossHit(1, 4, 9)
+    assert sorted(packets) == sorted(packets, key=lambda p: p.to_tuple())
+    print("\tordering is OK")
+
+    packet = max(packets, key=len)
+    assert len(packet) == len(packet.hits)
+    assert list(packet) == packet.hits
+    assert packet[-1] == packet.hits[-1]
+    assert packet.to_dict()["hits"][0] == packet[0].to_dict()
+    print("\tsequence protocol is OK")
+
+    print("==> Test OK\n\n")
+
+
 def test_debug_decode_events(
     test_file: Path,
     expect_trailer_idx: int,
@@ -416,6 +444,7 @@ if __name__ == "__main__":
             sys.exit(0)
 
     test_fundamental_class_comparisons()
+    test_pickle_hash_and_sequence_protocols(file_path=FILE_PATTERN_ALL_REGIONS)
     test_decode_partial_events_from_two_files()
 
     start = time.time()
@@ -148,7 +148,7 @@ fn test_decoding_single_event() {
                     row: 2,
                     column: 8
                 },
-            ]
+            ],
         },
         "unexpected decoding result"
     );
@@ -191,7 +191,7 @@ fn test_decoding_single_event_fsm() {
                     row: 2,
                     column: 8
                 },
-            ]
+            ],
         },
         "unexpected decoding result"
     );
@@ -549,6 +549,28 @@ fn test_decode_split_events_with_remainder() {
     assert_eq!(remainder.len(), 43);
 }
 
+#[test]
+fn test_skip_n_take_all_input_shorter_than_an_event() {
+    pyo3::prepare_freethreaded_python();
+    // Empty, or only delimiters
+    for bytes in [&[][..], &[0xFA], &[0xFA, 0xFA]] {
+        let (packets, remainder) = skip_n_take_all(bytes, 0).unwrap();
+        assert_eq!(packets, None);
+        assert_eq!(remainder, None);
+    }
+}
+
+#[test]
+fn test_skip_n_take_all_short_partial_event_is_remainder() {
+    pyo3::prepare_freethreaded_python();
+    let partial_event = [UNIT_FRAME_HEADER_1, REGION_HEADER_0];
+    let bytes = [&fake_event_simple()[..], &partial_event].concat();
+
+    let (packets, remainder) = skip_n_take_all(&bytes, 0).unwrap();
+    assert_eq!(packets.unwrap().len(), 1);
+    assert_eq!(remainder.unwrap(), partial_event);
+}
+
 #[test]
 fn test_decode_split_events_from_both_files() {
     pyo3::prepare_freethreaded_python();
@@ -866,3 +888,1064 @@ fn test_compare_result_3_events_partial_start() {
         }
     }
 }
+
+#[test]
+fn test_decode_from_file_with_noise_mask() {
+    pyo3::prepare_freethreaded_python();
+    let packets = decode_from_file(FILE_MOSS_NOISE_ALL_REGION.into()).unwrap();
+    let mask = PixelMask::from_packets(&packets, 0.01).unwrap();
+    assert!(!mask.pixels().is_empty());
+
+    let masked_packets = decode_from_file_with(
+        FILE_MOSS_NOISE_ALL_REGION.into(),
+        DecodeOptions {
+            mask: Some(&mask),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(masked_packets.len(), NOISE_ALL_REGION_PACKETS);
+
+    let masked_hits = masked_packets.iter().fold(0, |acc, p| acc + p.hits.len());
+    println!(
+        "Masked {} pixels, hits left: {masked_hits}",
+        mask.pixels().len()
+    );
+    assert!(masked_hits < NOISE_ALL_REGION_HITS);
+    assert!(masked_packets
+        .iter()
+        .all(|p| p.hits.iter().all(|h| !mask.is_masked(p.unit_id, h))));
+
+    // Masking while decoding bytes gives the same result
+    let bytes = std::fs::read(std::path::PathBuf::from(FILE_MOSS_NOISE_ALL_REGION)).unwrap();
+    let (masked_packets_from_bytes, _) = decode_all_events_with(
+        &bytes,
+        DecodeOptions {
+            mask: Some(&mask),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    compare_all_packets(&masked_packets, &masked_packets_from_bytes);
+}
+
+#[test]
+fn test_validate_all_events_from_files() {
+    pyo3::prepare_freethreaded_python();
+    for test_file in [
+        FILE_MOSS_NOISE_ALL_REGION,
+        FILE_NOISE_RANDOM_REGION,
+        FILE_PATTERN_ALL_REGIONS,
+        FILE_4_EVENTS_PARTIAL_END,
+        FILE_3_EVENTS_PARTIAL_START,
+    ] {
+        let diagnostics = validate_all_events_from_file(test_file.into()).unwrap();
+        assert!(
+            diagnostics.is_empty(),
+            "Expected no diagnostics for {test_file}, got: {diagnostics:?}"
+        );
+    }
+}
+
+#[test]
+fn test_diagnose_bit_flips_corrected_trailers() {
+    pyo3::prepare_freethreaded_python();
+    for test_file in [
+        FILE_MOSS_NOISE_ALL_REGION,
+        FILE_NOISE_RANDOM_REGION,
+        FILE_PATTERN_ALL_REGIONS,
+    ] {
+        let expect_packets = decode_from_file(test_file.into()).unwrap();
+        let report = diagnose_bit_flips_from_file(test_file.into(), false).unwrap();
+        assert_eq!(report.packets, expect_packets);
+        assert!(report.diagnostics.is_empty());
+
+        // A flipped bit 3 turns the trailer into 0xE8, which is one bit flip away from only the trailer in every state
+        let mut bytes = std::fs::read(test_file).unwrap();
+        bytes
+            .iter_mut()
+            .filter(|b| **b == 0xE0)
+            .for_each(|b| *b ^= 0x08);
+
+        let report = diagnose_bit_flips(&bytes, false).unwrap();
+        assert!(report.packets.is_empty());
+        assert_eq!(report.diagnostics.len(), expect_packets.len());
+        assert_eq!(report.uncorrectable, 0);
+
+        let report = diagnose_bit_flips(&bytes, true).unwrap();
+        assert_eq!(report.packets, expect_packets);
+        assert_eq!(report.corrected, expect_packets.len());
+        assert!(report
+            .diagnostics
+            .iter()
+            .all(|d| d.likely_word == Some(0xE0) && d.flipped_bit() == Some(3)));
+    }
+}
+
+#[test]
+fn test_run_stats_from_files() {
+    pyo3::prepare_freethreaded_python();
+    for (test_file, expect_packets, expect_hits) in [
+        (
+            FILE_MOSS_NOISE_ALL_REGION,
+            NOISE_ALL_REGION_PACKETS,
+            NOISE_ALL_REGION_HITS,
+        ),
+        (
+            FILE_NOISE_RANDOM_REGION,
+            NOISE_RANDOM_REGION_PACKETS,
+            NOISE_RANDOM_REGION_HITS,
+        ),
+        (
+            FILE_PATTERN_ALL_REGIONS,
+            PATTERN_ALL_REGIONS_PACKETS,
+            PATTERN_ALL_REGIONS_HITS,
+        ),
+    ] {
+        let stats = run_stats_from_file(test_file.into()).unwrap();
+        println!("{test_file}\n{stats}");
+        assert_eq!(stats.packets, expect_packets);
+        assert_eq!(stats.hits(), expect_hits);
+        assert!(stats.error_counts().is_empty());
+
+        let bytes = std::fs::read(std::path::PathBuf::from(test_file)).unwrap();
+        assert_eq!(stats.total_bytes, bytes.len());
+        assert_eq!(run_stats(&bytes), stats);
+    }
+}
+
+#[test]
+fn test_decode_all_events_with_idle_counts() {
+    pyo3::prepare_freethreaded_python();
+    let bytes = std::fs::read(std::path::PathBuf::from(FILE_MOSS_NOISE_ALL_REGION)).unwrap();
+
+    let (packets_with_counts, last_trailer_idx, mode_changes) =
+        decode_all_events_with_idle_counts(&bytes, DecodeOptions::default()).unwrap();
+    let (expect_packets, expect_last_trailer_idx) = decode_all_events(&bytes).unwrap();
+
+    let (packets, idle_counts): (Vec<MossPacket>, Vec<IdleCounts>) =
+        packets_with_counts.into_iter().unzip();
+    assert_eq!(last_trailer_idx, expect_last_trailer_idx);
+    compare_all_packets(&packets, &expect_packets);
+    assert!(mode_changes.is_empty());
+
+    assert!(idle_counts
+        .iter()
+        .all(|c| !c.is_mixed() && c.dmu_mode().is_none_or(|mode| mode == DmuMode::NoBackbone)));
+    assert_eq!(
+        idle_counts
+            .iter()
+            .map(|c| c.total() as usize)
+            .sum::<usize>(),
+        run_stats(&bytes).idle_no_backbone
+    );
+}
+
+/// Returns the packets of [FILE_PATTERN_ALL_REGIONS] and its events, each preceded by a 12 byte prefix with a 8 byte timestamp and 4 byte trigger ID.
+fn prefixed_pattern_all_regions() -> (Vec<MossPacket>, Vec<u8>) {
+    let bytes = std::fs::read(std::path::PathBuf::from(FILE_PATTERN_ALL_REGIONS)).unwrap();
+    let (expect_packets, _) = decode_all_events(&bytes).unwrap();
+
+    let mut prefixed_bytes = Vec::new();
+    let mut last_trailer_idx = 0;
+    for (trigger_id, _) in expect_packets.iter().enumerate() {
+        let (_, trailer_idx) = decode_event(&bytes[last_trailer_idx..]).unwrap();
+        let timestamp = 1_000_000 + 25 * trigger_id as u64;
+        prefixed_bytes.extend_from_slice(&timestamp.to_le_bytes());
+        prefixed_bytes.extend_from_slice(&(trigger_id as u32).to_le_bytes());
+        prefixed_bytes.extend_from_slice(&bytes[last_trailer_idx..=last_trailer_idx + trailer_idx]);
+        last_trailer_idx += trailer_idx + 1;
+    }
+    (expect_packets, prefixed_bytes)
+}
+
+#[test]
+fn test_decode_all_events_with_prefix() {
+    pyo3::prepare_freethreaded_python();
+    let (expect_packets, prefixed_bytes) = prefixed_pattern_all_regions();
+
+    // Without the framing layer the prefix is an invalid delimiter
+    assert!(decode_all_events(&prefixed_bytes).is_err());
+
+    let prefix = FpgaPrefix::new(12, 0, 8, 8, 4, false).unwrap();
+    let (prefixed_packets, last_trailer_idx) =
+        decode_all_events_with_prefix(&prefixed_bytes, &prefix, DecodeOptions::default()).unwrap();
+
+    assert_eq!(last_trailer_idx, prefixed_bytes.len() - 1);
+    assert_eq!(prefixed_packets.len(), PATTERN_ALL_REGIONS_PACKETS);
+    for (i, (packet, timestamp, trigger_id)) in prefixed_packets.into_iter().enumerate() {
+        assert_eq!(packet, expect_packets[i]);
+        assert_eq!(timestamp, Some(1_000_000 + 25 * i as u64));
+        assert_eq!(trigger_id, Some(i as u32));
+    }
+}
+
+#[test]
+fn test_decode_prefixed_events() {
+    pyo3::prepare_freethreaded_python();
+    let (expect_packets, prefixed_bytes) = prefixed_pattern_all_regions();
+    let prefix = FpgaPrefix::new(12, 0, 8, 8, 4, false).unwrap();
+    let options = DecodeOptions {
+        prefix: Some(&prefix),
+        ..Default::default()
+    };
+
+    let (packets, last_trailer_idx) = decode_all_events_with(&prefixed_bytes, options).unwrap();
+    assert_eq!(last_trailer_idx, prefixed_bytes.len() - 1);
+    compare_all_packets(&packets, &expect_packets);
+    let (batch, _) = decode_all_events_batch_with(&prefixed_bytes, options).unwrap();
+    let packets: Vec<MossPacket> = batch.iter().map(|p| p.to_packet()).collect();
+    compare_all_packets(&packets, &expect_packets);
+    // Prefixed data is decoded sequentially
+    let (packets, _) = pyo3::Python::with_gil(|py| {
+        decode_all_events_parallel_with(py, &prefixed_bytes, Some(4), options)
+    })
+    .unwrap();
+    compare_all_packets(&packets, &expect_packets);
+
+    let (packets, _) = decode_n_events_with(&prefixed_bytes, 10, Some(500), None, options).unwrap();
+    compare_all_packets(&packets, &expect_packets[500..510]);
+    let (packets, remainder) =
+        skip_n_take_all_with(&prefixed_bytes[..prefixed_bytes.len() - 5], 990, options).unwrap();
+    compare_all_packets(&packets.unwrap(), &expect_packets[990..999]);
+    // The remainder holds the prefix of the last event
+    let (packets, _) = decode_n_events_with(
+        &prefixed_bytes[prefixed_bytes.len() - 5..],
+        1,
+        None,
+        remainder,
+        options,
+    )
+    .unwrap();
+    compare_all_packets(&packets, &expect_packets[999..]);
+
+    let path = std::env::temp_dir().join("moss_decoder_test_decode_prefixed_events.raw");
+    std::fs::write(&path, &prefixed_bytes).unwrap();
+    compare_all_packets(
+        &decode_from_file_with(path.clone(), options).unwrap(),
+        &expect_packets,
+    );
+    let batch = decode_from_file_batch_with(path.clone(), options).unwrap();
+    let packets: Vec<MossPacket> = batch.iter().map(|p| p.to_packet()).collect();
+    compare_all_packets(&packets, &expect_packets);
+    compare_all_packets(
+        &decode_n_events_from_file_with(path.clone(), 10, Some(500), None, options).unwrap(),
+        &expect_packets[500..510],
+    );
+    let (packets, remainder) = skip_n_take_all_from_file_with(path.clone(), 990, options).unwrap();
+    compare_all_packets(&packets.unwrap(), &expect_packets[990..]);
+    assert_eq!(remainder, None);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_decode_all_events_with_prefix_huge_length() {
+    pyo3::prepare_freethreaded_python();
+    let bytes = std::fs::read(std::path::PathBuf::from(FILE_PATTERN_ALL_REGIONS)).unwrap();
+
+    assert!(FpgaPrefix::new(usize::MAX, 0, 0, 0, 0, false).is_err());
+
+    // The longest accepted prefix can't be present in the data
+    let length = usize::MAX - 3;
+    let prefix = FpgaPrefix::new(length, length - 8, 8, length - 4, 4, false).unwrap();
+    assert!(decode_all_events_with_prefix(&bytes, &prefix, DecodeOptions::default()).is_err());
+    let options = DecodeOptions {
+        prefix: Some(&prefix),
+        ..Default::default()
+    };
+    assert!(decode_all_events_with(&bytes, options).is_err());
+    assert!(decode_n_events_with(&bytes, 1, Some(1), None, options).is_err());
+    assert_eq!(
+        skip_n_take_all_with(&bytes, 0, options).unwrap(),
+        (None, Some(bytes.clone()))
+    );
+    assert!(decode_from_file_with(FILE_PATTERN_ALL_REGIONS.into(), options).is_err());
+}
+
+#[test]
+fn test_decode_from_reader_matches_file() {
+    pyo3::prepare_freethreaded_python();
+    for test_file in [
+        FILE_MOSS_NOISE_ALL_REGION,
+        FILE_NOISE_RANDOM_REGION,
+        FILE_PATTERN_ALL_REGIONS,
+    ] {
+        let bytes = std::fs::read(std::path::PathBuf::from(test_file)).unwrap();
+        let cursor = || std::io::Cursor::new(bytes.clone());
+
+        let expect_packets = decode_from_file(test_file.into()).unwrap();
+        compare_all_packets(
+            &source::decode_all(cursor(), DecodeOptions::default()).unwrap(),
+            &expect_packets,
+        );
+        compare_all_packets(
+            &source::decode_n(cursor(), 10, Some(5), None, DecodeOptions::default()).unwrap(),
+            &expect_packets[5..15],
+        );
+        let (skipped_packets, _) =
+            source::skip_n_take_all(cursor(), 100, DecodeOptions::default()).unwrap();
+        compare_all_packets(&skipped_packets.unwrap(), &expect_packets[100..]);
+        assert_eq!(
+            source::run_stats(cursor()).unwrap(),
+            run_stats_from_file(test_file.into()).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_decode_with_visitor_from_files() {
+    pyo3::prepare_freethreaded_python();
+    for test_file in [
+        FILE_MOSS_NOISE_ALL_REGION,
+        FILE_NOISE_RANDOM_REGION,
+        FILE_PATTERN_ALL_REGIONS,
+    ] {
+        let bytes = std::fs::read(std::path::PathBuf::from(test_file)).unwrap();
+        let (expect_packets, expect_last_trailer_idx) = decode_all_events(&bytes).unwrap();
+
+        let mut hits = Vec::new();
+        let consumed = decode_with(&bytes, &mut |unit_id, hit| hits.push((unit_id, hit))).unwrap();
+
+        assert_eq!(consumed, expect_last_trailer_idx + 1);
+        let expect_hits: Vec<(u8, MossHit)> = expect_packets
+            .iter()
+            .flat_map(|p| p.hits.iter().map(|hit| (p.unit_id, *hit)))
+            .collect();
+        assert_eq!(hits, expect_hits);
+    }
+}
+
+#[test]
+fn test_decode_packet_batch_from_files() {
+    pyo3::prepare_freethreaded_python();
+    for (test_file, expect_hits) in [
+        (FILE_MOSS_NOISE_ALL_REGION, NOISE_ALL_REGION_HITS),
+        (FILE_NOISE_RANDOM_REGION, NOISE_RANDOM_REGION_HITS),
+        (FILE_PATTERN_ALL_REGIONS, PATTERN_ALL_REGIONS_HITS),
+    ] {
+        let expect_packets = decode_from_file(test_file.into()).unwrap();
+        let batch = decode_from_file_batch(test_file.into()).unwrap();
+
+        assert_eq!(batch.len(), expect_packets.len());
+        assert_eq!(batch.hit_count(), expect_hits);
+        assert_eq!(batch.packet_offsets().last(), Some(&(expect_hits as u64)));
+        let packets: Vec<MossPacket> = batch.iter().map(|p| p.to_packet()).collect();
+        compare_all_packets(&packets, &expect_packets);
+
+        let bytes = std::fs::read(std::path::PathBuf::from(test_file)).unwrap();
+        let (bytes_batch, _) = decode_all_events_batch(&bytes).unwrap();
+        assert_eq!(bytes_batch, batch);
+    }
+}
+
+#[test]
+fn test_decode_all_events_parallel_matches_sequential() {
+    pyo3::prepare_freethreaded_python();
+    for test_file in [
+        FILE_MOSS_NOISE_ALL_REGION,
+        FILE_NOISE_RANDOM_REGION,
+        FILE_PATTERN_ALL_REGIONS,
+    ] {
+        let bytes = std::fs::read(std::path::PathBuf::from(test_file)).unwrap();
+        let expect = decode_all_events(&bytes).unwrap();
+        for threads in [1, 2, 3, 8, 1000] {
+            let got = pyo3::Python::with_gil(|py| {
+                decode_all_events_parallel(py, &bytes, Some(threads)).unwrap()
+            });
+            assert_eq!(got, expect, "{test_file} with {threads} threads");
+        }
+    }
+}
+
+#[test]
+fn test_decode_all_events_parallel_huge_threads() {
+    pyo3::prepare_freethreaded_python();
+    let bytes = std::fs::read(std::path::PathBuf::from(FILE_PATTERN_ALL_REGIONS)).unwrap();
+    let expect = decode_all_events(&bytes).unwrap();
+    for threads in [bytes.len(), 1 << 62, usize::MAX] {
+        let got = pyo3::Python::with_gil(|py| {
+            decode_all_events_parallel(py, &bytes, Some(threads)).unwrap()
+        });
+        assert_eq!(got, expect, "{threads} threads");
+    }
+}
+
+#[test]
+fn test_decode_all_events_parallel_error_packet_number() {
+    pyo3::prepare_freethreaded_python();
+    let mut events = fake_event_simple().repeat(20);
+    // Corrupt a data word of the 15th event
+    let event_len = fake_event_simple().len();
+    events[14 * event_len + 3] = 0x2F;
+
+    let expect_err = decode_all_events(&events).unwrap_err().to_string();
+    assert!(expect_err.contains("packet #15"), "{expect_err}");
+    for threads in [1, 4, 20] {
+        let err = pyo3::Python::with_gil(|py| {
+            decode_all_events_parallel(py, &events, Some(threads)).unwrap_err()
+        });
+        assert_eq!(err.to_string(), expect_err);
+    }
+}
+
+#[test]
+fn test_skip_n_take_all_with_vectorised_scan() {
+    pyo3::prepare_freethreaded_python();
+    let bytes = std::fs::read(std::path::PathBuf::from(FILE_PATTERN_ALL_REGIONS)).unwrap();
+    let (all_packets, _) = decode_all_events(&bytes).unwrap();
+
+    let skip = all_packets.len() / 2;
+    let (packets, _) = skip_n_take_all(&bytes, skip).unwrap();
+    assert_eq!(packets.unwrap(), all_packets[skip..]);
+
+    let boundaries: Vec<_> = moss_decoder::scan::event_boundaries(&bytes).collect();
+    assert_eq!(boundaries.len(), all_packets.len());
+}
+
+#[test]
+fn test_table_hit_decoder_matches_fsm() {
+    pyo3::prepare_freethreaded_python();
+    for test_file in [
+        FILE_MOSS_NOISE_ALL_REGION,
+        FILE_NOISE_RANDOM_REGION,
+        FILE_PATTERN_ALL_REGIONS,
+    ] {
+        let bytes = std::fs::read(std::path::PathBuf::from(test_file)).unwrap();
+        set_hit_decoder(HitDecoder::Fsm);
+        let expect = decode_all_events(&bytes).unwrap();
+        set_hit_decoder(HitDecoder::Table);
+        assert_eq!(get_hit_decoder(), HitDecoder::Table);
+        let got = decode_all_events(&bytes).unwrap();
+        set_hit_decoder(HitDecoder::Fsm);
+        assert_eq!(got, expect, "{test_file}");
+    }
+}
+
+#[test]
+fn test_event_index_random_access() {
+    pyo3::prepare_freethreaded_python();
+    for test_file in [FILE_MOSS_NOISE_ALL_REGION, FILE_PATTERN_ALL_REGIONS] {
+        // Work on a copy so the sidecar file is not written next to the test data
+        let path = std::env::temp_dir().join(format!(
+            "moss_decoder_test_event_index_{}",
+            std::path::Path::new(test_file)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+        ));
+        _ = std::fs::copy(test_file, &path).unwrap();
+        let sidecar = EventIndex::sidecar_path(path.clone());
+        _ = std::fs::remove_file(&sidecar);
+
+        let expect_packets = decode_from_file(path.clone()).unwrap();
+        let index = index_file(path.clone()).unwrap();
+        assert!(sidecar.exists());
+        assert_eq!(index.len(), expect_packets.len());
+        assert_eq!(
+            index.hit_counts(),
+            expect_packets
+                .iter()
+                .map(|p| p.hits.len() as u32)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(EventIndex::load_sidecar(&path), Some(index.clone()));
+
+        let last = expect_packets.len() - 1;
+        let packets = read_events(path.clone(), vec![last, 0, 5], None, None).unwrap();
+        assert_eq!(
+            packets,
+            vec![
+                expect_packets[last].clone(),
+                expect_packets[0].clone(),
+                expect_packets[5].clone()
+            ]
+        );
+        assert!(read_events(path.clone(), vec![last + 1], None, None).is_err());
+
+        // Skipping seeks directly to the first event to decode
+        let skipped = decode_n_events_from_file(path.clone(), 10, Some(100), None).unwrap();
+        assert_eq!(skipped, expect_packets[100..110]);
+
+        // An index built for a different file length is outdated
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.extend_from_slice(&[0xFA; 4]);
+        std::fs::write(&path, bytes).unwrap();
+        assert_eq!(EventIndex::load_sidecar(&path), None);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&sidecar).unwrap();
+    }
+}
+
+#[test]
+fn test_decode_filter_matches_manual_filtering() {
+    pyo3::prepare_freethreaded_python();
+    let filter = DecodeFilter::new(
+        Some(vec![1, 3, 5, 7, 9]),
+        Some(vec![1, 2]),
+        Some(2),
+        Some(8),
+        true,
+    )
+    .unwrap();
+    for test_file in [
+        FILE_MOSS_NOISE_ALL_REGION,
+        FILE_NOISE_RANDOM_REGION,
+        FILE_PATTERN_ALL_REGIONS,
+    ] {
+        let bytes = std::fs::read(test_file).unwrap();
+        let (all_packets, last_trailer_idx) = decode_all_events(&bytes).unwrap();
+        let expect_packets: Vec<MossPacket> = all_packets
+            .into_iter()
+            .filter_map(|mut p| filter.apply(&mut p).then_some(p))
+            .collect();
+        assert!(!expect_packets.is_empty());
+
+        let (packets, filtered_last_trailer_idx) = decode_all_events_with(
+            &bytes,
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(packets, expect_packets);
+        assert_eq!(filtered_last_trailer_idx, last_trailer_idx);
+
+        let (packets, _) = pyo3::Python::with_gil(|py| {
+            decode_all_events_parallel_with(
+                py,
+                &bytes,
+                Some(4),
+                DecodeOptions {
+                    filter: Some(&filter),
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap();
+        assert_eq!(packets, expect_packets);
+
+        let packets = decode_from_file_with(
+            test_file.into(),
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(packets, expect_packets);
+
+        let (packets, _) = skip_n_take_all_with(
+            &bytes,
+            0,
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(packets.unwrap(), expect_packets);
+
+        let (batch, _) = decode_all_events_batch_with(
+            &bytes,
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            batch.iter().map(|p| p.to_packet()).collect::<Vec<_>>(),
+            expect_packets
+        );
+
+        let batch = decode_from_file_batch_with(
+            test_file.into(),
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            batch.iter().map(|p| p.to_packet()).collect::<Vec<_>>(),
+            expect_packets
+        );
+
+        let (packets, _, _) = decode_all_events_with_idle_counts(
+            &bytes,
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(packets.len(), expect_packets.len());
+
+        // take counts all events, including the events dropped by the filter
+        let event_cnt = bytes.iter().filter(|b| **b == 0xE0).count();
+        let (packets, n_last_trailer_idx) = decode_n_events_with(
+            &bytes,
+            event_cnt,
+            None,
+            None,
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(packets, expect_packets);
+        assert_eq!(n_last_trailer_idx, last_trailer_idx);
+
+        let packets = decode_n_events_from_file_with(
+            test_file.into(),
+            event_cnt,
+            None,
+            None,
+            DecodeOptions {
+                filter: Some(&filter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(packets, expect_packets);
+    }
+}
+
+#[test]
+fn test_decode_filter_keeps_error_packet_number() {
+    pyo3::prepare_freethreaded_python();
+    let mut bytes = std::fs::read(FILE_PATTERN_ALL_REGIONS).unwrap();
+    // Corrupt the trailer of the 11th event so it runs into the next header
+    let trailer_idx = bytes
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b == 0xE0)
+        .nth(10)
+        .unwrap()
+        .0;
+    bytes[trailer_idx] = 0xD1;
+    let reject_all = DecodeFilter::new(Some(vec![]), None, None, None, false).unwrap();
+
+    let unfiltered_err = decode_all_events(&bytes).unwrap_err();
+    let filtered_err = decode_all_events_with(
+        &bytes,
+        DecodeOptions {
+            filter: Some(&reject_all),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(filtered_err.to_string(), unfiltered_err.to_string());
+    assert!(unfiltered_err.to_string().contains("packet #11"));
+
+    let batch_err = decode_all_events_batch_with(
+        &bytes,
+        DecodeOptions {
+            filter: Some(&reject_all),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(batch_err.to_string().contains("packet #11"));
+    let n_err = decode_n_events_with(
+        &bytes,
+        20,
+        None,
+        None,
+        DecodeOptions {
+            filter: Some(&reject_all),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(n_err.to_string(), unfiltered_err.to_string());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_json_round_trip() {
+    pyo3::prepare_freethreaded_python();
+    for test_file in [FILE_NOISE_RANDOM_REGION, FILE_PATTERN_ALL_REGIONS] {
+        let bytes = std::fs::read(test_file).unwrap();
+        let (packets, _) = decode_all_events(&bytes).unwrap();
+        let json = serde_json::to_string(&packets).unwrap();
+        let round_tripped: Vec<MossPacket> = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, packets);
+
+        let diagnostics = validate_all_events(&bytes).unwrap();
+        let json = serde_json::to_string(&diagnostics).unwrap();
+        let round_tripped: Vec<HitDiagnostic> = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, diagnostics);
+    }
+}
+
+#[test]
+fn test_packet_file_round_trip() {
+    use moss_decoder::packet_file::{PacketReader, PacketWriter};
+
+    pyo3::prepare_freethreaded_python();
+    for test_file in [
+        FILE_MOSS_NOISE_ALL_REGION,
+        FILE_NOISE_RANDOM_REGION,
+        FILE_PATTERN_ALL_REGIONS,
+    ] {
+        let bytes = std::fs::read(test_file).unwrap();
+        let (packets, _) = decode_all_events(&bytes).unwrap();
+
+        let mut writer = PacketWriter::new(Vec::new()).unwrap();
+        for packet in &packets {
+            writer.write_packet(packet).unwrap();
+        }
+        let packet_file = writer.finish().unwrap();
+        assert!(packet_file.len() < bytes.len());
+
+        let read_packets = PacketReader::new(packet_file.as_slice())
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read_packets, packets);
+    }
+}
+
+#[test]
+fn test_decode_file_to_csv_round_trip() {
+    use moss_decoder::csv_io::CsvReader;
+
+    pyo3::prepare_freethreaded_python();
+    for (test_file, delimiter) in [
+        (FILE_NOISE_RANDOM_REGION, ","),
+        (FILE_PATTERN_ALL_REGIONS, "\t"),
+    ] {
+        let csv_path = std::env::temp_dir().join(format!(
+            "moss_decoder_test_csv_{}.txt",
+            std::path::Path::new(test_file)
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+        ));
+        let expect_packets = decode_from_file(test_file.into()).unwrap();
+
+        let events = decode_file_to_csv(
+            test_file.into(),
+            csv_path.clone(),
+            delimiter,
+            true,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(events as usize, expect_packets.len());
+        assert_eq!(read_csv(csv_path.clone()).unwrap(), expect_packets);
+
+        let index = EventIndex::build(test_file.into()).unwrap();
+        let records = CsvReader::new(std::io::BufReader::new(
+            std::fs::File::open(&csv_path).unwrap(),
+        ))
+        .unwrap()
+        .collect::<std::io::Result<Vec<_>>>()
+        .unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| r.offset.unwrap())
+                .collect::<Vec<_>>(),
+            index.offsets()
+        );
+        _ = std::fs::remove_file(csv_path);
+    }
+}
+
+#[test]
+fn test_decode_from_file_error_policy() {
+    use moss_decoder::csv_io::CsvReader;
+
+    pyo3::prepare_freethreaded_python();
+    let mut bad_event = fake_event_simple();
+    bad_event[9] = 0xC9; // Corrupted REGION_HEADER_1
+    let bytes = [fake_event_simple(), bad_event, fake_event_simple()].concat();
+    let path = std::env::temp_dir().join("moss_decoder_test_error_policy.raw");
+    std::fs::write(&path, &bytes).unwrap();
+    let expect_packet = decode_event(&fake_event_simple()).unwrap().0;
+
+    let err = decode_from_file(path.clone()).unwrap_err();
+    assert!(
+        err.to_string().contains("Failed decoding packet #2"),
+        "{err}"
+    );
+    assert_eq!(
+        decode_from_file_with(
+            path.clone(),
+            DecodeOptions {
+                policy: ErrorPolicy::SkipBadEvents,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        vec![expect_packet.clone(); 2]
+    );
+    assert_eq!(
+        decode_n_events_from_file_with(
+            path.clone(),
+            1,
+            Some(1),
+            None,
+            DecodeOptions {
+                policy: ErrorPolicy::SkipBadEvents,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
+        vec![expect_packet.clone()]
+    );
+    let (packets, _) = skip_n_take_all_from_file_with(
+        path.clone(),
+        0,
+        DecodeOptions {
+            policy: ErrorPolicy::SkipBadEvents,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(packets.unwrap().len(), 2);
+    let batch = decode_from_file_batch_with(
+        path.clone(),
+        DecodeOptions {
+            policy: ErrorPolicy::SkipBadEvents,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(batch.len(), 2);
+
+    let err = decode_from_file_with(
+        path.clone(),
+        DecodeOptions {
+            policy: ErrorPolicy::StopAndReturnPartial,
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    pyo3::Python::with_gil(|py| {
+        let partial: Vec<MossPacket> = err.value(py).getattr("partial").unwrap().extract().unwrap();
+        assert_eq!(partial, vec![expect_packet.clone()]);
+    });
+
+    // The rows written before the error are kept, the skipped event keeps its event number
+    let csv_path = std::env::temp_dir().join("moss_decoder_test_error_policy.csv");
+    let err = decode_file_to_csv(
+        path.clone(),
+        csv_path.clone(),
+        ",",
+        false,
+        None,
+        None,
+        Some(ErrorPolicy::StopAndReturnPartial),
+    )
+    .unwrap_err();
+    pyo3::Python::with_gil(|py| {
+        let partial: u64 = err.value(py).getattr("partial").unwrap().extract().unwrap();
+        assert_eq!(partial, 1);
+    });
+    assert_eq!(read_csv(csv_path.clone()).unwrap(), vec![expect_packet]);
+
+    let events = decode_file_to_csv(
+        path.clone(),
+        csv_path.clone(),
+        ",",
+        false,
+        None,
+        None,
+        Some(ErrorPolicy::SkipBadEvents),
+    )
+    .unwrap();
+    assert_eq!(events, 2);
+    let records = CsvReader::new(std::io::BufReader::new(
+        std::fs::File::open(&csv_path).unwrap(),
+    ))
+    .unwrap()
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap();
+    assert_eq!(
+        records.iter().map(|r| r.event).collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+    _ = std::fs::remove_file(csv_path);
+    _ = std::fs::remove_file(path);
+}
+
+#[cfg(feature = "hdf5")]
+#[test]
+fn test_decode_file_to_hdf5() {
+    use moss_decoder::hdf5_output::decode_file_to_hdf5;
+
+    pyo3::prepare_freethreaded_python();
+    let h5_path = std::env::temp_dir().join("moss_decoder_test_decode_file_to_hdf5.h5");
+    let expect_packets = decode_from_file(FILE_PATTERN_ALL_REGIONS.into()).unwrap();
+
+    let events = decode_file_to_hdf5(
+        FILE_PATTERN_ALL_REGIONS.into(),
+        h5_path.clone(),
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(events as usize, expect_packets.len());
+
+    let file = hdf5::File::open(&h5_path).unwrap();
+    let read_u64 = |name: &str| -> Vec<u64> { file.dataset(name).unwrap().read_raw().unwrap() };
+    let rows: Vec<u16> = file.dataset("hits/row").unwrap().read_raw().unwrap();
+    let unit_ids: Vec<u8> = file.dataset("events/unit_id").unwrap().read_raw().unwrap();
+    let hit_offsets = read_u64("events/hit_offset");
+
+    assert_eq!(read_u64("events/event"), (0..events).collect::<Vec<_>>());
+    assert_eq!(
+        read_u64("events/byte_offset"),
+        EventIndex::build(FILE_PATTERN_ALL_REGIONS.into())
+            .unwrap()
+            .offsets()
+    );
+    for (i, packet) in expect_packets.iter().enumerate() {
+        assert_eq!(unit_ids[i], packet.unit_id);
+        let start = hit_offsets[i] as usize;
+        assert_eq!(
+            rows[start..start + packet.hits.len()],
+            packet.hits.iter().map(|h| h.row).collect::<Vec<_>>()
+        );
+    }
+    drop(file);
+    _ = std::fs::remove_file(h5_path);
+}
+
+#[test]
+fn test_decode_minimum_size_input_does_not_panic() {
+    pyo3::prepare_freethreaded_python();
+    for bytes in [
+        &[UNIT_FRAME_HEADER_1, UNIT_FRAME_TRAILER][..],
+        &[UNIT_FRAME_HEADER_1, REGION_HEADER_0, UNIT_FRAME_TRAILER],
+    ] {
+        _ = decode_all_events(bytes);
+        _ = decode_all_events_with_idle_counts(bytes, DecodeOptions::default());
+        _ = debug_decode_all_events(bytes);
+        assert!(skip_n_take_all(bytes, 0).is_ok());
+    }
+}
+
+#[test]
+fn test_decode_huge_take_and_skip_does_not_panic() {
+    pyo3::prepare_freethreaded_python();
+    let bytes = fake_multiple_events();
+    for n in [usize::MAX, 1 << 62] {
+        assert!(decode_n_events(&bytes, n, None, None).is_err());
+        assert!(decode_n_events(&bytes, 1, Some(n), None).is_err());
+        assert!(skip_n_take_all(&bytes, n).is_err());
+        assert!(source::decode_n(
+            std::io::Cursor::new(&bytes),
+            n,
+            None,
+            None,
+            DecodeOptions::default()
+        )
+        .is_err());
+    }
+}
+
+#[test]
+fn test_decode_n_events_prepend_buffer() {
+    pyo3::prepare_freethreaded_python();
+    let bytes = fake_multiple_events();
+    let (expect_packets, _) = decode_n_events(&bytes, 2, None, None).unwrap();
+
+    // The first event split in two
+    let (packets, last_trailer_idx) =
+        decode_n_events(&bytes[5..], 2, None, Some(bytes[..5].to_vec())).unwrap();
+    assert_eq!(packets, expect_packets);
+    assert_eq!(
+        &bytes[5..][last_trailer_idx..=last_trailer_idx],
+        &[UNIT_FRAME_TRAILER]
+    );
+
+    // The event in the prepend buffer is not complete
+    assert!(decode_n_events(&[], 1, None, Some(vec![UNIT_FRAME_HEADER_1])).is_err());
+    // The prepend buffer contains a complete event
+    assert!(decode_n_events(&bytes[5..], 1, None, Some(fake_event_simple())).is_err());
+}
+
+/// Collects the log records of the threads that enable capturing, other tests running in parallel are not logged.
+struct CaptureLogger;
+
+thread_local! {
+    static CAPTURED_RECORDS: std::cell::RefCell<Option<Vec<(log::Level, String)>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        CAPTURED_RECORDS.with_borrow(Option::is_some)
+    }
+
+    fn log(&self, record: &log::Record) {
+        CAPTURED_RECORDS.with_borrow_mut(|records| {
+            if let Some(records) = records {
+                records.push((record.level(), record.args().to_string()));
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+fn capture_logs(f: impl FnOnce()) -> Vec<(log::Level, String)> {
+    static LOGGER: CaptureLogger = CaptureLogger;
+    _ = log::set_logger(&LOGGER);
+    log::set_max_level(log::LevelFilter::Trace);
+    CAPTURED_RECORDS.set(Some(Vec::new()));
+    f();
+    CAPTURED_RECORDS.take().unwrap()
+}
+
+#[test]
+fn test_decoder_logging() {
+    pyo3::prepare_freethreaded_python();
+    let mut bytes = fake_event_simple();
+    bytes.extend(fake_event_protocol_error());
+
+    // Both hit decoders log the same words and states
+    let records = capture_logs(|| {
+        let (_, trailer_idx) = decode_event(&bytes).unwrap();
+        assert!(decode_event(&bytes[trailer_idx + 1..]).is_err());
+    });
+    assert_eq!(
+        records[..2],
+        [
+            (
+                log::Level::Trace,
+                "0xC0 at index=0 in state UNIT_FRAME_HEADER".to_string()
+            ),
+            (
+                log::Level::Trace,
+                "0x00 at index=1 in state REGION_HEADER0".to_string()
+            ),
+        ]
+    );
+    let (level, message) = records.last().unwrap();
+    assert_eq!(*level, log::Level::Warn);
+    assert!(message.starts_with("ProtocolError: "), "{message}");
+
+    let records = capture_logs(|| {
+        let (_, _, invalid_words) =
+            debug_decode_all_events(&fake_event_protocol_error_fb_in_idle()).unwrap();
+        assert_eq!(invalid_words.len(), 1);
+    });
+    assert_eq!(
+        records,
+        vec![(
+            log::Level::Warn,
+            "Invalid word=0xFB at index=13 in MOSS event, region 0".to_string()
+        )]
+    );
+}
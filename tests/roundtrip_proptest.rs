@@ -0,0 +1,219 @@
+//! Property-based round-trip tests: arbitrary valid packets are encoded into raw MOSS bytes
+//! and every decode entry point must return the same packets, also when the bytes arrive split at any byte boundary.
+use moss_decoder::moss_protocol::test_util::*;
+use moss_decoder::*;
+
+use pretty_assertions::assert_eq;
+use proptest::prelude::*;
+use std::io::Read;
+
+/// Delimiter byte allowed between events, skipped when searching for the next Unit Frame Header.
+const DELIMITER: u8 = 0xFA;
+
+/// Packets and the raw bytes that encode them.
+#[derive(Debug, Clone)]
+struct EncodedRun {
+    packets: Vec<MossPacket>,
+    bytes: Vec<u8>,
+    /// Index of the trailer of each event.
+    trailer_idxs: Vec<usize>,
+}
+
+fn hit_strategy() -> impl Strategy<Value = MossHit> {
+    (0..4u8, 0..320u16, 0..320u16).prop_map(|(region, row, column)| MossHit {
+        region,
+        row,
+        column,
+    })
+}
+
+/// A packet with its hits ordered by region and the number of idle words after each hit.
+fn event_strategy() -> impl Strategy<Value = (MossPacket, Vec<usize>)> {
+    (
+        1..=10u8,
+        prop::collection::vec((hit_strategy(), 0..3usize), 0..40),
+    )
+        .prop_map(|(unit_id, mut hits)| {
+            hits.sort_by_key(|(hit, _)| hit.region);
+            let (hits, idles) = hits.into_iter().unzip();
+            (MossPacket { unit_id, hits }, idles)
+        })
+}
+
+fn run_strategy() -> impl Strategy<Value = EncodedRun> {
+    prop::collection::vec((event_strategy(), 0..3usize), 1..8).prop_map(|events| {
+        let mut run = EncodedRun {
+            packets: Vec::new(),
+            bytes: Vec::new(),
+            trailer_idxs: Vec::new(),
+        };
+        for ((packet, idles), delimiters) in events {
+            run.bytes.extend(std::iter::repeat_n(DELIMITER, delimiters));
+            run.bytes.extend(encode_event(&packet, &idles));
+            run.trailer_idxs.push(run.bytes.len() - 1);
+            run.packets.push(packet);
+        }
+        run
+    })
+}
+
+/// A reader that returns the bytes before `split` in the first read and the rest in the following reads.
+struct SplitReader<'a> {
+    bytes: &'a [u8],
+    split: usize,
+}
+
+impl Read for SplitReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = if self.split > 0 {
+            self.split.min(buf.len())
+        } else {
+            self.bytes.len().min(buf.len())
+        };
+        buf[..n].copy_from_slice(&self.bytes[..n]);
+        self.bytes = &self.bytes[n..];
+        self.split = self.split.saturating_sub(n);
+        Ok(n)
+    }
+}
+
+fn split_reader(bytes: &[u8], split: usize) -> SplitReader<'_> {
+    SplitReader { bytes, split }
+}
+
+fn temp_file(bytes: &[u8]) -> std::path::PathBuf {
+    static FILE_CNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "moss_decoder_roundtrip_{}_{}.raw",
+        std::process::id(),
+        FILE_CNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    ));
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn test_encode_event_matches_fake_event() {
+    let hit = |region, row, column| MossHit {
+        region,
+        row,
+        column,
+    };
+    let packet = MossPacket {
+        unit_id: 1,
+        hits: vec![hit(0, 2, 8), hit(0, 10, 8), hit(1, 301, 433), hit(3, 2, 8)],
+    };
+    assert_eq!(encode_event(&packet, &[1]), fake_event_simple());
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn prop_decode_event(run in run_strategy()) {
+        pyo3::prepare_freethreaded_python();
+        let (packet, trailer_idx) = decode_event(&run.bytes).unwrap();
+        prop_assert_eq!(packet, run.packets[0].clone());
+        prop_assert_eq!(trailer_idx, run.trailer_idxs[0]);
+    }
+
+    #[test]
+    fn prop_decode_all_events(run in run_strategy()) {
+        pyo3::prepare_freethreaded_python();
+        let (packets, last_trailer_idx) = decode_all_events(&run.bytes).unwrap();
+        prop_assert_eq!(packets, run.packets.clone());
+        prop_assert_eq!(last_trailer_idx, run.bytes.len() - 1);
+
+        let (packets, last_trailer_idx, invalid_words) =
+            debug_decode_all_events(&run.bytes).unwrap();
+        prop_assert_eq!(packets, run.packets);
+        prop_assert_eq!(last_trailer_idx, run.bytes.len() - 1);
+        prop_assert!(invalid_words.is_empty());
+    }
+
+    #[test]
+    fn prop_decode_n_events(run in run_strategy(), take in 1..8usize, skip in 0..8usize) {
+        pyo3::prepare_freethreaded_python();
+        let packet_cnt = run.packets.len();
+        prop_assume!(skip + take <= packet_cnt);
+
+        let skip_arg = (skip > 0).then_some(skip);
+        let (packets, last_trailer_idx) =
+            decode_n_events(&run.bytes, take, skip_arg, None).unwrap();
+        prop_assert_eq!(&packets[..], &run.packets[skip..skip + take]);
+        prop_assert_eq!(last_trailer_idx, run.trailer_idxs[skip + take - 1]);
+
+        let (packets, remainder) = skip_n_take_all(&run.bytes, skip).unwrap();
+        prop_assert_eq!(packets.unwrap(), &run.packets[skip..]);
+        prop_assert_eq!(remainder, None);
+    }
+
+    #[test]
+    fn prop_file_variants(run in run_strategy(), skip in 0..8usize) {
+        pyo3::prepare_freethreaded_python();
+        let packet_cnt = run.packets.len();
+        let path = temp_file(&run.bytes);
+
+        prop_assert_eq!(decode_from_file(path.clone()).unwrap(), run.packets.clone());
+
+        let (packets, last_trailer_idx, invalid_words) =
+            debug_decode_all_events_from_file(path.clone()).unwrap();
+        prop_assert_eq!(packets, run.packets.clone());
+        prop_assert_eq!(last_trailer_idx, run.bytes.len() - 1);
+        prop_assert!(invalid_words.is_empty());
+
+        if skip < packet_cnt {
+            let skip_arg = (skip > 0).then_some(skip);
+            let packets =
+                decode_n_events_from_file(path.clone(), packet_cnt - skip, skip_arg, None)
+                    .unwrap();
+            prop_assert_eq!(&packets[..], &run.packets[skip..]);
+
+            let (packets, remainder) =
+                skip_n_take_all_from_file(path.clone(), skip).unwrap();
+            prop_assert_eq!(packets.unwrap(), &run.packets[skip..]);
+            prop_assert_eq!(remainder, None);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+proptest! {
+    // Every split decodes the whole run several times, each with a fresh 10 MiB read buffer
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn prop_split_at_every_byte(run in run_strategy()) {
+        pyo3::prepare_freethreaded_python();
+        let packet_cnt = run.packets.len();
+        for split in 0..=run.bytes.len() {
+            let reader = || split_reader(&run.bytes, split);
+            let packets = source::decode_all(reader(), DecodeOptions::default()).unwrap();
+            prop_assert_eq!(packets, run.packets.clone(), "split at {}", split);
+            let packets = source::decode_n(reader(), packet_cnt, None, None, DecodeOptions::default()).unwrap();
+            prop_assert_eq!(packets, run.packets.clone(), "split at {}", split);
+            let (packets, remainder) = source::skip_n_take_all(reader(), 0, DecodeOptions::default()).unwrap();
+            prop_assert_eq!(packets.unwrap(), run.packets.clone(), "split at {}", split);
+            prop_assert_eq!(remainder, None);
+
+            // Decode the bytes before the split, then the remainder followed by the bytes after the split
+            let (first, remainder) = skip_n_take_all(&run.bytes[..split], 0).unwrap();
+            let first = first.unwrap_or_default();
+            let mut rest = remainder.clone().unwrap_or_default();
+            rest.extend_from_slice(&run.bytes[split..]);
+            let (second, remainder_end) = skip_n_take_all(&rest, 0).unwrap();
+            let packets: Vec<MossPacket> =
+                first.iter().cloned().chain(second.into_iter().flatten()).collect();
+            prop_assert_eq!(packets, run.packets.clone(), "split at {}", split);
+            prop_assert_eq!(remainder_end, None);
+
+            // Same with the remainder as the prepend buffer
+            let take = packet_cnt - first.len();
+            if take > 0 {
+                let (second, _) =
+                    decode_n_events(&run.bytes[split..], take, None, remainder).unwrap();
+                prop_assert_eq!(&second[..], &run.packets[first.len()..], "split at {}", split);
+            }
+        }
+    }
+}